@@ -0,0 +1,126 @@
+//! Compares the baseline `Arena` component storage against the packed `Dense` and `Sparse`
+//! layouts on workloads modeled after `sys_apply_kinematics`. The `scan` workload walks the stored
+//! `Pos` components directly rather than going through a `Query`.
+//!
+//! Run with `cargo bench -p hg-ecs --bench storage`.
+
+#![feature(context_injection)]
+
+const ENTITIES: u32 = 10_000;
+const ITERS: u32 = 200;
+const DT: f32 = 1. / 60.;
+
+macro_rules! measure {
+    ($label:expr, $body:expr) => {{
+        let mut histogram = ::hg_ecs::profile::TimeHistogram::new();
+
+        for _ in 0..ITERS {
+            let start = ::std::time::Instant::now();
+            ::std::hint::black_box($body);
+            histogram.record(start.elapsed());
+        }
+
+        println!("{:<20} {histogram}", $label);
+    }};
+}
+
+macro_rules! storage_suite {
+    ($name:ident, $kind:ident) => {
+        mod $name {
+            use hg_ecs::{bind, component, entity::Component, Entity, Obj, Query, Resource, World};
+
+            use super::{DT, ENTITIES, ITERS};
+
+            #[derive(Debug, Default)]
+            pub struct Pos([f32; 2]);
+
+            #[derive(Debug, Default)]
+            pub struct Vel([f32; 2]);
+
+            #[derive(Debug, Default)]
+            pub struct Frozen;
+
+            component!(
+                Pos { storage: $kind },
+                Vel { storage: $kind },
+                Frozen { storage: $kind },
+            );
+
+            fn spawn(count: u32) -> Vec<Entity> {
+                let group = Entity::new(Entity::root());
+
+                let entities = Entity::spawn_batch(
+                    group,
+                    (0..count).map(|i| (Pos::default(), Vel([i as f32, 1.]))),
+                );
+
+                // Split the population across two archetypes like a real world would.
+                for &entity in entities.iter().step_by(10) {
+                    entity.add(Frozen);
+                }
+
+                Entity::flush(|_world| {});
+                entities
+            }
+
+            fn integrate() {
+                for (mut pos, vel) in Query::<(Obj<Pos>, Obj<Vel>)>::new() {
+                    pos.0[0] += vel.0[0] * DT;
+                    pos.0[1] += vel.0[1] * DT;
+                }
+            }
+
+            fn scan() {
+                let vels = <<Vel as Component>::Arena>::fetch();
+
+                for pos in <<Pos as Component>::Arena>::fetch_mut().iter_mut() {
+                    let Some(vel) = vels.get_by_entity(pos.owner) else {
+                        continue;
+                    };
+
+                    pos.value.0[0] += vel.value.0[0] * DT;
+                    pos.value.0[1] += vel.value.0[1] * DT;
+                }
+            }
+
+            fn lookup(entities: &[Entity]) {
+                for &entity in entities {
+                    let vel = entity.get::<Vel>().0;
+                    let mut pos = entity.get::<Pos>();
+                    pos.0[0] += vel[0] * DT;
+                    pos.0[1] += vel[1] * DT;
+                }
+            }
+
+            fn churn() {
+                let entities = spawn(ENTITIES / 10);
+                Entity::destroy_batch_now([entities[0].parent().unwrap()]);
+                Entity::flush(|_world| {});
+            }
+
+            pub fn run() {
+                let mut world = World::new();
+                bind!(world);
+
+                let entities = spawn(ENTITIES);
+
+                measure!(concat!(stringify!($name), "/query"), integrate());
+                measure!(concat!(stringify!($name), "/scan"), scan());
+                measure!(concat!(stringify!($name), "/lookup"), lookup(&entities));
+                measure!(concat!(stringify!($name), "/churn"), churn());
+            }
+        }
+    };
+}
+
+storage_suite!(arena, Arena);
+storage_suite!(dense, Dense);
+storage_suite!(sparse, Sparse);
+
+fn main() {
+    println!("{ENTITIES} entities, {ITERS} iterations per workload");
+
+    arena::run();
+    dense::run();
+    sparse::run();
+}
@@ -4,7 +4,9 @@ use std::{
     context::pack,
     fmt,
     hash::Hash,
+    mem,
     ops::{Deref, Range},
+    sync::OnceLock,
 };
 
 use hg_utils::{
@@ -12,13 +14,26 @@ use hg_utils::{
     iter::{MergeIter, RemoveIter},
 };
 use index_vec::{define_index_type, IndexVec};
+use linkme::distributed_slice;
 use rustc_hash::FxBuildHasher;
 use thunderdome::Index;
 
-use crate::{bind, entity::Component, world::ImmutableWorld, AccessComp, Entity, Obj, World};
+use crate::{
+    bind,
+    digest::ComponentHash,
+    entity::Component,
+    rollback::ComponentRollback,
+    snapshot::ComponentSerde,
+    stats::{map_bytes, set_bytes, vec_bytes, StorageStats},
+    world::ImmutableWorld,
+    AccessComp, Entity, Obj, World,
+};
 
 // === ComponentId === //
 
+#[distributed_slice]
+pub static COMPONENTS: [fn() -> ComponentId];
+
 #[derive(Copy, Clone)]
 pub struct ComponentId(&'static ComponentInfo);
 
@@ -38,16 +53,19 @@ impl ComponentId {
             const INFO: &'static ComponentInfo = &ComponentInfo {
                 type_id: TypeId::of::<T>,
                 type_name: type_name::<T>,
+                serde: T::SERDE,
+                hash: T::HASH,
+                reflect: T::REFLECT,
+                rollback: T::ROLLBACK,
                 debug_fmt: |world, entity, fmt| {
                     let storage = world.read::<T::Arena>();
 
-                    let idx = storage.entity_map[&entity];
-                    let (_ent, val) = &storage.arena[idx];
-
-                    fmt.field(type_name::<T>(), val);
+                    fmt.field(type_name::<T>(), &storage[entity].value);
                 },
+                storage_stats: |world| world.read::<T::Arena>().stats(),
                 fetch_idx: |world, entity| {
-                    unsafe { &*world.single::<T::Arena>() }.entity_map[&entity]
+                    let storage = unsafe { &*world.single::<T::Arena>() };
+                    storage[entity].handle
                 },
                 remove_no_tracking: |world, entity| {
                     bind!(world, let cx: &mut AccessComp<T>);
@@ -61,6 +79,30 @@ impl ComponentId {
                         entity.remove_from_storage::<T>(pack!(cx));
                     }
                 },
+                hook_order: T::HOOK_ORDER,
+                on_add: if T::ON_ADD.is_some() {
+                    Some(|world, entity| {
+                        // The component may have been removed through `remove_now` since it was
+                        // added.
+                        let storage = unsafe { &*world.single::<T::Arena>() };
+                        let Some(slot) = storage.get_by_entity(entity) else {
+                            return;
+                        };
+
+                        (T::ON_ADD.unwrap())(world, Obj::from_raw(slot.handle));
+                    })
+                } else {
+                    None
+                },
+                on_remove: if T::ON_REMOVE.is_some() {
+                    Some(|world, indices| {
+                        for &idx in indices {
+                            (T::ON_REMOVE.unwrap())(world, Obj::from_raw(idx));
+                        }
+                    })
+                } else {
+                    None
+                },
                 populate_indices: |world, set, list| {
                     bind!(world, let cx: &AccessComp<T>);
 
@@ -75,6 +117,36 @@ impl ComponentId {
 
         Self(Helper::<T>::INFO)
     }
+
+    pub fn all() -> impl Iterator<Item = ComponentId> {
+        COMPONENTS.iter().map(|v| v())
+    }
+
+    /// Looks up a component registered for serialization by its stable name.
+    pub fn lookup_serde(name: &str) -> Option<ComponentId> {
+        static MAP: OnceLock<FxHashMap<&'static str, ComponentId>> = OnceLock::new();
+
+        MAP.get_or_init(|| {
+            let mut map = FxHashMap::default();
+
+            for comp in Self::all() {
+                let Some(serde) = comp.serde else {
+                    continue;
+                };
+
+                if let Some(other) = map.insert(serde.name, comp) {
+                    panic!(
+                        "{other:?} and {comp:?} are both serialized as `{}`",
+                        serde.name,
+                    );
+                }
+            }
+
+            map
+        })
+        .get(name)
+        .copied()
+    }
 }
 
 impl Deref for ComponentId {
@@ -115,11 +187,19 @@ impl PartialOrd for ComponentId {
 pub struct ComponentInfo {
     pub type_id: fn() -> TypeId,
     pub type_name: fn() -> &'static str,
+    pub serde: Option<&'static ComponentSerde>,
+    pub hash: Option<&'static ComponentHash>,
+    pub reflect: Option<&'static ComponentSerde>,
+    pub rollback: Option<&'static ComponentRollback>,
     pub(crate) debug_fmt: fn(ImmutableWorld, Entity, &mut fmt::DebugStruct<'_, '_>),
+    pub(crate) storage_stats: fn(ImmutableWorld) -> StorageStats,
     pub(crate) fetch_idx: unsafe fn(&World, Entity) -> Index,
     pub(crate) remove_for_deferred: fn(&mut World, &FxHashSet<Entity>),
     pub(crate) remove_no_tracking: fn(&mut World, Entity),
     pub(crate) populate_indices: fn(&mut World, &FxHashSet<Entity>, &mut Vec<Index>),
+    pub(crate) hook_order: i32,
+    pub(crate) on_add: Option<fn(&mut World, Entity)>,
+    pub(crate) on_remove: Option<fn(&mut World, &[Index])>,
 }
 
 // === ArchetypeId === //
@@ -325,6 +405,58 @@ impl ArchetypeStore {
         arches
     }
 
+    /// Finds the archetypes containing every component in `with` and none of the components in
+    /// `without`. Exclusion is checked once per candidate archetype.
+    pub fn archetypes_matching(
+        &self,
+        with: impl IntoIterator<Item = ComponentId>,
+        without: impl IntoIterator<Item = ComponentId>,
+    ) -> Vec<ArchetypeId> {
+        let mut arches = self.archetypes_with_set(with);
+        let without = without.into_iter().collect::<Vec<_>>();
+
+        if !without.is_empty() {
+            arches.retain(|&arch| {
+                let comps = self.components_set(arch);
+                without.iter().all(|comp| !comps.contains(comp))
+            });
+        }
+
+        arches
+    }
+
+    pub fn archetype_count(&self) -> usize {
+        self.arena.len()
+    }
+
+    /// Iterates over every archetype which has been created, in creation order.
+    pub fn ids(&self) -> impl Iterator<Item = ArchetypeId> + '_ {
+        self.arena.indices()
+    }
+
+    /// Estimates the heap memory used by the archetype graph.
+    pub fn estimated_bytes(&self) -> usize {
+        let per_arch = self
+            .arena
+            .iter()
+            .map(|data| {
+                mem::size_of::<FxHashSet<ComponentId>>()
+                    + set_bytes(&data.comp_map)
+                    + map_bytes(&data.pos)
+                    + map_bytes(&data.neg)
+            })
+            .sum::<usize>();
+
+        let comp_arches = self.comp_arches.values().map(vec_bytes).sum::<usize>();
+
+        self.arena.capacity() * mem::size_of::<ArchetypeData>()
+            + per_arch
+            + vec_bytes(&self.comp_buf)
+            + map_bytes(&self.map)
+            + map_bytes(&self.comp_arches)
+            + comp_arches
+    }
+
     pub fn components(&self, id: ArchetypeId) -> &[ComponentId] {
         let comps = self.arena[id].comps.clone();
         &self.comp_buf[comps]
@@ -0,0 +1,353 @@
+use std::{context::pack, fmt, mem};
+
+use thiserror::Error;
+
+use crate::{
+    archetype::ComponentId,
+    bind,
+    entity::{Component, EntityStore},
+    resource, AccessComp, Entity, Resource, World, WORLD,
+};
+
+// === CommandEntity === //
+
+/// The target of a command: either an existing entity or one spawned by an earlier command in
+/// the same buffer.
+///
+/// Spawned entities are identified by the batch of commands they were recorded in, which ends
+/// with the next [`Entity::flush`], and their position among that batch's spawns. Using such a
+/// handle once its batch has been applied fails with [`CommandError::StaleSpawn`].
+#[derive(Debug, Copy, Clone, Hash, Eq, PartialEq)]
+pub enum CommandEntity {
+    Existing(Entity),
+    Spawned { batch: u32, index: u32 },
+}
+
+impl From<Entity> for CommandEntity {
+    fn from(entity: Entity) -> Self {
+        Self::Existing(entity)
+    }
+}
+
+// === CommandError === //
+
+#[derive(Debug, Clone, Error)]
+pub enum CommandError {
+    #[error("cannot {op} entity 0x{:x} because it is dead or condemned", .entity.raw().to_bits())]
+    DeadEntity { op: &'static str, entity: Entity },
+    #[error(
+        "cannot remove `{name}` from entity 0x{:x} because it does not have one",
+        .entity.raw().to_bits(),
+    )]
+    MissingComponent { entity: Entity, name: &'static str },
+    #[error(
+        "cannot make entity 0x{:x} a child of its descendant 0x{:x}",
+        .entity.raw().to_bits(),
+        .parent.raw().to_bits(),
+    )]
+    CyclicParent { entity: Entity, parent: Entity },
+    #[error("cannot {op} an entity spawned by a batch of commands which has already been applied")]
+    StaleSpawn { op: &'static str },
+}
+
+// === Commands === //
+
+/// Records structural changes to be applied by the next [`Entity::flush`], making it possible to
+/// spawn, add, remove, reparent and destroy while iterating over a [`Query`](crate::Query).
+///
+/// Commands are applied in the order in which they were recorded. A command which fails is
+/// skipped and its error can be retrieved through [`Commands::take_errors`]. Removals and
+/// destructions go through `remove` and `destroy` and are therefore processed by the same
+/// `flush` which applied them, running `on_remove` hooks along the way.
+#[derive(Default)]
+pub struct Commands {
+    commands: Vec<Command>,
+    batch: u32,
+    spawned: u32,
+    errors: Vec<CommandError>,
+}
+
+/// The entities spawned thus far by the batch of commands being applied.
+struct SpawnedEntities {
+    batch: u32,
+    entities: Vec<Entity>,
+}
+
+enum Command {
+    Spawn {
+        parent: CommandEntity,
+    },
+    Add {
+        target: CommandEntity,
+        apply: Box<dyn FnOnce(&mut World, Entity)>,
+    },
+    Remove {
+        target: CommandEntity,
+        comp: ComponentId,
+        apply: fn(&mut World, Entity),
+    },
+    SetParent {
+        target: CommandEntity,
+        parent: Option<CommandEntity>,
+    },
+    Destroy {
+        target: CommandEntity,
+    },
+}
+
+resource!(Commands);
+
+impl fmt::Debug for Commands {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Commands")
+            .field("len", &self.commands.len())
+            .field("errors", &self.errors)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Commands {
+    pub fn len(&self) -> usize {
+        self.commands.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.commands.is_empty()
+    }
+
+    /// Records the creation of a new entity under `parent`. The returned handle can be used as
+    /// the target of subsequent commands in this buffer.
+    pub fn spawn(&mut self, parent: impl Into<CommandEntity>) -> CommandEntity {
+        let index = self.spawned;
+        self.spawned += 1;
+
+        self.commands.push(Command::Spawn {
+            parent: parent.into(),
+        });
+
+        CommandEntity::Spawned {
+            batch: self.batch,
+            index,
+        }
+    }
+
+    pub fn add<T: Component>(&mut self, target: impl Into<CommandEntity>, value: T) {
+        self.commands.push(Command::Add {
+            target: target.into(),
+            apply: Box::new(move |world, entity| {
+                bind!(world, let cx: &mut AccessComp<T>);
+                entity.add(value, pack!(cx));
+            }),
+        });
+    }
+
+    pub fn remove<T: Component>(&mut self, target: impl Into<CommandEntity>) {
+        self.commands.push(Command::Remove {
+            target: target.into(),
+            comp: ComponentId::of::<T>(),
+            apply: |world, entity| {
+                bind!(world);
+                entity.remove::<T>();
+            },
+        });
+    }
+
+    pub fn set_parent(&mut self, target: impl Into<CommandEntity>, parent: Option<CommandEntity>) {
+        self.commands.push(Command::SetParent {
+            target: target.into(),
+            parent,
+        });
+    }
+
+    pub fn destroy(&mut self, target: impl Into<CommandEntity>) {
+        self.commands.push(Command::Destroy {
+            target: target.into(),
+        });
+    }
+
+    /// Returns the errors produced by commands applied since the last call to this method.
+    pub fn take_errors(&mut self) -> Vec<CommandError> {
+        mem::take(&mut self.errors)
+    }
+
+    /// Applies and clears every recorded command. This is called by `Entity::flush`.
+    pub(crate) fn apply_recorded() {
+        let me = Self::fetch_mut();
+
+        if me.commands.is_empty() {
+            return;
+        }
+
+        let commands = mem::take(&mut me.commands);
+        let mut spawned = SpawnedEntities {
+            batch: me.batch,
+            entities: Vec::new(),
+        };
+
+        // Commands recorded from here on belong to the next batch.
+        me.batch = me.batch.wrapping_add(1);
+        me.spawned = 0;
+
+        let mut errors = Vec::new();
+
+        for command in commands {
+            if let Err(err) = Self::apply(command, &mut spawned) {
+                tracing::warn!("failed to apply command: {err}");
+                errors.push(err);
+            }
+        }
+
+        Self::fetch_mut().errors.extend(errors);
+    }
+
+    fn apply(command: Command, spawned: &mut SpawnedEntities) -> Result<(), CommandError> {
+        match command {
+            Command::Spawn { parent } => {
+                // Failed spawns still take up their slot so that later handles resolve properly.
+                match Self::resolve(parent, spawned, "spawn a child under") {
+                    Ok(parent) => spawned.entities.push(Entity::new(parent)),
+                    Err(err) => {
+                        spawned.entities.push(Entity::DANGLING);
+                        return Err(err);
+                    }
+                }
+            }
+            Command::Add { target, apply } => {
+                let target = Self::resolve(target, spawned, "add a component to")?;
+                apply(&mut WORLD, target);
+            }
+            Command::Remove {
+                target,
+                comp,
+                apply,
+            } => {
+                let target = Self::resolve(target, spawned, "remove a component from")?;
+
+                if !EntityStore::fetch().components(target).contains(&comp) {
+                    return Err(CommandError::MissingComponent {
+                        entity: target,
+                        name: (comp.type_name)(),
+                    });
+                }
+
+                apply(&mut WORLD, target);
+            }
+            Command::SetParent { target, parent } => {
+                let target = Self::resolve(target, spawned, "reparent")?;
+                let parent = match parent {
+                    Some(parent) => {
+                        Some(Self::resolve(parent, spawned, "reparent an entity under")?)
+                    }
+                    None => None,
+                };
+
+                if let Some(parent) = parent {
+                    if parent == target || Self::is_ancestor(target, parent) {
+                        return Err(CommandError::CyclicParent {
+                            entity: target,
+                            parent,
+                        });
+                    }
+                }
+
+                target.set_parent(parent);
+            }
+            Command::Destroy { target } => {
+                Self::resolve(target, spawned, "destroy")?.destroy();
+            }
+        }
+
+        Ok(())
+    }
+
+    fn resolve(
+        target: CommandEntity,
+        spawned: &SpawnedEntities,
+        op: &'static str,
+    ) -> Result<Entity, CommandError> {
+        let entity = match target {
+            CommandEntity::Existing(entity) => entity,
+            CommandEntity::Spawned { batch, index } => match spawned.entities.get(index as usize) {
+                Some(&entity) if batch == spawned.batch => entity,
+                _ => return Err(CommandError::StaleSpawn { op }),
+            },
+        };
+
+        if !entity.is_alive() || entity.is_condemned() {
+            return Err(CommandError::DeadEntity { op, entity });
+        }
+
+        Ok(entity)
+    }
+
+    fn is_ancestor(ancestor: Entity, entity: Entity) -> bool {
+        entity.ancestors().any(|other| other == ancestor)
+    }
+}
+
+// === Tests === //
+
+#[cfg(test)]
+mod tests {
+    use crate::{bind, component, Entity, Resource as _, World};
+
+    use super::{CommandError, Commands};
+
+    #[derive(Debug)]
+    pub struct Marker(u32);
+
+    component!(Marker);
+
+    #[test]
+    fn applies_in_order_and_reports_errors() {
+        let mut world = World::new();
+        bind!(world);
+
+        let parent = Entity::new(Entity::root());
+        let doomed = Entity::new(Entity::root());
+
+        let commands = Commands::fetch_mut();
+        let child = commands.spawn(parent);
+        commands.add(child, Marker(1));
+        commands.destroy(doomed);
+        commands.add(doomed, Marker(2));
+        commands.remove::<Marker>(parent);
+
+        Entity::flush(|_world| {});
+
+        let child = parent.children().into_iter().next().unwrap();
+        assert_eq!(child.get::<Marker>().0, 1);
+        assert!(!doomed.is_alive());
+
+        let errors = Commands::fetch_mut().take_errors();
+        assert!(matches!(
+            errors[..],
+            [
+                CommandError::DeadEntity { entity, .. },
+                CommandError::MissingComponent { .. },
+            ] if entity == doomed,
+        ));
+    }
+
+    #[test]
+    fn rejects_stale_spawn_handles() {
+        let mut world = World::new();
+        bind!(world);
+
+        let commands = Commands::fetch_mut();
+        let child = commands.spawn(Entity::root());
+        commands.add(child, Marker(1));
+        Entity::flush(|_world| {});
+
+        // The handle refers to a batch which has already been applied.
+        let commands = Commands::fetch_mut();
+        commands.add(child, Marker(2));
+        Entity::flush(|_world| {});
+
+        let errors = Commands::fetch_mut().take_errors();
+        assert!(matches!(errors[..], [CommandError::StaleSpawn { .. }]));
+
+        let child = Entity::root().children().into_iter().next().unwrap();
+        assert_eq!(child.get::<Marker>().0, 1);
+    }
+}
@@ -0,0 +1,422 @@
+use std::{
+    cmp::Ordering,
+    context::{unpack, Bundle},
+    fmt, io,
+    sync::OnceLock,
+};
+
+use hg_utils::hash::FxHashMap;
+use serde::Serialize;
+use thiserror::Error;
+
+use crate::{
+    archetype::ComponentId,
+    bind,
+    entity::{Component, EntityStore},
+    snapshot::{bind_capture_state, pre_order},
+    world::ImmutableWorld,
+    Entity, World, WORLD,
+};
+
+// === ComponentHash === //
+
+pub struct ComponentHash {
+    pub name: &'static str,
+    pub hash: fn(ImmutableWorld, Entity) -> serde_json::Result<u64>,
+}
+
+impl fmt::Debug for ComponentHash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ComponentHash")
+            .field("name", &self.name)
+            .finish_non_exhaustive()
+    }
+}
+
+impl ComponentHash {
+    pub const fn of<T: Component + Serialize>() -> &'static Self {
+        struct Helper<T>(T);
+
+        impl<T: Component + Serialize> Helper<T> {
+            const HASH: &'static ComponentHash = &ComponentHash {
+                name: T::HASH_NAME,
+                hash: |world, entity| {
+                    let storage = world.read::<T::Arena>();
+
+                    // Components are hashed through their serialized form so that references to
+                    // other entities are hashed by their position in the tree rather than by
+                    // their slot in the arena.
+                    let mut hasher = StableHasher::new();
+                    serde_json::to_writer(&mut hasher, &storage[entity].value)?;
+
+                    Ok(hasher.finish())
+                },
+            };
+        }
+
+        Helper::<T>::HASH
+    }
+}
+
+// === StableHasher === //
+
+/// A 64-bit FNV-1a hasher. Unlike the hashers in `std`, its output only depends on the bytes
+/// written to it so it can be compared across processes and platforms.
+#[derive(Debug, Copy, Clone)]
+pub struct StableHasher(u64);
+
+impl Default for StableHasher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl StableHasher {
+    const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0000_0100_0000_01b3;
+
+    pub const fn new() -> Self {
+        Self(Self::OFFSET_BASIS)
+    }
+
+    pub fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 ^= byte as u64;
+            self.0 = self.0.wrapping_mul(Self::PRIME);
+        }
+    }
+
+    pub fn write_u64(&mut self, value: u64) {
+        self.write(&value.to_le_bytes());
+    }
+
+    pub fn write_str(&mut self, value: &str) {
+        self.write_u64(value.len() as u64);
+        self.write(value.as_bytes());
+    }
+
+    pub fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+impl io::Write for StableHasher {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        StableHasher::write(self, buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// === WorldDigest === //
+
+#[derive(Debug, Error)]
+#[error("failed to hash component `{name}`")]
+pub struct DigestError {
+    pub name: &'static str,
+    #[source]
+    pub error: serde_json::Error,
+}
+
+/// Per-component hashes of an entity subtree, used to detect desyncs between worlds which are
+/// expected to evolve identically.
+///
+/// Nodes are stored in pre-order, following the current order of each parent's children. Since
+/// removing a child moves its last sibling into its place, two worlds only produce the same digest
+/// if they added and removed children in the same order. The components of each node are sorted
+/// by the name given to the `hash` option of `component!`, and only components registered through
+/// that option are hashed. Neither the arena slots the entities occupy, the type names of their
+/// components, nor the iteration order of any hash map affect the digest.
+#[derive(Debug, Clone, Default)]
+pub struct WorldDigest {
+    pub nodes: Vec<DigestNode>,
+}
+
+#[derive(Debug, Clone)]
+pub struct DigestNode {
+    /// The entity this node was captured from. This is only meaningful in the world it came from
+    /// and does not contribute to the digest's hash.
+    pub entity: Entity,
+
+    /// The index of the parent node or `None` for the root of the digest.
+    pub parent: Option<u32>,
+
+    /// The hash of every hashable component of this node, sorted by hash name.
+    pub components: Vec<(ComponentId, u64)>,
+}
+
+impl WorldDigest {
+    pub fn capture(root: Entity, cx: Bundle<&mut WORLD>) -> Result<Self, DigestError> {
+        let world = unpack!(cx => &mut WORLD).reborrow();
+        let world = world.immutable();
+        let store = world.read::<EntityStore>();
+
+        check_hash_names();
+
+        let (order, local_ids) = pre_order(store, root);
+        let _guard = bind_capture_state(local_ids);
+
+        let nodes = world.bind_tls(|| {
+            order
+                .iter()
+                .map(|&(entity, parent)| {
+                    let mut components = Vec::new();
+
+                    for comp in store.components(entity) {
+                        let Some(hash) = comp.hash else {
+                            continue;
+                        };
+
+                        let value = (hash.hash)(world, entity).map_err(|error| DigestError {
+                            name: hash.name,
+                            error,
+                        })?;
+
+                        components.push((*comp, value));
+                    }
+
+                    components.sort_by_key(|&(comp, _)| hash_name(comp));
+
+                    Ok(DigestNode {
+                        entity,
+                        parent,
+                        components,
+                    })
+                })
+                .collect::<Result<Vec<_>, DigestError>>()
+        })?;
+
+        Ok(Self { nodes })
+    }
+
+    /// Hashes the entire digest into a single value which is cheap to exchange between peers.
+    pub fn hash(&self) -> u64 {
+        let mut hasher = StableHasher::new();
+
+        for node in &self.nodes {
+            hasher.write_u64(node.parent.map_or(u64::MAX, u64::from));
+            hasher.write_u64(node.components.len() as u64);
+
+            for &(comp, hash) in &node.components {
+                hasher.write_str(hash_name(comp));
+                hasher.write_u64(hash);
+            }
+        }
+
+        hasher.finish()
+    }
+
+    /// Finds the first node, in pre-order, at which the two digests disagree.
+    pub fn diff(&self, other: &Self) -> Option<DigestMismatch> {
+        for index in 0..self.nodes.len().max(other.nodes.len()) {
+            let left = self.nodes.get(index);
+            let right = other.nodes.get(index);
+
+            let kind = match (left, right) {
+                (Some(left), Some(right)) if left.parent == right.parent => {
+                    match Self::diff_components(&left.components, &right.components) {
+                        Some(kind) => kind,
+                        None => continue,
+                    }
+                }
+                _ => MismatchKind::Entity,
+            };
+
+            return Some(DigestMismatch {
+                index,
+                left: left.map(|node| node.entity),
+                right: right.map(|node| node.entity),
+                kind,
+            });
+        }
+
+        None
+    }
+
+    fn diff_components(
+        left: &[(ComponentId, u64)],
+        right: &[(ComponentId, u64)],
+    ) -> Option<MismatchKind> {
+        // Both lists are sorted by hash name so we can walk them in lockstep.
+        let mut left = left.iter().peekable();
+        let mut right = right.iter().peekable();
+
+        loop {
+            let kind = match (left.peek(), right.peek()) {
+                (None, None) => return None,
+                (Some(&&(comp, _)), None) | (None, Some(&&(comp, _))) => {
+                    MismatchKind::Presence(hash_name(comp))
+                }
+                (Some(&&(left_comp, left_hash)), Some(&&(right_comp, right_hash))) => {
+                    match hash_name(left_comp).cmp(hash_name(right_comp)) {
+                        Ordering::Less => MismatchKind::Presence(hash_name(left_comp)),
+                        Ordering::Greater => MismatchKind::Presence(hash_name(right_comp)),
+                        Ordering::Equal if left_hash != right_hash => {
+                            MismatchKind::Value(hash_name(left_comp))
+                        }
+                        Ordering::Equal => {
+                            left.next();
+                            right.next();
+                            continue;
+                        }
+                    }
+                }
+            };
+
+            return Some(kind);
+        }
+    }
+
+    /// Captures both worlds from their roots and finds the first point at which they disagree.
+    pub fn diff_worlds(
+        left: &mut World,
+        right: &mut World,
+    ) -> Result<Option<DigestMismatch>, DigestError> {
+        let left = {
+            bind!(left);
+            Self::capture(Entity::root())?
+        };
+
+        let right = {
+            bind!(right);
+            Self::capture(Entity::root())?
+        };
+
+        Ok(left.diff(&right))
+    }
+}
+
+/// The name under which a component registered through the `hash` option is hashed.
+fn hash_name(comp: ComponentId) -> &'static str {
+    comp.hash.expect("component is not hashed").name
+}
+
+/// Ensures that no two hashed components share a name since they would be indistinguishable in a
+/// digest.
+fn check_hash_names() {
+    static CHECKED: OnceLock<()> = OnceLock::new();
+
+    CHECKED.get_or_init(|| {
+        let mut names = FxHashMap::default();
+
+        for comp in ComponentId::all() {
+            let Some(hash) = comp.hash else {
+                continue;
+            };
+
+            if let Some(other) = names.insert(hash.name, comp) {
+                panic!("{other:?} and {comp:?} are both hashed as `{}`", hash.name);
+            }
+        }
+    });
+}
+
+#[derive(Debug, Clone)]
+pub struct DigestMismatch {
+    /// The index of the node, in pre-order, at which the digests first disagree.
+    pub index: usize,
+
+    /// The entity at that index in the first digest, if it has one.
+    pub left: Option<Entity>,
+
+    /// The entity at that index in the second digest, if it has one.
+    pub right: Option<Entity>,
+
+    pub kind: MismatchKind,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum MismatchKind {
+    /// The node is missing from one of the digests or has a different parent in each.
+    Entity,
+
+    /// The component with the given hash name is only present on one of the two entities.
+    Presence(&'static str),
+
+    /// The component with the given hash name is present on both entities but its value differs.
+    Value(&'static str),
+}
+
+impl fmt::Display for DigestMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let fmt_entity = |entity: Option<Entity>| match entity {
+            Some(entity) => format!("0x{:x}", entity.raw().to_bits()),
+            None => "<none>".to_string(),
+        };
+
+        write!(
+            f,
+            "node {} ({} vs {}): ",
+            self.index,
+            fmt_entity(self.left),
+            fmt_entity(self.right),
+        )?;
+
+        match self.kind {
+            MismatchKind::Entity => f.write_str("tree structure differs"),
+            MismatchKind::Presence(name) => write!(f, "component `{name}` is only on one side"),
+            MismatchKind::Value(name) => write!(f, "component `{name}` differs"),
+        }
+    }
+}
+
+// === Tests === //
+
+#[cfg(test)]
+mod tests {
+    use serde::Serialize;
+
+    use crate::{bind, component, Entity, Obj, World};
+
+    use super::{MismatchKind, WorldDigest};
+
+    #[derive(Debug, Serialize)]
+    pub struct Health(u32);
+
+    #[derive(Debug, Serialize)]
+    pub struct Target(Obj<Health>);
+
+    component!(Health { hash: "health" }, Target { hash: "target" });
+
+    fn spawn_pair(world: &mut World, padding: usize) -> Obj<Health> {
+        bind!(world);
+
+        // Occupy and free some arena slots so that the pair ends up with different IDs.
+        for _ in 0..padding {
+            Entity::new(Entity::root()).destroy_now();
+        }
+
+        let parent = Entity::new(Entity::root());
+        let health = Entity::new(parent).add(Health(42));
+        parent.add(Target(health));
+        health
+    }
+
+    #[test]
+    fn digest_ignores_slots() {
+        let mut left = World::new();
+        let mut right = World::new();
+
+        spawn_pair(&mut left, 0);
+        let mut health = spawn_pair(&mut right, 3);
+
+        assert!(WorldDigest::diff_worlds(&mut left, &mut right)
+            .unwrap()
+            .is_none());
+
+        {
+            bind!(&mut right);
+            health.0 = 7;
+        }
+
+        let mismatch = WorldDigest::diff_worlds(&mut left, &mut right)
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(mismatch.index, 2);
+        assert_eq!(mismatch.kind, MismatchKind::Value("health"));
+    }
+}
@@ -1,21 +1,35 @@
 use std::{
     any::type_name,
-    context::{pack, Bundle, BundleItemSet, DerefCx, DerefCxMut},
-    fmt, iter,
+    cmp::Ordering,
+    context::{pack, unpack, Bundle, BundleItemSet, DerefCx, DerefCxMut},
+    fmt,
+    hash::{self, Hash},
+    iter,
     marker::PhantomData,
     mem,
-    ops::DerefMut,
+    ops::{self, DerefMut},
+    panic::Location,
     rc::Rc,
     slice,
+    time::Instant,
 };
 
 use derive_where::derive_where;
-use hg_utils::hash::{hash_map::Entry, FxHashMap, FxHashSet};
+use hg_utils::hash::{FxHashMap, FxHashSet};
 use thunderdome::{Arena, Index};
 
 use crate::{
     archetype::{ArchetypeId, ArchetypeStore, ComponentId},
+    bind,
+    command::Commands,
+    digest::ComponentHash,
+    event::EventBus,
+    name::{name_of, Name},
+    profile::Profiler,
     resource,
+    rollback::ComponentRollback,
+    snapshot::ComponentSerde,
+    stats::{arena_bytes, map_bytes, set_bytes, vec_bytes, FlushChurn, StorageStats},
     world::{can_format_entity, can_format_obj, ImmutableWorld, WorldFmt},
     AccessRes, AccessResRef, Resource, World, WORLD,
 };
@@ -39,9 +53,10 @@ pub struct EntityStore {
     query_state: Rc<EntityQueryState>,
 
     /// Maps entities that have been reshaped to their original archetype. Destroyed entities are
-    /// included in the `dead_entities` map instead.
+    /// included in the `dead_entities` map instead. Entities which were reparented or which lost a
+    /// child are included as well, even if their archetype did not change.
     ///
-    /// This is only used to patch up `archetype_members` during a `flush`.
+    /// This is only used to patch up `archetype_members` and `hierarchy_cache` during a `flush`.
     reshaped_entities: FxHashMap<Entity, ArchetypeId>,
 
     /// The set of original archetypes and position therein of entities destroyed through
@@ -50,11 +65,24 @@ pub struct EntityStore {
     /// This is only used to patch up `archetype_members` during a `flush`.
     dead_entities: FxHashSet<(ArchetypeId, u32)>,
 
+    /// Cached results of `Entity::descendants_with`, keyed by the root of the subtree and then by
+    /// its sorted set of required components.
+    ///
+    /// Entries are invalidated during `flush` if an entity in their subtree was reshaped,
+    /// reparented, or destroyed since the previous one.
+    hierarchy_cache: FxHashMap<Entity, FxHashMap<Box<[ComponentId]>, EntitySubtree>>,
+
     // TODO: Document
     target_queue_state: EntityQueueState,
 
     // TODO: Document
     view_queue_state: Rc<EntityQueueState>,
+
+    /// The churn of the last `flush`.
+    last_flush_churn: FlushChurn,
+
+    /// The churn of every `flush` thus far.
+    total_flush_churn: FlushChurn,
 }
 
 #[derive(Debug)]
@@ -135,8 +163,11 @@ impl Default for EntityStore {
             query_state: Rc::default(),
             reshaped_entities: FxHashMap::default(),
             dead_entities: FxHashSet::default(),
+            hierarchy_cache: FxHashMap::default(),
             target_queue_state: EntityQueueState::default(),
             view_queue_state: Rc::default(),
+            last_flush_churn: FlushChurn::default(),
+            total_flush_churn: FlushChurn::default(),
         };
 
         store.root = Entity::new_root(&mut store);
@@ -146,6 +177,152 @@ impl Default for EntityStore {
 
 resource!(EntityStore);
 
+impl EntityStore {
+    pub fn root(&self) -> Entity {
+        self.root
+    }
+
+    pub fn is_alive(&self, entity: Entity) -> bool {
+        self.entities.contains(entity.0)
+    }
+
+    pub fn entity_count(&self) -> usize {
+        self.entities.len()
+    }
+
+    pub fn archetype_count(&self) -> usize {
+        self.archetypes.archetype_count()
+    }
+
+    /// Counts the archetypes with at least one member as of the last `flush`.
+    pub fn populated_archetype_count(&self) -> usize {
+        self.query_state
+            .index_members
+            .values()
+            .filter(|members| !members.is_empty())
+            .count()
+    }
+
+    pub fn archetypes(&self) -> &ArchetypeStore {
+        &self.archetypes
+    }
+
+    /// Counts the members of an archetype as of the last `flush`.
+    pub fn archetype_member_count(&self, arch: ArchetypeId) -> usize {
+        self.query_state
+            .index_members
+            .get(&arch)
+            .map_or(0, |members| members.len())
+    }
+
+    pub fn cached_subtree_count(&self) -> usize {
+        self.hierarchy_cache.values().map(|cache| cache.len()).sum()
+    }
+
+    pub fn last_flush_churn(&self) -> FlushChurn {
+        self.last_flush_churn
+    }
+
+    pub fn total_flush_churn(&self) -> FlushChurn {
+        self.total_flush_churn
+    }
+
+    /// Estimates the heap memory used by the store. Cached subtrees are only counted by their
+    /// table entries and children lists shared with a live `EntityChildren` are counted anyway.
+    pub fn estimated_bytes(&self) -> usize {
+        let children = self
+            .entities
+            .iter()
+            .map(|(_, info)| vec_bytes(&*info.children.vec))
+            .sum::<usize>();
+
+        let index_members = self
+            .query_state
+            .index_members
+            .values()
+            .map(vec_bytes)
+            .sum::<usize>();
+
+        let comp_members = self
+            .query_state
+            .comp_members
+            .values()
+            .map(vec_bytes)
+            .sum::<usize>();
+
+        let hierarchy_cache = self.hierarchy_cache.values().map(map_bytes).sum::<usize>();
+
+        arena_bytes(&self.entities)
+            + children
+            + self.archetypes.estimated_bytes()
+            + map_bytes(&self.query_state.index_members)
+            + index_members
+            + map_bytes(&self.query_state.comp_members)
+            + comp_members
+            + map_bytes(&self.reshaped_entities)
+            + set_bytes(&self.dead_entities)
+            + map_bytes(&self.hierarchy_cache)
+            + hierarchy_cache
+    }
+
+    pub fn parent(&self, entity: Entity) -> Option<Entity> {
+        self.entities[entity.0].parent
+    }
+
+    pub fn children(&self, entity: Entity) -> &EntityChildren {
+        &self.entities[entity.0].children
+    }
+
+    /// Lists the components the entity currently owns, accounting for `add` and `remove_now` but
+    /// not for queued removals.
+    pub fn components(&self, entity: Entity) -> &[ComponentId] {
+        self.archetypes
+            .components(self.entities[entity.0].archetype)
+    }
+
+    /// Records the death of an entity which has already been removed from `entities` so that the
+    /// next `flush` can remove it from its pre-`flush` archetype.
+    fn mark_dead(&mut self, entity: Entity, info: &EntityInfo) {
+        let old_archetype = self
+            .reshaped_entities
+            .remove(&entity)
+            .unwrap_or(info.archetype);
+
+        if old_archetype != ArchetypeId::EMPTY {
+            self.dead_entities
+                .insert((old_archetype, info.index_in_archetype));
+        }
+
+        // Hierarchy queries rooted at a dead entity can never be requested again.
+        self.hierarchy_cache.remove(&entity);
+    }
+
+    /// Drops the cached hierarchy queries of every reshaped entity and all its ancestors. Since
+    /// reparenting or destroying an entity marks its old parent as reshaped, this accounts for
+    /// every change to the hierarchy since the last `flush`.
+    fn invalidate_hierarchy_cache(&mut self) {
+        if self.hierarchy_cache.is_empty() {
+            return;
+        }
+
+        let mut visited = FxHashSet::default();
+
+        for &entity in self.reshaped_entities.keys() {
+            let mut iter = Some(entity);
+
+            while let Some(curr) = iter {
+                // The ancestors of a visited entity have been visited as well.
+                if !visited.insert(curr) {
+                    break;
+                }
+
+                self.hierarchy_cache.remove(&curr);
+                iter = self.entities[curr.0].parent;
+            }
+        }
+    }
+}
+
 // === Entity === //
 
 #[derive(Copy, Clone, Hash, Eq, PartialEq, Ord, PartialOrd)]
@@ -154,31 +331,45 @@ pub struct Entity(Index);
 impl fmt::Debug for Entity {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         ImmutableWorld::try_use_tls(|world| {
-            if let Some(world) = world.filter(|_| can_format_entity(*self)) {
-                let store = world.read::<EntityStore>();
+            let Some(world) = world else {
+                return f
+                    .debug_struct("Entity")
+                    .field("id", &format_args!("0x{:x}", self.0.to_bits()))
+                    .finish();
+            };
+
+            let expand = can_format_entity(world, *self);
+            let store = world.read::<EntityStore>();
 
-                let mut f = f.debug_struct("Entity");
+            let mut f = f.debug_struct("Entity");
 
-                f.field("id", &format_args!("0x{:x}", self.0.to_bits()));
+            f.field("id", &format_args!("0x{:x}", self.0.to_bits()));
 
-                if let Some(entity) = store.entities.get(self.0) {
-                    let comps = store.archetypes.components(entity.archetype);
+            // Names are shown even for entities which have already been formatted so that
+            // references to them remain recognizable.
+            if let Some(name) = name_of(world.read::<<Name as Component>::Arena>(), *self) {
+                f.field("name", &name);
+            }
+
+            if !expand {
+                return f.finish();
+            }
+
+            if let Some(entity) = store.entities.get(self.0) {
+                let comps = store.archetypes.components(entity.archetype);
 
-                    for comp in comps {
+                for &comp in comps {
+                    if comp != ComponentId::of::<Name>() {
                         (comp.debug_fmt)(world, *self, &mut f);
                     }
-
-                    f.field("children", &entity.children.vec);
-                } else {
-                    f.field("is_alive", &false);
                 }
 
-                f.finish()
+                f.field("children", &entity.children.vec);
             } else {
-                f.debug_struct("Entity")
-                    .field("id", &format_args!("0x{:x}", self.0.to_bits()))
-                    .finish()
+                f.field("is_alive", &false);
             }
+
+            f.finish()
         })
     }
 }
@@ -186,6 +377,14 @@ impl fmt::Debug for Entity {
 impl Entity {
     pub const DANGLING: Self = Self(Index::DANGLING);
 
+    pub fn from_raw(index: Index) -> Self {
+        Self(index)
+    }
+
+    pub fn raw(self) -> Index {
+        self.0
+    }
+
     pub fn new(parent: Entity) -> Self {
         let node = Self::new_root(EntityStore::fetch_mut());
         node.set_parent(Some(parent));
@@ -216,6 +415,15 @@ impl Entity {
         EntityStore::fetch().entities.contains(self.0)
     }
 
+    /// Returns whether the entity was queued up for destruction through `destroy` but has yet to
+    /// be destroyed by [`Entity::flush`].
+    pub fn is_condemned(self) -> bool {
+        EntityStore::fetch()
+            .entities
+            .get(self.0)
+            .is_some_and(|info| info.condemned())
+    }
+
     pub fn parent(self) -> Option<Entity> {
         EntityStore::fetch().entities[self.0].parent
     }
@@ -229,11 +437,14 @@ impl Entity {
 
         // Remove from old parent
         let me = &mut store.entities[self.0];
+        Self::mark_shape_dirty_before_update(&mut store.reshaped_entities, self, me);
+
         let old_parent = me.parent.take();
         let old_index = me.index_in_parent();
 
-        if let Some(parent) = old_parent {
-            let parent = &mut store.entities[parent.0];
+        if let Some(parent_id) = old_parent {
+            let parent = &mut store.entities[parent_id.0];
+            Self::mark_shape_dirty_before_update(&mut store.reshaped_entities, parent_id, parent);
 
             parent.children.mutate().swap_remove(old_index as usize);
             if let Some(&moved) = parent.children.vec.get(old_index as usize) {
@@ -264,7 +475,9 @@ impl Entity {
             .or_insert(entity_info.archetype);
     }
 
+    #[track_caller]
     pub fn add<T: Component>(self, value: T, cx: Bundle<&mut AccessComp<T>>) -> Obj<T> {
+        let tick = WORLD.change_tick();
         let store = EntityStore::fetch_mut();
         let storage = &mut **<T::Arena>::fetch_mut(pack!(@env, cx));
 
@@ -277,18 +490,14 @@ impl Entity {
         assert!(!entity.condemned(), "{self:?} is condemned");
 
         // See if we can update the existing component in-place.
-        let entry = match storage.entity_map.entry(self) {
-            Entry::Occupied(entry) => {
-                let handle = *entry.get();
-                storage.arena[handle].1 = value;
-                return Obj::from_raw(handle);
-            }
-            Entry::Vacant(entry) => entry,
-        };
+        if let Some(slot) = storage.get_by_entity_mut(self) {
+            slot.value = value;
+            slot.changed_tick = tick;
+            return Obj::from_raw(slot.handle);
+        }
 
         // Otherwise, create the new `Obj`...
-        let handle = storage.arena.insert((self, value));
-        entry.insert(handle);
+        let handle = storage.insert(self, tick, value);
 
         // ...and update the `EntityStore` to reflect the additional component.
         Self::mark_shape_dirty_before_update(&mut store.reshaped_entities, self, entity);
@@ -314,14 +523,16 @@ impl Entity {
         self
     }
 
+    #[track_caller]
     pub fn try_get<T: Component>(self, cx: Bundle<&AccessComp<T>>) -> Option<Obj<T>> {
-        <T::Arena>::fetch(pack!(@env, cx))
-            .entity_map
-            .get(&self)
-            .copied()
-            .map(Obj::from_raw)
+        // Not using `Option::map` so that the handle records our caller as its origin.
+        match <T::Arena>::fetch(pack!(@env, cx)).get_by_entity(self) {
+            Some(slot) => Some(Obj::from_raw(slot.handle)),
+            None => None,
+        }
     }
 
+    #[track_caller]
     pub fn get<T: Component>(self, cx: Bundle<&AccessComp<T>>) -> Obj<T> {
         self.try_get(pack!(cx)).unwrap_or_else(|| {
             panic!(
@@ -331,6 +542,7 @@ impl Entity {
         })
     }
 
+    #[track_caller]
     pub fn try_deep_get<T: Component>(self, cx: Bundle<&AccessComp<T>>) -> Option<Obj<T>> {
         let mut iter = Some(self);
 
@@ -345,6 +557,7 @@ impl Entity {
         None
     }
 
+    #[track_caller]
     pub fn deep_get<T: Component>(self, cx: Bundle<&AccessComp<T>>) -> Obj<T> {
         self.try_deep_get(pack!(cx)).unwrap_or_else(|| {
             panic!(
@@ -364,6 +577,7 @@ impl Entity {
             .insert(self);
     }
 
+    /// Removes the component immediately without running its `on_remove` hook.
     pub fn remove_now<T: Component>(self, cx: Bundle<&mut AccessComp<T>>) -> Option<T> {
         let store = EntityStore::fetch_mut();
 
@@ -386,11 +600,7 @@ impl Entity {
     ) -> Option<T> {
         let storage = <T::Arena>::fetch_mut(pack!(@env, cx));
 
-        let Some(obj) = storage.entity_map.remove(&self) else {
-            return None;
-        };
-
-        Some(storage.arena.remove(obj).unwrap().1)
+        storage.remove(self).map(|slot| slot.value)
     }
 
     pub fn destroy(self) {
@@ -405,6 +615,7 @@ impl Entity {
         store.target_queue_state.condemned.push(self);
     }
 
+    /// Destroys the entity and its descendants immediately without running any `on_remove` hooks.
     pub fn destroy_now(self) {
         let store = EntityStore::fetch_mut();
 
@@ -419,6 +630,13 @@ impl Entity {
             .parent
             .and_then(|parent| store.entities.get_mut(parent.0))
         {
+            // This invalidates the hierarchy queries of our ancestors.
+            Self::mark_shape_dirty_before_update(
+                &mut store.reshaped_entities,
+                entity.parent.unwrap(),
+                parent,
+            );
+
             let children = parent.children.mutate();
             let index_in_parent = entity.index_in_parent();
             children.swap_remove(index_in_parent as usize);
@@ -429,16 +647,7 @@ impl Entity {
         }
 
         // Remove from the reshaped map and into the dead map.
-        let old_archetype = store
-            .reshaped_entities
-            .remove(&self)
-            .unwrap_or(entity.archetype);
-
-        if old_archetype != ArchetypeId::EMPTY {
-            store
-                .dead_entities
-                .insert((entity.archetype, entity.index_in_archetype));
-        }
+        store.mark_dead(self, &entity);
 
         // Destroy all the components.
         let arch = entity.archetype;
@@ -456,6 +665,169 @@ impl Entity {
         }
     }
 
+    /// Spawns one child of `parent` per bundle. Each entity is placed directly into the archetype
+    /// of its bundle rather than being reshaped once per component.
+    pub fn spawn_batch<B: ComponentBundle>(
+        parent: Entity,
+        bundles: impl IntoIterator<Item = B>,
+    ) -> Vec<Entity> {
+        let bundles = bundles.into_iter().collect::<Vec<_>>();
+        let store = EntityStore::fetch_mut();
+
+        assert!(store.entities.contains(parent.0), "{parent:?} is not alive");
+
+        // Determine the archetype shared by every entity.
+        let mut archetype = ArchetypeId::EMPTY;
+        let mut comp_count = 0;
+
+        for comp in B::comp_ids() {
+            archetype = store.archetypes.lookup_extend(archetype, comp);
+            comp_count += 1;
+        }
+
+        assert_eq!(
+            store.archetypes.components(archetype).len(),
+            comp_count,
+            "bundle `{}` contains the same component type more than once",
+            type_name::<B>(),
+        );
+
+        // Create the entities...
+        let first_index = store.entities[parent.0].children.len();
+        let mut entities = Vec::with_capacity(bundles.len());
+
+        for i in 0..bundles.len() {
+            let mut info = EntityInfo {
+                archetype,
+                index_in_archetype: 0,
+                index_in_parent_bitor_condemned: 0,
+                parent: Some(parent),
+                children: EntityChildren { vec: Rc::default() },
+            };
+            info.set_index_in_parent(first_index + i);
+
+            let entity = Self(store.entities.insert(info));
+
+            // Like any other entity, these only join their archetype upon the next `flush`.
+            store.reshaped_entities.insert(entity, ArchetypeId::EMPTY);
+            entities.push(entity);
+        }
+
+        store.entities[parent.0]
+            .children
+            .mutate()
+            .extend_from_slice(&entities);
+
+        // ...and give them their components.
+        B::insert_batch(&mut WORLD, &entities, bundles);
+
+        entities
+    }
+
+    /// Destroys the given entities and their descendants immediately without running any
+    /// `on_remove` hooks. This is equivalent to calling `destroy_now` on each of them but removes
+    /// components one storage at a time.
+    pub fn destroy_batch_now(roots: impl IntoIterator<Item = Entity>) {
+        // Detach the subtrees from the rest of the hierarchy.
+        let roots = roots
+            .into_iter()
+            .filter(|&root| root.is_alive())
+            .collect::<Vec<_>>();
+
+        for &root in &roots {
+            root.set_parent(None);
+        }
+
+        // Destroy entity information before calling destructors to avoid reentrant operations on
+        // dying entities.
+        let store = EntityStore::fetch_mut();
+        let mut removed = FxHashMap::<ComponentId, FxHashSet<Entity>>::default();
+        let mut visit_stack = roots;
+
+        while let Some(curr) = visit_stack.pop() {
+            let Some(info) = store.entities.remove(curr.0) else {
+                continue;
+            };
+
+            store.mark_dead(curr, &info);
+
+            for &comp in store.archetypes.components(info.archetype) {
+                removed.entry(comp).or_default().insert(curr);
+            }
+
+            visit_stack.extend(&info.children);
+        }
+
+        // Destroy all the components.
+        for (comp, entities) in &removed {
+            (comp.remove_for_deferred)(&mut WORLD, entities);
+        }
+    }
+
+    /// Iterates over the entity's parent, grandparent, and so on up to the root.
+    pub fn ancestors<'a>(self, cx: Bundle<AccessResRef<'a, EntityStore>>) -> Ancestors<'a> {
+        let store = EntityStore::fetch(pack!(cx));
+
+        Ancestors {
+            store,
+            next: store.entities[self.0].parent,
+        }
+    }
+
+    /// Collects the entities in the subtree rooted at this entity, including the entity itself,
+    /// which own every component in `comps`. Entities are listed in pre-order.
+    ///
+    /// Results are cached and only invalidated by `flush`, so, much like with `Query`, changes made
+    /// to the subtree since the last `flush` may not be reflected yet. In particular, the subtree
+    /// may still list entities which have since been destroyed or have lost a component.
+    pub fn descendants_with(self, comps: impl IntoIterator<Item = ComponentId>) -> EntitySubtree {
+        let store = EntityStore::fetch_mut();
+
+        let mut comps = comps.into_iter().collect::<Vec<_>>();
+        comps.sort();
+        comps.dedup();
+
+        if let Some(subtree) = store
+            .hierarchy_cache
+            .get(&self)
+            .and_then(|cache| cache.get(comps.as_slice()))
+        {
+            return subtree.clone();
+        }
+
+        assert!(store.entities.contains(self.0), "{self:?} is not alive");
+
+        let mut order = Vec::new();
+        let mut visit_stack = vec![self];
+
+        while let Some(curr) = visit_stack.pop() {
+            let info = &store.entities[curr.0];
+            let owned = store.archetypes.components_set(info.archetype);
+
+            if comps.iter().all(|comp| owned.contains(comp)) {
+                order.push(curr);
+            }
+
+            // Push in reverse so that the first child is visited first.
+            visit_stack.extend(info.children.vec.iter().rev());
+        }
+
+        let subtree = EntitySubtree {
+            inner: Rc::new(EntitySubtreeInner {
+                members: order.iter().copied().collect(),
+                order,
+            }),
+        };
+
+        store
+            .hierarchy_cache
+            .entry(self)
+            .or_default()
+            .insert(comps.into_boxed_slice(), subtree.clone());
+
+        subtree
+    }
+
     pub fn debug<'a>(self, cx: Bundle<&'a mut WORLD>) -> WorldFmt<'a, Self> {
         WorldFmt::new(self, pack!(cx))
     }
@@ -477,8 +849,15 @@ impl Entity {
     }
 
     pub fn flush(mut f: impl FnMut(&mut World)) {
+        let _span = tracing::debug_span!("Entity::flush").entered();
+        let start = Instant::now();
+        let mut batches = 0;
+
         // Process queued operations
         loop {
+            // Apply recorded commands first since they may queue up further operations.
+            Commands::apply_recorded();
+
             // See if there are any operations remaining.
             let store = EntityStore::fetch_mut();
             let mut queue = mem::take(&mut store.target_queue_state);
@@ -486,6 +865,8 @@ impl Entity {
                 break;
             }
 
+            batches += 1;
+
             // Normalize queue
             let orig_condemned_len = queue.condemned.len();
 
@@ -521,6 +902,20 @@ impl Entity {
             let queue = Rc::new(queue);
             EntityStore::fetch_mut().view_queue_state = queue.clone();
 
+            // Run `on_remove` hooks while the doomed components are still alive. Operations they
+            // queue up are handled in the next iteration of this loop.
+            let mut removed = queue
+                .to_remove
+                .iter()
+                .filter_map(|(&comp, (_deleted, indices))| Some((comp, comp.on_remove?, indices)))
+                .collect::<Vec<_>>();
+
+            removed.sort_by_key(|&(comp, ..)| hook_order_key(comp));
+
+            for (_comp, on_remove, indices) in removed {
+                on_remove(&mut WORLD, indices);
+            }
+
             // Run handler
             f(&mut WORLD);
 
@@ -541,9 +936,17 @@ impl Entity {
 
         // Process reshape requests.
         let store = EntityStore::fetch_mut();
+        store.invalidate_hierarchy_cache();
+
         let archetype_members = Rc::get_mut(&mut store.query_state)
             .expect("cannot `flush` the world while it is still being iterated over");
 
+        let mut churn = FlushChurn {
+            flushes: 1,
+            reshaped: 0,
+            destroyed: store.dead_entities.len() as u64,
+        };
+
         // Begin with entity destruction since we don't want to try to move the indices of dead
         // entities as we update the archetypes.
         for (arch, idx) in store.dead_entities.drain() {
@@ -592,6 +995,8 @@ impl Entity {
         }
 
         // Now, we can handle move requests involving entirely live entities.
+        let mut added = Vec::new();
+
         for (entity, old_arch) in store.reshaped_entities.drain() {
             let own_info = &store.entities[entity.0];
             let curr_arch = own_info.archetype;
@@ -602,6 +1007,8 @@ impl Entity {
                 continue;
             }
 
+            churn.reshaped += 1;
+
             // Remove from the old archetype.
             if old_arch != ArchetypeId::EMPTY {
                 let index_members = archetype_members.index_members.get_mut(&old_arch).unwrap();
@@ -652,9 +1059,97 @@ impl Entity {
                         .get_mut(&(curr_arch, comp))
                         .unwrap()
                         .push(unsafe { (comp.fetch_idx)(&WORLD, entity) });
+
+                    if comp.on_add.is_some()
+                        && !store.archetypes.components_set(old_arch).contains(&comp)
+                    {
+                        added.push((comp, entity));
+                    }
                 }
             }
         }
+
+        // Run `on_add` hooks now that queries can see the new components. Structural changes made
+        // by these hooks are only applied by the next `flush`.
+        added.sort_by_key(|&(comp, _entity)| hook_order_key(comp));
+
+        for (comp, entity) in added {
+            (comp.on_add.unwrap())(&mut WORLD, entity);
+        }
+
+        let store = EntityStore::fetch_mut();
+        store.last_flush_churn = churn;
+        store.total_flush_churn += churn;
+
+        // Finally, begin a new change-detection period and event frame.
+        World::advance_change_tick(&mut WORLD);
+        EventBus::end_frame();
+
+        Profiler::fetch_mut().record_flush(start.elapsed(), batches);
+    }
+}
+
+/// Orders the hooks run by `Entity::flush` so that teardown doesn't depend on hash map order.
+fn hook_order_key(comp: ComponentId) -> (i32, &'static str) {
+    (comp.hook_order, (comp.type_name)())
+}
+
+#[derive(Debug, Clone)]
+pub struct Ancestors<'a> {
+    store: &'a EntityStore,
+    next: Option<Entity>,
+}
+
+impl Iterator for Ancestors<'_> {
+    type Item = Entity;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let curr = self.next?;
+        self.next = self.store.entities[curr.0].parent;
+        Some(curr)
+    }
+}
+
+#[derive(Clone)]
+pub struct EntitySubtree {
+    inner: Rc<EntitySubtreeInner>,
+}
+
+struct EntitySubtreeInner {
+    order: Vec<Entity>,
+    members: FxHashSet<Entity>,
+}
+
+impl fmt::Debug for EntitySubtree {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.inner.order.iter()).finish()
+    }
+}
+
+impl EntitySubtree {
+    pub fn len(&self) -> usize {
+        self.inner.order.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inner.order.is_empty()
+    }
+
+    pub fn contains(&self, entity: Entity) -> bool {
+        self.inner.members.contains(&entity)
+    }
+
+    pub fn iter(&self) -> iter::Copied<slice::Iter<'_, Entity>> {
+        self.inner.order.iter().copied()
+    }
+}
+
+impl<'a> IntoIterator for &'a EntitySubtree {
+    type Item = Entity;
+    type IntoIter = iter::Copied<slice::Iter<'a, Entity>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
     }
 }
 
@@ -701,62 +1196,727 @@ pub type AccessCompMut<'a, T> = (&'a WORLD, &'a mut AccessComp<T>);
 
 pub trait Component: 'static + Sized + fmt::Debug {
     type Arena: Resource + DerefMut<Target = Storage<Self>>;
+
+    /// Set through the `storage` option of the `component!` macro.
+    const STORAGE: StorageKind = StorageKind::Arena;
+
+    /// Set through the `serde` option of the `component!` macro.
+    const SERDE: Option<&'static ComponentSerde> = None;
+
+    /// The name under which the component is serialized, which must remain stable across builds.
+    /// Set through the `serde` option of the `component!` macro.
+    const SERDE_NAME: &'static str = "";
+
+    /// Set through the `hash` option of the `component!` macro.
+    const HASH: Option<&'static ComponentHash> = None;
+
+    /// The name under which the component is hashed, which must remain stable across builds.
+    /// Set through the `hash` option of the `component!` macro.
+    const HASH_NAME: &'static str = "";
+
+    /// Set through the `reflect` option of the `component!` macro. Reflection goes through the
+    /// same hooks as serialization but does not require the `serde` option.
+    const REFLECT: Option<&'static ComponentSerde> = None;
+
+    /// Set through the `rollback` option of the `component!` macro.
+    const ROLLBACK: Option<&'static ComponentRollback> = None;
+
+    /// Set through the `hook_order` option of the `component!` macro. Within a `flush`, the hooks
+    /// of components with a lower order run before those of components with a higher order. Ties
+    /// are broken by type name.
+    const HOOK_ORDER: i32 = 0;
+
+    /// Set through the `on_add` option of the `component!` macro.
+    const ON_ADD: Option<fn(&mut World, Obj<Self>)> = None;
+
+    /// Set through the `on_remove` option of the `component!` macro.
+    const ON_REMOVE: Option<fn(&mut World, Obj<Self>)> = None;
 }
 
+/// The storage of every component of type `T`, laid out as selected through [`StorageKind`].
+///
+/// By default, components live in a generational arena whose indices are the handles wrapped by
+/// [`Obj`] and entities are mapped to their component's handle by a hash map. The `Dense` and
+/// `Sparse` layouts instead pack components contiguously in a dense array, in no particular order,
+/// so that passes over every component of a type walk memory linearly. Removing a component from
+/// such a layout moves the last one into its place. [`Obj`] handles remain stable across such
+/// moves because they are resolved through a table mapping handles to positions in the dense array.
 #[derive(Debug)]
-#[derive_where(Default)]
 pub struct Storage<T> {
-    pub arena: Arena<(Entity, T)>,
-    pub entity_map: FxHashMap<Entity, Index>,
+    layout: Layout<T>,
+
+    /// The handle and owner of the last component removed from each handle slot, used to diagnose
+    /// dangling `Obj` dereferences.
+    #[cfg(debug_assertions)]
+    graveyard: FxHashMap<u32, (Index, Entity)>,
 }
 
-#[doc(hidden)]
-pub mod component_internals {
-    pub use {
-        super::{Component, Storage},
-        crate::resource,
-        std::ops::{Deref, DerefMut},
-    };
+#[derive(Debug)]
+enum Layout<T> {
+    Arena {
+        /// The components, indexed by their handle.
+        arena: Arena<StorageSlot<T>>,
+
+        /// Maps entities to the handle of their component.
+        entity_map: FxHashMap<Entity, Index>,
+    },
+    Dense {
+        /// The components, packed contiguously.
+        dense: Vec<StorageSlot<T>>,
+
+        /// Maps the handles wrapped by `Obj` to the position of their component in `dense`.
+        handles: Arena<u32>,
+
+        /// Maps entities to the position of their component in `dense`.
+        entity_map: EntityMap,
+    },
 }
 
-#[macro_export]
-macro_rules! component {
-    ($($ty:ty),*$(,)?) => {$(
-        const _: () = {
-            #[derive(Default)]
-            pub struct Storage($crate::entity::component_internals::Storage<$ty>);
+impl<T> Storage<T> {
+    pub fn kind(&self) -> StorageKind {
+        match &self.layout {
+            Layout::Arena { .. } => StorageKind::Arena,
+            Layout::Dense { entity_map, .. } => entity_map.kind(),
+        }
+    }
 
-            impl $crate::entity::component_internals::Deref for Storage {
-                type Target = $crate::entity::component_internals::Storage<$ty>;
+    pub fn len(&self) -> usize {
+        match &self.layout {
+            Layout::Arena { arena, .. } => arena.len(),
+            Layout::Dense { dense, .. } => dense.len(),
+        }
+    }
 
-                fn deref(&self) -> &Self::Target {
-                    &self.0
-                }
-            }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
 
-            impl $crate::entity::component_internals::DerefMut for Storage {
-                fn deref_mut(&mut self) -> &mut Self::Target {
-                    &mut self.0
-                }
+    pub fn contains(&self, handle: Index) -> bool {
+        match &self.layout {
+            Layout::Arena { arena, .. } => arena.contains(handle),
+            Layout::Dense { handles, .. } => handles.contains(handle),
+        }
+    }
+
+    pub fn get(&self, handle: Index) -> Option<&StorageSlot<T>> {
+        match &self.layout {
+            Layout::Arena { arena, .. } => arena.get(handle),
+            Layout::Dense { dense, handles, .. } => {
+                let &pos = handles.get(handle)?;
+
+                Some(&dense[pos as usize])
             }
+        }
+    }
 
-            $crate::entity::component_internals::resource!(Storage);
+    pub fn get_mut(&mut self, handle: Index) -> Option<&mut StorageSlot<T>> {
+        match &mut self.layout {
+            Layout::Arena { arena, .. } => arena.get_mut(handle),
+            Layout::Dense { dense, handles, .. } => {
+                let &pos = handles.get(handle)?;
 
-            impl $crate::entity::component_internals::Component for $ty {
-                type Arena = Storage;
+                Some(&mut dense[pos as usize])
             }
-        };
-    )*};
-}
+        }
+    }
 
-pub use component;
+    pub fn get_by_entity(&self, entity: Entity) -> Option<&StorageSlot<T>> {
+        match &self.layout {
+            Layout::Arena { arena, entity_map } => {
+                let &handle = entity_map.get(&entity)?;
 
-// === Obj === //
+                Some(&arena[handle])
+            }
+            Layout::Dense {
+                dense, entity_map, ..
+            } => {
+                let pos = entity_map.get(entity)?;
 
-#[derive_where(Copy, Clone, Hash, Eq, PartialEq, Ord, PartialOrd)]
+                Some(&dense[pos as usize])
+            }
+        }
+    }
+
+    pub fn get_by_entity_mut(&mut self, entity: Entity) -> Option<&mut StorageSlot<T>> {
+        match &mut self.layout {
+            Layout::Arena { arena, entity_map } => {
+                let &handle = entity_map.get(&entity)?;
+
+                Some(&mut arena[handle])
+            }
+            Layout::Dense {
+                dense, entity_map, ..
+            } => {
+                let pos = entity_map.get(entity)?;
+
+                Some(&mut dense[pos as usize])
+            }
+        }
+    }
+
+    /// Iterates over every component in the order in which they are stored.
+    pub fn iter(&self) -> impl Iterator<Item = &StorageSlot<T>> {
+        match &self.layout {
+            Layout::Arena { arena, .. } => LayoutIter::Arena(arena.iter().map(|(_, slot)| slot)),
+            Layout::Dense { dense, .. } => LayoutIter::Dense(dense.iter()),
+        }
+    }
+
+    /// Like [`Storage::iter`] but allows the components to be mutated. Unlike dereferencing an
+    /// [`Obj`], this does not update `changed_tick`.
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut StorageSlot<T>> {
+        match &mut self.layout {
+            Layout::Arena { arena, .. } => {
+                LayoutIter::Arena(arena.iter_mut().map(|(_, slot)| slot))
+            }
+            Layout::Dense { dense, .. } => LayoutIter::Dense(dense.iter_mut()),
+        }
+    }
+
+    /// Inserts the component of an entity which doesn't have one yet, returning its handle.
+    pub(crate) fn insert(&mut self, owner: Entity, tick: u32, value: T) -> Index {
+        let slot = StorageSlot {
+            owner,
+            handle: Index::DANGLING,
+            added_tick: tick,
+            changed_tick: tick,
+            value,
+        };
+
+        let (handle, replaced) = match &mut self.layout {
+            Layout::Arena { arena, entity_map } => {
+                let handle = arena.insert(slot);
+                arena[handle].handle = handle;
+
+                (handle, entity_map.insert(owner, handle).is_some())
+            }
+            Layout::Dense {
+                dense,
+                handles,
+                entity_map,
+            } => {
+                let pos = u32::try_from(dense.len())
+                    .unwrap_or_else(|_| panic!("too many `{}` components", type_name::<T>()));
+
+                let handle = handles.insert(pos);
+                dense.push(StorageSlot { handle, ..slot });
+
+                (handle, entity_map.insert(owner, pos).is_some())
+            }
+        };
+
+        debug_assert!(!replaced, "{owner:?} already had this component");
+
+        handle
+    }
+
+    /// Removes the component of the given entity, if it has one.
+    pub(crate) fn remove(&mut self, entity: Entity) -> Option<StorageSlot<T>> {
+        let slot = match &mut self.layout {
+            Layout::Arena { arena, entity_map } => {
+                let handle = entity_map.remove(&entity)?;
+                arena.remove(handle).unwrap()
+            }
+            Layout::Dense {
+                dense,
+                handles,
+                entity_map,
+            } => {
+                let pos = entity_map.remove(entity)?;
+                let slot = dense.swap_remove(pos as usize);
+                handles.remove(slot.handle);
+
+                // Patch up the component we moved into the hole.
+                if let Some(moved) = dense.get(pos as usize) {
+                    handles[moved.handle] = pos;
+                    entity_map.insert(moved.owner, pos);
+                }
+
+                slot
+            }
+        };
+
+        #[cfg(debug_assertions)]
+        self.graveyard
+            .insert(slot.handle.slot(), (slot.handle, entity));
+
+        Some(slot)
+    }
+
+    pub(crate) fn reserve(&mut self, additional: usize) {
+        match &mut self.layout {
+            Layout::Arena { entity_map, .. } => entity_map.reserve(additional),
+            Layout::Dense {
+                dense, entity_map, ..
+            } => {
+                dense.reserve(additional);
+                entity_map.reserve(additional);
+            }
+        }
+    }
+
+    pub fn stats(&self) -> StorageStats {
+        let (capacity, bytes) = match &self.layout {
+            Layout::Arena { arena, entity_map } => {
+                (arena.capacity(), arena_bytes(arena) + map_bytes(entity_map))
+            }
+            Layout::Dense {
+                dense,
+                handles,
+                entity_map,
+            } => (
+                dense.capacity(),
+                vec_bytes(dense) + arena_bytes(handles) + entity_map.estimated_bytes(),
+            ),
+        };
+
+        #[cfg(debug_assertions)]
+        let bytes = bytes + map_bytes(&self.graveyard);
+
+        StorageStats {
+            kind: self.kind(),
+            len: self.len(),
+            capacity,
+            bytes,
+        }
+    }
+}
+
+impl<T: Component> Default for Storage<T> {
+    fn default() -> Self {
+        let layout = match T::STORAGE {
+            StorageKind::Arena => Layout::Arena {
+                arena: Arena::new(),
+                entity_map: FxHashMap::default(),
+            },
+            kind @ (StorageKind::Dense | StorageKind::Sparse) => Layout::Dense {
+                dense: Vec::new(),
+                handles: Arena::new(),
+                entity_map: EntityMap::new(kind),
+            },
+        };
+
+        Self {
+            layout,
+            #[cfg(debug_assertions)]
+            graveyard: FxHashMap::default(),
+        }
+    }
+}
+
+impl<T> ops::Index<Entity> for Storage<T> {
+    type Output = StorageSlot<T>;
+
+    fn index(&self, entity: Entity) -> &Self::Output {
+        self.get_by_entity(entity)
+            .unwrap_or_else(|| panic!("{entity:?} does not have this component"))
+    }
+}
+
+/// Iterates over the components of whichever layout a [`Storage`] uses.
+enum LayoutIter<A, D> {
+    Arena(A),
+    Dense(D),
+}
+
+impl<A: Iterator, D: Iterator<Item = A::Item>> Iterator for LayoutIter<A, D> {
+    type Item = A::Item;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            Self::Arena(iter) => iter.next(),
+            Self::Dense(iter) => iter.next(),
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        match self {
+            Self::Arena(iter) => iter.size_hint(),
+            Self::Dense(iter) => iter.size_hint(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct StorageSlot<T> {
+    /// The entity owning this component.
+    pub owner: Entity,
+
+    /// The handle through which `Obj`s refer to this component.
+    pub handle: Index,
+
+    /// The `World::change_tick` during which the component was inserted.
+    pub added_tick: u32,
+
+    /// The `World::change_tick` during which the component was last mutably dereferenced or
+    /// overwritten.
+    pub changed_tick: u32,
+
+    pub value: T,
+}
+
+/// Selects how a [`Storage`] lays out its components and finds the component belonging to a
+/// given entity.
+#[derive(Debug, Copy, Clone, Hash, Eq, PartialEq, Default)]
+pub enum StorageKind {
+    /// Components live in a generational arena and entities are looked up in a hash map.
+    /// Dereferencing an [`Obj`] indexes the arena directly.
+    #[default]
+    Arena,
+
+    /// Components are packed in a dense array and entities are looked up in a hash map. This
+    /// speeds up passes over every component of a type at the cost of an extra indirection when
+    /// dereferencing an [`Obj`]. It is best suited to components which few entities have.
+    Dense,
+
+    /// Components are packed in a dense array and entities are looked up in a sparse array indexed
+    /// by their arena slot, making lookups a bounds check and a generation check. The array grows
+    /// to fit the largest slot of any entity which ever had the component so this is best suited
+    /// to components which most entities have.
+    Sparse,
+}
+
+/// Maps entities to the position of their component in the dense array of a [`Storage`].
+#[derive(Debug, Clone)]
+enum EntityMap {
+    Hashed(FxHashMap<Entity, u32>),
+
+    /// Indexed by arena slot. Entries hold the entity they were inserted for so that entities
+    /// which reuse the slot of a dead one don't inherit its component.
+    Sparse(Vec<Option<(Entity, u32)>>),
+}
+
+impl EntityMap {
+    fn new(kind: StorageKind) -> Self {
+        match kind {
+            StorageKind::Dense => Self::Hashed(FxHashMap::default()),
+            StorageKind::Sparse => Self::Sparse(Vec::new()),
+            StorageKind::Arena => unreachable!("arena storages map entities to handles"),
+        }
+    }
+
+    fn kind(&self) -> StorageKind {
+        match self {
+            Self::Hashed(_) => StorageKind::Dense,
+            Self::Sparse(_) => StorageKind::Sparse,
+        }
+    }
+
+    fn get(&self, entity: Entity) -> Option<u32> {
+        match self {
+            Self::Hashed(map) => map.get(&entity).copied(),
+            Self::Sparse(slots) => match slots.get(entity.0.slot() as usize) {
+                Some(&Some((owner, pos))) if owner == entity => Some(pos),
+                _ => None,
+            },
+        }
+    }
+
+    fn insert(&mut self, entity: Entity, pos: u32) -> Option<u32> {
+        match self {
+            Self::Hashed(map) => map.insert(entity, pos),
+            Self::Sparse(slots) => {
+                let slot = entity.0.slot() as usize;
+
+                if slot >= slots.len() {
+                    slots.resize(slot + 1, None);
+                }
+
+                // Entries left behind by dead entities occupying the same slot are overwritten.
+                slots[slot]
+                    .replace((entity, pos))
+                    .filter(|&(owner, _)| owner == entity)
+                    .map(|(_, pos)| pos)
+            }
+        }
+    }
+
+    fn remove(&mut self, entity: Entity) -> Option<u32> {
+        match self {
+            Self::Hashed(map) => map.remove(&entity),
+            Self::Sparse(slots) => {
+                let slot = slots.get_mut(entity.0.slot() as usize)?;
+
+                if !matches!(slot, Some((owner, _)) if *owner == entity) {
+                    return None;
+                }
+
+                slot.take().map(|(_, pos)| pos)
+            }
+        }
+    }
+
+    fn estimated_bytes(&self) -> usize {
+        match self {
+            Self::Hashed(map) => map_bytes(map),
+            Self::Sparse(slots) => vec_bytes(slots),
+        }
+    }
+
+    fn reserve(&mut self, additional: usize) {
+        match self {
+            Self::Hashed(map) => map.reserve(additional),
+            // The array is sized by slot rather than by count so there's nothing useful to do.
+            Self::Sparse(_) => {}
+        }
+    }
+}
+
+#[doc(hidden)]
+pub mod component_internals {
+    pub use {
+        super::{Component, Obj, Storage, StorageKind},
+        crate::{
+            archetype::{ComponentId, COMPONENTS},
+            component_options,
+            digest::ComponentHash,
+            resource,
+            rollback::ComponentRollback,
+            snapshot::ComponentSerde,
+            World,
+        },
+        linkme::{self, distributed_slice},
+        std::{
+            ops::{Deref, DerefMut},
+            option::Option::{self, Some},
+        },
+    };
+}
+
+/// Defines one or more component types, each optionally followed by a braced list of options:
+///
+/// - `storage: <StorageKind variant>`: selects how the component's storage lays out its components.
+///   This defaults to `Arena`. See [`StorageKind`] for the available strategies.
+/// - `serde: "<name>"`: includes the component in
+///   [`EntitySnapshot`](crate::snapshot::EntitySnapshot)s, which refer to it by the given name.
+///   Names must be unique among components and should never change once snapshots using them have
+///   been saved. The component must implement `Serialize` and `Deserialize`.
+/// - `hash: "<name>"`: includes the component in [`WorldDigest`](crate::digest::WorldDigest)s,
+///   which order and identify it by the given name. Names must be unique among hashed components
+///   and identical across every build whose digests are compared. The component must implement
+///   `Serialize`.
+/// - `reflect`: exposes the component to the [`reflect`](crate::reflect) API through its
+///   serialization hooks. The component must implement `Serialize` and `Deserialize`.
+/// - `rollback`: includes the component in [`RollbackBuffer`](crate::rollback::RollbackBuffer)s.
+///   The component must implement `Clone`.
+/// - `on_add: <fn(&mut World, Obj<Self>)>`: called by [`Entity::flush`] once the entity has been
+///   moved into an archetype containing the component.
+/// - `on_remove: <fn(&mut World, Obj<Self>)>`: called by [`Entity::flush`] for components removed
+///   through `remove` or `destroy` while they're still alive. `remove_now` and `destroy_now` do
+///   not run this hook.
+/// - `hook_order: <i32>`: orders the `on_add` and `on_remove` hooks run by a single
+///   [`Entity::flush`]. Hooks of components with a lower order run first and ties are broken by
+///   type name. This defaults to `0` and lets components which refer to one another, such as
+///   nodes and the peers they're replicated to, be torn down in a well-defined order.
+#[macro_export]
+macro_rules! component {
+    ($($ty:ty $({ $($opt:tt)* })?),*$(,)?) => {$(
+        const _: () = {
+            #[derive(Default)]
+            pub struct Storage($crate::entity::component_internals::Storage<$ty>);
+
+            impl $crate::entity::component_internals::Deref for Storage {
+                type Target = $crate::entity::component_internals::Storage<$ty>;
+
+                fn deref(&self) -> &Self::Target {
+                    &self.0
+                }
+            }
+
+            impl $crate::entity::component_internals::DerefMut for Storage {
+                fn deref_mut(&mut self) -> &mut Self::Target {
+                    &mut self.0
+                }
+            }
+
+            $crate::entity::component_internals::resource!(Storage);
+
+            impl $crate::entity::component_internals::Component for $ty {
+                type Arena = Storage;
+
+                $($crate::entity::component_internals::component_options!($($opt)*);)?
+            }
+
+            #[$crate::entity::component_internals::distributed_slice($crate::entity::component_internals::COMPONENTS)]
+            #[linkme(crate = $crate::entity::component_internals::linkme)]
+            static COMP: fn() -> $crate::entity::component_internals::ComponentId
+                = $crate::entity::component_internals::ComponentId::of::<$ty>;
+        };
+    )*};
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! component_options {
+    () => {};
+    (storage: $kind:ident $(, $($rest:tt)*)?) => {
+        const STORAGE: $crate::entity::component_internals::StorageKind =
+            $crate::entity::component_internals::StorageKind::$kind;
+
+        $crate::entity::component_internals::component_options!($($($rest)*)?);
+    };
+    (serde: $name:literal $(, $($rest:tt)*)?) => {
+        const SERDE_NAME: &'static str = $name;
+        const SERDE: $crate::entity::component_internals::Option<
+            &'static $crate::entity::component_internals::ComponentSerde,
+        > = $crate::entity::component_internals::Some(
+            $crate::entity::component_internals::ComponentSerde::of::<Self>(),
+        );
+
+        $crate::entity::component_internals::component_options!($($($rest)*)?);
+    };
+    (hash: $name:literal $(, $($rest:tt)*)?) => {
+        const HASH_NAME: &'static str = $name;
+        const HASH: $crate::entity::component_internals::Option<
+            &'static $crate::entity::component_internals::ComponentHash,
+        > = $crate::entity::component_internals::Some(
+            $crate::entity::component_internals::ComponentHash::of::<Self>(),
+        );
+
+        $crate::entity::component_internals::component_options!($($($rest)*)?);
+    };
+    (reflect $(, $($rest:tt)*)?) => {
+        const REFLECT: $crate::entity::component_internals::Option<
+            &'static $crate::entity::component_internals::ComponentSerde,
+        > = $crate::entity::component_internals::Some(
+            $crate::entity::component_internals::ComponentSerde::of::<Self>(),
+        );
+
+        $crate::entity::component_internals::component_options!($($($rest)*)?);
+    };
+    (rollback $(, $($rest:tt)*)?) => {
+        const ROLLBACK: $crate::entity::component_internals::Option<
+            &'static $crate::entity::component_internals::ComponentRollback,
+        > = $crate::entity::component_internals::Some(
+            $crate::entity::component_internals::ComponentRollback::of::<Self>(),
+        );
+
+        $crate::entity::component_internals::component_options!($($($rest)*)?);
+    };
+    (hook_order: $order:expr $(, $($rest:tt)*)?) => {
+        const HOOK_ORDER: i32 = $order;
+
+        $crate::entity::component_internals::component_options!($($($rest)*)?);
+    };
+    (on_add: $hook:expr $(, $($rest:tt)*)?) => {
+        const ON_ADD: $crate::entity::component_internals::Option<
+            fn(
+                &mut $crate::entity::component_internals::World,
+                $crate::entity::component_internals::Obj<Self>,
+            ),
+        > = $crate::entity::component_internals::Some($hook);
+
+        $crate::entity::component_internals::component_options!($($($rest)*)?);
+    };
+    (on_remove: $hook:expr $(, $($rest:tt)*)?) => {
+        const ON_REMOVE: $crate::entity::component_internals::Option<
+            fn(
+                &mut $crate::entity::component_internals::World,
+                $crate::entity::component_internals::Obj<Self>,
+            ),
+        > = $crate::entity::component_internals::Some($hook);
+
+        $crate::entity::component_internals::component_options!($($($rest)*)?);
+    };
+}
+
+pub use component;
+
+// === ComponentBundle === //
+
+/// A tuple of components which can be given to entities en masse through
+/// [`Entity::spawn_batch`].
+pub trait ComponentBundle: 'static + Sized {
+    fn comp_ids() -> impl IntoIterator<Item = ComponentId>;
+
+    /// Inserts each bundle's components into their storages on behalf of the corresponding entity
+    /// without updating the entity's archetype.
+    fn insert_batch(world: &mut World, entities: &[Entity], bundles: Vec<Self>);
+}
+
+fn insert_column<T: Component>(world: &mut World, entities: &[Entity], values: Vec<T>) {
+    bind!(world, let cx: &mut AccessComp<T>);
+
+    let tick = WORLD.change_tick();
+    let storage = &mut **<T::Arena>::fetch_mut(pack!(@env, cx));
+
+    storage.reserve(entities.len());
+
+    for (&owner, value) in entities.iter().zip(values) {
+        storage.insert(owner, tick, value);
+    }
+}
+
+macro_rules! impl_component_bundle {
+    ($($para:ident:$field:tt),*) => {
+        impl<$($para: Component),*> ComponentBundle for ($($para,)*) {
+            fn comp_ids() -> impl IntoIterator<Item = ComponentId> {
+                [$(ComponentId::of::<$para>()),*]
+            }
+
+            fn insert_batch(world: &mut World, entities: &[Entity], bundles: Vec<Self>) {
+                let mut columns = ($(Vec::<$para>::with_capacity(bundles.len()),)*);
+
+                for bundle in bundles {
+                    $(columns.$field.push(bundle.$field);)*
+                }
+
+                $(insert_column(world, entities, columns.$field);)*
+            }
+        }
+    };
+}
+
+impl_tuples!(impl_component_bundle; no_unit);
+
+// === Obj === //
+
+/// A handle to a component of type `T`.
+///
+/// Dereferencing an `Obj` whose component has been removed panics. In debug builds, the panic
+/// reports the entity which owned the component and the location at which the handle was
+/// created. Use a [`WeakObj`] to hold onto components which may be removed.
 pub struct Obj<T: Component> {
     _ty: PhantomData<fn(T) -> T>,
     index: Index,
+
+    /// Where this handle was created. This is ignored by comparisons and hashing.
+    #[cfg(debug_assertions)]
+    origin: Option<&'static Location<'static>>,
+}
+
+impl<T: Component> Copy for Obj<T> {}
+
+impl<T: Component> Clone for Obj<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T: Component> Hash for Obj<T> {
+    fn hash<H: hash::Hasher>(&self, state: &mut H) {
+        self.index.hash(state);
+    }
+}
+
+impl<T: Component> Eq for Obj<T> {}
+
+impl<T: Component> PartialEq for Obj<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.index == other.index
+    }
+}
+
+impl<T: Component> Ord for Obj<T> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.index.cmp(&other.index)
+    }
+}
+
+impl<T: Component> PartialOrd for Obj<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
 }
 
 impl<T: Component> fmt::Debug for Obj<T> {
@@ -764,13 +1924,13 @@ impl<T: Component> fmt::Debug for Obj<T> {
         let index = self.index.to_bits();
 
         ImmutableWorld::try_use_tls(|world| {
-            if let Some(world) = world.filter(|_| can_format_obj(*self)) {
+            if let Some(world) = world.filter(|&world| can_format_obj(world, *self)) {
                 let storage = world.read::<T::Arena>();
 
-                if let Some((_owner, value)) = storage.arena.get(self.index) {
+                if let Some(slot) = storage.get(self.index) {
                     f.debug_tuple("Obj")
                         .field(&format_args!("0x{index:x}"))
-                        .field(value)
+                        .field(&slot.value)
                         .finish()
                 } else {
                     struct Dead;
@@ -799,12 +1959,17 @@ impl<T: Component> Obj<T> {
     pub const DANGLING: Obj<T> = Obj {
         _ty: PhantomData,
         index: Index::DANGLING,
+        #[cfg(debug_assertions)]
+        origin: None,
     };
 
+    #[track_caller]
     pub fn from_raw(index: Index) -> Self {
         Self {
             _ty: PhantomData,
             index,
+            #[cfg(debug_assertions)]
+            origin: Some(Location::caller()),
         }
     }
 
@@ -813,31 +1978,415 @@ impl<T: Component> Obj<T> {
     }
 
     pub fn is_alive(me: Self, cx: Bundle<&AccessComp<T>>) -> bool {
-        <T::Arena>::fetch(pack!(@env, cx)).arena.contains(me.index)
+        <T::Arena>::fetch(pack!(@env, cx)).contains(me.index)
     }
 
+    #[track_caller]
     pub fn entity(self, cx: Bundle<&AccessComp<T>>) -> Entity {
-        <T::Arena>::fetch(pack!(@env, cx)).arena[self.index].0
+        let storage = <T::Arena>::fetch(pack!(@env, cx));
+
+        match storage.get(self.index) {
+            Some(slot) => slot.owner,
+            None => self.dangling(storage),
+        }
     }
 
     pub fn debug<'a>(self, cx: Bundle<&'a mut WORLD>) -> WorldFmt<'a, Self> {
         WorldFmt::new(self, pack!(cx))
     }
+
+    pub fn downgrade(self) -> WeakObj<T> {
+        WeakObj { obj: self }
+    }
+
+    /// Panics with as much information about the dangling handle as we have.
+    #[cold]
+    #[track_caller]
+    fn dangling(self, storage: &Storage<T>) -> ! {
+        let index = self.index.to_bits();
+
+        #[cfg(debug_assertions)]
+        {
+            let owner = match storage.graveyard.get(&self.index.slot()) {
+                Some(&(removed, owner)) if removed == self.index => {
+                    format!("entity 0x{:x}", owner.raw().to_bits())
+                }
+                _ => "an unknown entity".to_string(),
+            };
+
+            let origin = match self.origin {
+                Some(origin) => origin.to_string(),
+                None => "an unknown location".to_string(),
+            };
+
+            panic!(
+                "dereferenced a dangling `Obj<{}>` (0x{index:x}) whose component belonged to \
+                 {owner}; the handle was created at {origin}",
+                type_name::<T>(),
+            );
+        }
+
+        #[cfg(not(debug_assertions))]
+        {
+            let _ = storage;
+
+            panic!(
+                "dereferenced a dangling `Obj<{}>` (0x{index:x})",
+                type_name::<T>(),
+            );
+        }
+    }
 }
 
 impl<'i, 'o, T: Component> DerefCx<'i, 'o> for Obj<T> {
     type ContextRef = AccessCompRef<'o, T>;
     type TargetCx = T;
 
+    #[track_caller]
     fn deref_cx(&'i self, cx: Bundle<Self::ContextRef>) -> &'o Self::TargetCx {
-        &<T::Arena>::fetch(pack!(cx)).arena[self.index].1
+        let storage = <T::Arena>::fetch(pack!(cx));
+
+        match storage.get(self.index) {
+            Some(slot) => &slot.value,
+            None => self.dangling(storage),
+        }
     }
 }
 
 impl<'i, 'o, T: Component> DerefCxMut<'i, 'o> for Obj<T> {
     type ContextMut = AccessCompMut<'o, T>;
 
+    #[track_caller]
     fn deref_cx_mut(&'i mut self, cx: Bundle<Self::ContextMut>) -> &'o mut Self::TargetCx {
-        &mut <T::Arena>::fetch_mut(pack!(cx)).arena[self.index].1
+        let tick = unpack!(cx => &WORLD).change_tick();
+        let storage = <T::Arena>::fetch_mut(pack!(cx));
+
+        if !storage.contains(self.index) {
+            self.dangling(storage);
+        }
+
+        let slot = storage.get_mut(self.index).unwrap();
+        slot.changed_tick = tick;
+        &mut slot.value
+    }
+}
+
+// === WeakObj === //
+
+/// A handle to a component of type `T` which may have been removed. Unlike [`Obj`], this must be
+/// resolved through [`WeakObj::get`] before being dereferenced.
+#[derive_where(Copy, Clone, Hash, Eq, PartialEq)]
+pub struct WeakObj<T: Component> {
+    obj: Obj<T>,
+}
+
+impl<T: Component> fmt::Debug for WeakObj<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("WeakObj")
+            .field(&format_args!("0x{:x}", self.obj.index.to_bits()))
+            .finish()
+    }
+}
+
+impl<T: Component> Default for WeakObj<T> {
+    fn default() -> Self {
+        Self::DANGLING
+    }
+}
+
+impl<T: Component> From<Obj<T>> for WeakObj<T> {
+    fn from(obj: Obj<T>) -> Self {
+        obj.downgrade()
+    }
+}
+
+impl<T: Component> WeakObj<T> {
+    pub const DANGLING: WeakObj<T> = WeakObj { obj: Obj::DANGLING };
+
+    pub fn is_alive(self, cx: Bundle<&AccessComp<T>>) -> bool {
+        Obj::is_alive(self.obj, pack!(cx))
+    }
+
+    /// Returns the strong handle if the component still exists.
+    pub fn get(self, cx: Bundle<&AccessComp<T>>) -> Option<Obj<T>> {
+        self.is_alive(pack!(cx)).then_some(self.obj)
+    }
+
+    /// Returns the owner of the component if it still exists.
+    pub fn entity(self, cx: Bundle<&AccessComp<T>>) -> Option<Entity> {
+        <T::Arena>::fetch(pack!(@env, cx))
+            .get(self.obj.index)
+            .map(|slot| slot.owner)
+    }
+}
+
+// === Tests === //
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use crate::{
+        archetype::ComponentId, bind, component, entity::Component, Entity, Obj, Query, Resource,
+        SubtreeQuery, WeakObj, World,
+    };
+
+    #[derive(Debug)]
+    pub struct Tile(u32);
+
+    #[derive(Debug)]
+    pub struct Solid;
+
+    #[derive(Debug)]
+    pub struct Cell(u32);
+
+    component!(Tile, Solid, Cell { storage: Sparse });
+
+    thread_local! {
+        static HOOK_LOG: RefCell<Vec<&'static str>> = const { RefCell::new(Vec::new()) };
+    }
+
+    fn log_hook(event: &'static str) {
+        HOOK_LOG.with_borrow_mut(|log| log.push(event));
+    }
+
+    #[derive(Debug)]
+    pub struct Early;
+
+    #[derive(Debug)]
+    pub struct Late;
+
+    component!(
+        Early {
+            hook_order: -1,
+            on_add: |_world, _early| log_hook("add early"),
+            on_remove: |_world, _early| log_hook("remove early"),
+        },
+        Late {
+            hook_order: 1,
+            on_add: |_world, _late| log_hook("add late"),
+            on_remove: |_world, _late| log_hook("remove late"),
+        },
+    );
+
+    #[test]
+    fn destroy_reshaped_entity() {
+        let mut world = World::new();
+        bind!(world);
+
+        Entity::new(Entity::root()).with(Tile(0)).with(Solid);
+        let floor = Entity::new(Entity::root()).with(Tile(1));
+        Entity::new(Entity::root()).with(Tile(2));
+        Entity::flush(|_world| {});
+
+        // The entity must be removed from the archetype it had as of the last flush rather than
+        // from the one it was reshaped into.
+        floor.add(Solid);
+        floor.destroy_now();
+        Entity::flush(|_world| {});
+
+        let mut walls = Vec::new();
+
+        for (tile, _solid) in Query::<(Obj<Tile>, Obj<Solid>)>::new() {
+            walls.push(tile.0);
+        }
+
+        assert_eq!(walls, [0]);
+        assert_eq!(Query::<Obj<Tile>>::new().count(), 2);
+    }
+
+    #[test]
+    fn batch_spawn_and_destroy() {
+        let mut world = World::new();
+        bind!(world);
+
+        let level = Entity::new(Entity::root());
+        let tiles = Entity::spawn_batch(level, (0..100).map(|i| (Tile(i), Solid)));
+        Entity::flush(|_world| {});
+
+        assert_eq!(level.children().len(), 100);
+        assert_eq!(tiles[42].get::<Tile>().0, 42);
+        assert_eq!(Query::<(Obj<Tile>, Obj<Solid>)>::new().count(), 100);
+
+        Entity::destroy_batch_now([level]);
+        Entity::flush(|_world| {});
+
+        for tile in tiles {
+            assert!(!tile.is_alive());
+        }
+
+        assert!(!level.is_alive());
+        assert_eq!(Query::<Obj<Tile>>::new().count(), 0);
+    }
+
+    fn solids_under(root: Entity) -> Vec<Entity> {
+        root.descendants_with([ComponentId::of::<Solid>()])
+            .iter()
+            .collect()
+    }
+
+    #[test]
+    fn ancestors_and_descendants() {
+        let mut world = World::new();
+        bind!(world);
+
+        let level = Entity::new(Entity::root());
+        let wall = Entity::new(level).with(Tile(0)).with(Solid);
+        let floor = Entity::new(level).with(Tile(1));
+        let crack = Entity::new(floor).with(Tile(2)).with(Solid);
+        Entity::flush(|_world| {});
+
+        assert_eq!(
+            crack.ancestors().collect::<Vec<_>>(),
+            [floor, level, Entity::root()]
+        );
+        assert_eq!(
+            level.descendants_with([]).iter().collect::<Vec<_>>(),
+            [level, wall, floor, crack]
+        );
+        assert_eq!(solids_under(level), [wall, crack]);
+        assert_eq!(solids_under(floor), [crack]);
+
+        let mut tiles = Vec::new();
+
+        for (tile, _solid) in SubtreeQuery::<(Obj<Tile>, Obj<Solid>)>::new(level) {
+            tiles.push(tile.0);
+        }
+
+        assert_eq!(tiles, [0, 2]);
+    }
+
+    #[test]
+    fn hierarchy_cache_invalidation() {
+        let mut world = World::new();
+        bind!(world);
+
+        let level = Entity::new(Entity::root());
+        let wall = Entity::new(level).with(Solid);
+        let floor = Entity::new(level);
+        let crack = Entity::new(floor).with(Solid);
+        Entity::flush(|_world| {});
+
+        assert_eq!(solids_under(level), [wall, crack]);
+        assert_eq!(solids_under(floor), [crack]);
+
+        // Cached results only observe a reparent once it has been flushed.
+        crack.set_parent(Some(Entity::root()));
+        assert_eq!(solids_under(level), [wall, crack]);
+
+        Entity::flush(|_world| {});
+        assert_eq!(solids_under(level), [wall]);
+        assert!(solids_under(floor).is_empty());
+
+        crack.set_parent(Some(floor));
+        floor.add(Solid);
+        Entity::flush(|_world| {});
+        assert_eq!(solids_under(level), [wall, floor, crack]);
+
+        // Destroyed entities are dropped from the subtrees of their old ancestors.
+        wall.destroy_now();
+        Entity::flush(|_world| {});
+        assert_eq!(solids_under(level), [floor, crack]);
+
+        crack.destroy();
+        Entity::flush(|_world| {});
+        assert_eq!(solids_under(level), [floor]);
+        assert_eq!(solids_under(floor), [floor]);
+    }
+
+    #[test]
+    fn storage_keeps_handles_across_removal() {
+        let mut world = World::new();
+        bind!(world);
+
+        let mut cells = Vec::new();
+
+        for i in 0..4 {
+            cells.push(Entity::new(Entity::root()).add(Cell(i)));
+        }
+
+        // Removing the first component moves the last one into its place.
+        cells[0].entity().remove_now::<Cell>();
+
+        for &cell in &cells[1..] {
+            assert_eq!(cell.entity().get::<Cell>(), cell);
+        }
+
+        assert_eq!(cells[3].0, 3);
+        assert_eq!(<<Cell as Component>::Arena>::fetch().len(), 3);
+
+        // The freed slots are reused by the next insertion under a new generation.
+        let entity = cells[0].entity();
+        let reused = entity.add(Cell(4));
+
+        assert_eq!(Obj::raw(reused).slot(), Obj::raw(cells[0]).slot());
+        assert_ne!(reused, cells[0]);
+        assert!(!Obj::is_alive(cells[0]));
+        assert_eq!(reused.0, 4);
+    }
+
+    #[test]
+    fn storage_rejects_generation_mismatch() {
+        let mut world = World::new();
+        bind!(world);
+
+        let dead = Entity::new(Entity::root()).with(Cell(0));
+        dead.destroy_now();
+
+        // The new entity reuses the slot of the dead one but must not inherit its component.
+        let reused = Entity::new(Entity::root());
+        assert_eq!(reused.raw().slot(), dead.raw().slot());
+        assert!(reused.try_get::<Cell>().is_none());
+
+        reused.add(Cell(1));
+        assert!(dead.try_get::<Cell>().is_none());
+        assert_eq!(reused.get::<Cell>().0, 1);
+    }
+
+    #[test]
+    fn weak_obj_resolves_to_none() {
+        let mut world = World::new();
+        bind!(world);
+
+        let tile = Entity::new(Entity::root()).add(Tile(1));
+        let weak = WeakObj::from(tile);
+        assert_eq!(weak.get(), Some(tile));
+        assert_eq!(weak.entity(), Some(tile.entity()));
+
+        tile.entity().remove_now::<Tile>();
+        assert_eq!(weak.get(), None);
+        assert_eq!(weak.entity(), None);
+        assert_eq!(WeakObj::<Tile>::default().get(), None);
+    }
+
+    #[test]
+    fn hooks_run_in_order() {
+        let mut world = World::new();
+        bind!(world);
+
+        let mut entities = Vec::new();
+
+        for _ in 0..3 {
+            entities.push(Entity::new(Entity::root()).with(Late).with(Early));
+        }
+
+        Entity::flush(|_world| {});
+
+        for entity in entities {
+            entity.destroy();
+        }
+
+        Entity::flush(|_world| {});
+
+        assert_eq!(
+            HOOK_LOG.take(),
+            [
+                ["add early"; 3].as_slice(),
+                &["add late"; 3],
+                &["remove early"; 3],
+                &["remove late"; 3],
+            ]
+            .concat(),
+        );
     }
 }
@@ -0,0 +1,259 @@
+use std::{
+    any::{type_name, Any, TypeId},
+    collections::VecDeque,
+    fmt,
+};
+
+use hg_utils::hash::FxHashMap;
+
+use crate::{resource, Resource};
+
+// === Event === //
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Retention {
+    /// Events are dropped once the given number of frames, including the one in which they were
+    /// sent, have ended, regardless of whether every reader has seen them. Readers which fall
+    /// further behind than this silently miss events.
+    Frames(u32),
+
+    /// Events are dropped once every reader has read them. Events sent while the type has no
+    /// readers are dropped at the end of the frame so readers should be added ahead of time
+    /// through [`EventBus::add_reader`].
+    UntilConsumed,
+}
+
+pub trait Event: 'static + Sized + fmt::Debug {
+    /// Set through the `retention` option of the `event!` macro.
+    const RETENTION: Retention = Retention::Frames(1);
+}
+
+/// Defines one or more event types, each optionally followed by a braced `retention: <Retention>`
+/// option. By default, events are only retained until the end of the frame in which they were
+/// sent.
+#[macro_export]
+macro_rules! event {
+    ($($ty:ty $({ retention: $retention:expr $(,)? })?),*$(,)?) => {$(
+        impl $crate::event::Event for $ty {
+            $(const RETENTION: $crate::event::Retention = $retention;)?
+        }
+    )*};
+}
+
+pub use event;
+
+// === EventBus === //
+
+/// Buffers events by type so that any number of systems can read them at their own pace.
+///
+/// Readers are identified by name, typically that of the system doing the reading, and each
+/// keeps its own cursor into the buffer. A frame ends at the end of every `Entity::flush`, at
+/// which point expired events are dropped according to their type's [`Retention`].
+#[derive(Debug, Default)]
+pub struct EventBus {
+    frame: u64,
+    queues: FxHashMap<TypeId, Box<dyn ErasedEventQueue>>,
+}
+
+resource!(EventBus);
+
+impl EventBus {
+    fn queue<E: Event>(&mut self) -> &mut EventQueue<E> {
+        self.queues
+            .entry(TypeId::of::<E>())
+            .or_insert_with(|| Box::new(EventQueue::<E>::default()))
+            .as_any_mut()
+            .downcast_mut()
+            .unwrap()
+    }
+
+    pub fn send<E: Event>(&mut self, event: E) {
+        let frame = self.frame;
+        self.queue::<E>().events.push_back((frame, event));
+    }
+
+    /// Registers a reader which will see every event sent from now on. Does nothing if the reader
+    /// already exists.
+    pub fn add_reader<E: Event>(&mut self, reader: &'static str) {
+        let queue = self.queue::<E>();
+        let end = queue.end();
+        queue.readers.entry(reader).or_insert(end);
+    }
+
+    pub fn remove_reader<E: Event>(&mut self, reader: &'static str) {
+        self.queue::<E>().readers.remove(reader);
+    }
+
+    /// Returns every event the given reader has yet to see and marks them as read. Readers which
+    /// have not been added beforehand start at the oldest retained event.
+    pub fn read<E: Event + Clone>(&mut self, reader: &'static str) -> Vec<E> {
+        let queue = self.queue::<E>();
+        let head = queue.head;
+        let end = queue.end();
+
+        let cursor = queue.readers.entry(reader).or_insert(head);
+        let start = (*cursor).max(head);
+        *cursor = end;
+
+        queue
+            .events
+            .range((start - head) as usize..)
+            .map(|(_, event)| event.clone())
+            .collect()
+    }
+
+    /// Counts the events the given reader has yet to see.
+    pub fn pending<E: Event>(&mut self, reader: &'static str) -> usize {
+        let queue = self.queue::<E>();
+        let start = queue
+            .readers
+            .get(reader)
+            .map_or(queue.head, |&v| v.max(queue.head));
+
+        (queue.end() - start) as usize
+    }
+
+    /// Ends the current frame, dropping expired events. This is called by `Entity::flush`.
+    pub fn end_frame() {
+        Self::fetch_mut().advance_frame();
+    }
+
+    fn advance_frame(&mut self) {
+        self.frame += 1;
+
+        for queue in self.queues.values_mut() {
+            queue.end_frame(self.frame);
+        }
+    }
+}
+
+impl fmt::Display for EventBus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for queue in self.queues.values() {
+            queue.fmt_summary(f)?;
+        }
+
+        Ok(())
+    }
+}
+
+// === EventQueue === //
+
+trait ErasedEventQueue: fmt::Debug {
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+
+    fn end_frame(&mut self, frame: u64);
+
+    fn fmt_summary(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result;
+}
+
+#[derive(Debug)]
+struct EventQueue<E> {
+    /// Retained events alongside the frame in which they were sent, oldest first.
+    events: VecDeque<(u64, E)>,
+
+    /// The sequence number of the first event in `events`.
+    head: u64,
+
+    /// The sequence number of the next event each reader will see.
+    readers: FxHashMap<&'static str, u64>,
+}
+
+impl<E> Default for EventQueue<E> {
+    fn default() -> Self {
+        Self {
+            events: VecDeque::new(),
+            head: 0,
+            readers: FxHashMap::default(),
+        }
+    }
+}
+
+impl<E> EventQueue<E> {
+    fn end(&self) -> u64 {
+        self.head + self.events.len() as u64
+    }
+}
+
+impl<E: Event> ErasedEventQueue for EventQueue<E> {
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn end_frame(&mut self, frame: u64) {
+        let keep_from = match E::RETENTION {
+            Retention::Frames(frames) => {
+                let frames = u64::from(frames.max(1));
+
+                self.events
+                    .iter()
+                    .position(|&(sent, _)| frame - sent < frames)
+                    .map_or(self.end(), |idx| self.head + idx as u64)
+            }
+            Retention::UntilConsumed => self.readers.values().copied().min().unwrap_or(self.end()),
+        };
+
+        while self.head < keep_from {
+            self.events.pop_front();
+            self.head += 1;
+        }
+    }
+
+    fn fmt_summary(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{}: {} retained, {} reader(s)",
+            type_name::<E>(),
+            self.events.len(),
+            self.readers.len(),
+        )
+    }
+}
+
+// === Tests === //
+
+#[cfg(test)]
+mod tests {
+    use super::{event, EventBus, Retention};
+
+    #[derive(Debug, Clone, PartialEq)]
+    struct Ping(u32);
+
+    #[derive(Debug, Clone, PartialEq)]
+    struct Joined(u32);
+
+    event!(
+        Ping { retention: Retention::Frames(2) },
+        Joined { retention: Retention::UntilConsumed },
+    );
+
+    #[test]
+    fn retention_and_cursors() {
+        let mut bus = EventBus::default();
+        bus.add_reader::<Joined>("fast");
+        bus.add_reader::<Joined>("slow");
+
+        bus.send(Ping(1));
+        bus.send(Joined(1));
+        assert_eq!(bus.read::<Ping>("a"), [Ping(1)]);
+        assert_eq!(bus.read::<Joined>("fast"), [Joined(1)]);
+
+        // Retained events survive as many frames as requested.
+        bus.advance_frame();
+        bus.send(Ping(2));
+        assert_eq!(bus.read::<Ping>("a"), [Ping(2)]);
+        assert_eq!(bus.read::<Ping>("b"), [Ping(1), Ping(2)]);
+
+        bus.advance_frame();
+        assert_eq!(bus.read::<Ping>("c"), [Ping(2)]);
+
+        // Unconsumed events are kept for slow readers.
+        bus.send(Joined(2));
+        assert_eq!(bus.pending::<Joined>("slow"), 2);
+        assert_eq!(bus.read::<Joined>("slow"), [Joined(1), Joined(2)]);
+        assert_eq!(bus.read::<Joined>("fast"), [Joined(2)]);
+
+        bus.advance_frame();
+        assert!(bus.read::<Joined>("late").is_empty());
+    }
+}
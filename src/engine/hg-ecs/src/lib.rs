@@ -1,17 +1,102 @@
 #![feature(context_injection)]
 
+// Must be defined before the modules which implement traits for tuples.
+macro_rules! impl_tuples {
+	// Internal
+	(
+		$target:path : []
+		$(| [
+			$({$($pre:tt)*})*
+		])?
+	) => { /* terminal recursion case */ };
+	(
+		$target:path : [
+			{$($next:tt)*}
+			// Remaining invocations
+			$($rest:tt)*
+		] $(| [
+			// Accumulated arguments
+			$({$($pre:tt)*})*
+		])?
+	) => {
+		$target!(
+			$($($($pre)*,)*)?
+			$($next)*
+		);
+		impl_tuples!(
+			$target : [
+				$($rest)*
+			] | [
+				$($({$($pre)*})*)?
+				{$($next)*}
+			]
+		);
+	};
+
+	// Public
+	($target:path; no_unit) => {
+		impl_tuples!(
+			$target : [
+				{A: 0}
+				{B: 1}
+				{C: 2}
+				{D: 3}
+				{E: 4}
+				{F: 5}
+				{G: 6}
+				{H: 7}
+				{I: 8}
+				{J: 9}
+				{K: 10}
+				{L: 11}
+			]
+		);
+	};
+	($target:path; only_full) => {
+		$target!(
+			A:0,
+			B:1,
+			C:2,
+			D:3,
+			E:4,
+			F:5,
+			G:6,
+			H:7,
+			I:8,
+			J:9,
+			K:10,
+			L:11
+		);
+	};
+	($target:path) => {
+		$target!();
+		impl_tuples!($target; no_unit);
+	};
+}
+
 pub mod archetype;
+pub mod command;
+pub mod digest;
 pub mod entity;
+pub mod event;
+pub mod name;
+pub mod parallel;
+pub mod profile;
 pub mod query;
+pub mod reflect;
+pub mod rollback;
+pub mod schedule;
 pub mod signal;
+pub mod snapshot;
+pub mod stats;
 pub mod world;
 
 pub use thunderdome::Index;
 
 pub mod prelude {
     pub use crate::{
-        entity::{component, AccessComp, AccessCompMut, AccessCompRef, Entity, Obj},
-        query::Query,
+        entity::{component, AccessComp, AccessCompMut, AccessCompRef, Entity, Obj, WeakObj},
+        query::{Added, Changed, Query, SubtreeQuery, Without},
         world::{bind, resource, AccessRes, AccessResMut, AccessResRef, Resource, World, WORLD},
     };
 }
@@ -0,0 +1,289 @@
+use std::{
+    borrow::Cow,
+    collections::BTreeSet,
+    context::{pack, Bundle},
+    fmt,
+};
+
+use serde::{Deserialize, Serialize};
+use thunderdome::Index;
+
+use crate::{
+    archetype::ComponentId,
+    component,
+    entity::{AccessCompRef, Component, EntityStore, Storage},
+    world::ImmutableWorld,
+    Entity, Resource,
+};
+
+// === Components === //
+
+/// A name through which an entity can be found among its siblings using [`Entity::find`]. Names
+/// should be unique among siblings and must not contain `/`.
+#[derive(Debug, Clone, Hash, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Name(Cow<'static, str>);
+
+/// A set of strings through which entities can be found using [`Entity::descendants_tagged`].
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Tags(BTreeSet<Cow<'static, str>>);
+
+component!(
+    Name {
+        serde: "name",
+        reflect
+    },
+    Tags {
+        serde: "tags",
+        reflect
+    }
+);
+
+impl Name {
+    pub fn new(name: impl Into<Cow<'static, str>>) -> Self {
+        let name = name.into();
+        assert!(!name.contains('/'), "entity name {name:?} contains a `/`");
+
+        Self(name)
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for Name {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl Tags {
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn has(&self, tag: &str) -> bool {
+        self.0.contains(tag)
+    }
+
+    /// Adds a tag, returning whether it was newly added.
+    pub fn insert(&mut self, tag: impl Into<Cow<'static, str>>) -> bool {
+        self.0.insert(tag.into())
+    }
+
+    /// Removes a tag, returning whether it was present.
+    pub fn remove(&mut self, tag: &str) -> bool {
+        self.0.remove(tag)
+    }
+
+    /// Iterates over the tags in lexicographic order.
+    pub fn iter(&self) -> impl Iterator<Item = &str> + '_ {
+        self.0.iter().map(|tag| &**tag)
+    }
+}
+
+// === Entity === //
+
+impl Entity {
+    pub fn with_name(self, name: impl Into<Cow<'static, str>>) -> Self {
+        self.add(Name::new(name));
+        self
+    }
+
+    pub fn name<'a>(self, cx: Bundle<AccessCompRef<'a, Name>>) -> Option<&'a str> {
+        name_of(<<Name as Component>::Arena>::fetch(pack!(cx)), self)
+    }
+
+    pub fn with_tag(self, tag: impl Into<Cow<'static, str>>) -> Self {
+        self.add_tag(tag);
+        self
+    }
+
+    pub fn add_tag(self, tag: impl Into<Cow<'static, str>>) {
+        match self.try_get::<Tags>() {
+            Some(mut tags) => {
+                tags.insert(tag);
+            }
+            None => {
+                let mut tags = Tags::default();
+                tags.insert(tag);
+                self.add(tags);
+            }
+        }
+    }
+
+    /// Removes a tag from the entity, returning whether it was present.
+    pub fn remove_tag(self, tag: &str) -> bool {
+        match self.try_get::<Tags>() {
+            Some(mut tags) => tags.remove(tag),
+            None => false,
+        }
+    }
+
+    pub fn has_tag(self, tag: &str) -> bool {
+        match self.try_get::<Tags>() {
+            Some(tags) => tags.has(tag),
+            None => false,
+        }
+    }
+
+    /// Collects the entities in the subtree rooted at this entity, including the entity itself,
+    /// which have the given tag. Entities are listed in pre-order.
+    pub fn descendants_tagged(self, tag: &str) -> Vec<Entity> {
+        let mut tagged = Vec::new();
+
+        // The cached subtree may list entities which have lost their tags since the last flush.
+        for entity in &self.descendants_with([ComponentId::of::<Tags>()]) {
+            if entity.has_tag(tag) {
+                tagged.push(entity);
+            }
+        }
+
+        tagged
+    }
+
+    /// Finds the first child with the given name.
+    pub fn child_named(self, name: &str) -> Option<Entity> {
+        for child in &self.children() {
+            if child.name() == Some(name) {
+                return Some(child);
+            }
+        }
+
+        None
+    }
+
+    /// Finds an entity by its `/`-separated path relative to this entity. Paths starting with a `/`
+    /// are relative to the root instead. Besides names, segments can be `..` to refer to the
+    /// parent or the raw ID of an unnamed child as formatted by [`Entity::path`].
+    pub fn find(self, path: &str) -> Option<Entity> {
+        let (mut curr, path) = match path.strip_prefix('/') {
+            Some(path) => (Entity::root(), path),
+            None => (self, path),
+        };
+
+        for segment in path.split('/') {
+            curr = match segment {
+                "" | "." => continue,
+                ".." => curr.parent()?,
+                _ => match curr.child_named(segment) {
+                    Some(child) => child,
+                    None => curr.child_with_raw_id(segment)?,
+                },
+            };
+        }
+
+        Some(curr)
+    }
+
+    fn child_with_raw_id(self, segment: &str) -> Option<Entity> {
+        let bits = u64::from_str_radix(segment.strip_prefix("0x")?, 16).ok()?;
+        let child = Entity::from_raw(Index::from_bits(bits)?);
+
+        let store = EntityStore::fetch();
+        (store.is_alive(child) && store.parent(child) == Some(self)).then_some(child)
+    }
+
+    /// Formats the absolute path of this entity, e.g. `/level/tilemap/foreground`. Unnamed entities
+    /// are written as their raw ID. This is also how `Entity` is displayed while a world is bound
+    /// through [`WorldFmt`](crate::world::WorldFmt).
+    pub fn path(self) -> String {
+        let mut path = String::new();
+        write_path(
+            EntityStore::fetch(),
+            <<Name as Component>::Arena>::fetch(),
+            self,
+            &mut path,
+        )
+        .unwrap();
+
+        path
+    }
+}
+
+impl fmt::Display for Entity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        ImmutableWorld::try_use_tls(|world| {
+            let Some(world) = world.filter(|world| world.read::<EntityStore>().is_alive(*self))
+            else {
+                return write!(f, "0x{:x}", self.raw().to_bits());
+            };
+
+            write_path(
+                world.read::<EntityStore>(),
+                world.read::<<Name as Component>::Arena>(),
+                *self,
+                f,
+            )
+        })
+    }
+}
+
+pub(crate) fn name_of(names: &Storage<Name>, entity: Entity) -> Option<&str> {
+    Some(names.get_by_entity(entity)?.value.as_str())
+}
+
+fn write_path(
+    store: &EntityStore,
+    names: &Storage<Name>,
+    entity: Entity,
+    f: &mut impl fmt::Write,
+) -> fmt::Result {
+    let mut segments = Vec::new();
+    let mut curr = entity;
+
+    while let Some(parent) = store.parent(curr) {
+        segments.push(curr);
+        curr = parent;
+    }
+
+    if segments.is_empty() {
+        return f.write_char('/');
+    }
+
+    for &segment in segments.iter().rev() {
+        match name_of(names, segment) {
+            Some(name) => write!(f, "/{name}")?,
+            None => write!(f, "/0x{:x}", segment.raw().to_bits())?,
+        }
+    }
+
+    Ok(())
+}
+
+// === Tests === //
+
+#[cfg(test)]
+mod tests {
+    use crate::{bind, Entity, World};
+
+    #[test]
+    fn names_tags_and_paths() {
+        let mut world = World::new();
+        bind!(world);
+
+        let level = Entity::new(Entity::root()).with_name("level");
+        let tilemap = Entity::new(level).with_name("tilemap").with_tag("solid");
+        let foreground = Entity::new(tilemap)
+            .with_name("foreground")
+            .with_tag("solid");
+        let unnamed = Entity::new(tilemap);
+
+        assert_eq!(
+            Entity::root().find("level/tilemap/foreground"),
+            Some(foreground)
+        );
+        assert_eq!(foreground.find("../.."), Some(level));
+        assert_eq!(level.find("tilemap/background"), None);
+        assert_eq!(foreground.path(), "/level/tilemap/foreground");
+        assert_eq!(Entity::root().find(&unnamed.path()), Some(unnamed));
+
+        assert_eq!(level.descendants_tagged("solid"), [tilemap, foreground]);
+        assert!(tilemap.remove_tag("solid"));
+        assert!(!tilemap.has_tag("solid"));
+    }
+}
@@ -0,0 +1,351 @@
+use std::{
+    any::{type_name, TypeId},
+    fmt,
+    sync::atomic::{AtomicUsize, Ordering::*},
+    thread,
+    time::{Duration, Instant},
+};
+
+use hg_utils::hash::FxHashMap;
+
+use crate::{
+    entity::{Component, Storage},
+    Entity, Resource, World,
+};
+
+// === AccessSet === //
+
+#[derive(Debug, Copy, Clone)]
+struct Access {
+    name: &'static str,
+    write: bool,
+    fetch: fn(&World) -> *mut (),
+}
+
+/// The resources and component storages a shared system may access through its [`SharedWorld`].
+///
+/// Resources are keyed by the same context item as their [`AccessToken`](crate::world::AccessToken)
+/// so a component storage and its `AccessComp` refer to the same entry.
+#[derive(Clone, Default)]
+pub struct AccessSet {
+    accesses: FxHashMap<TypeId, Access>,
+}
+
+impl fmt::Debug for AccessSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map()
+            .entries(
+                self.accesses
+                    .values()
+                    .map(|access| (access.name, if access.write { "write" } else { "read" })),
+            )
+            .finish()
+    }
+}
+
+impl AccessSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn with<R: Resource>(mut self, write: bool) -> Self {
+        self.accesses
+            .entry(TypeId::of::<R::Cx>())
+            .or_insert(Access {
+                name: type_name::<R>(),
+                write: false,
+                fetch: |world| world.single::<R>().cast(),
+            })
+            .write |= write;
+
+        self
+    }
+
+    /// Declares shared access to a resource. Several systems reading the same resource may run at
+    /// once, hence the `Sync` bound.
+    pub fn read<R: Resource + Sync>(self) -> Self {
+        self.with::<R>(false)
+    }
+
+    /// Declares exclusive access to a resource. Systems writing to a resource never run alongside
+    /// any other system accessing it but may still run on a worker thread, hence the `Send` bound.
+    pub fn write<R: Resource + Send>(self) -> Self {
+        self.with::<R>(true)
+    }
+
+    /// Declares shared access to the storage of component `T`.
+    pub fn read_comp<T: Component>(self) -> Self
+    where
+        T::Arena: Sync,
+    {
+        self.read::<T::Arena>()
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.accesses.values().all(|access| !access.write)
+    }
+
+    /// Determines whether two systems with these access sets could observe one another's effects
+    /// if they were run at the same time.
+    pub fn conflicts_with(&self, other: &AccessSet) -> bool {
+        self.accesses.iter().any(|(id, access)| {
+            other
+                .accesses
+                .get(id)
+                .is_some_and(|other| access.write || other.write)
+        })
+    }
+}
+
+// === SharedWorld === //
+
+#[derive(Debug, Copy, Clone)]
+struct SharedPtr(*mut ());
+
+// Safety: `run_batch` only hands these out to systems whose access sets don't conflict and the
+// `AccessSet` constructors ensure that the pointees are `Sync` or `Send` as appropriate.
+unsafe impl Send for SharedPtr {}
+unsafe impl Sync for SharedPtr {}
+
+type Deferred = Box<dyn FnOnce(&mut World) + Send>;
+
+/// The view of the world given to a system running on a worker thread.
+///
+/// Implicit context is not available to shared systems, even those run on the main thread, so
+/// every resource must be fetched through this view, which panics if the system did not declare the
+/// appropriate access in its [`AccessSet`]. Structural changes and other effects can be deferred
+/// until the end of the batch through [`SharedWorld::defer`].
+pub struct SharedWorld<'a> {
+    system: &'static str,
+    access: &'a AccessSet,
+    ptrs: &'a FxHashMap<TypeId, SharedPtr>,
+    deferred: Vec<Deferred>,
+}
+
+impl fmt::Debug for SharedWorld<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SharedWorld")
+            .field("system", &self.system)
+            .field("access", &self.access)
+            .finish_non_exhaustive()
+    }
+}
+
+impl SharedWorld<'_> {
+    fn lookup<R: Resource>(&self, write: bool) -> *mut R {
+        let id = TypeId::of::<R::Cx>();
+
+        assert!(
+            self.access
+                .accesses
+                .get(&id)
+                .is_some_and(|access| access.write || !write),
+            "system `{}` did not declare {} access to `{}`",
+            self.system,
+            if write { "write" } else { "read" },
+            type_name::<R>(),
+        );
+
+        self.ptrs[&id].0.cast()
+    }
+
+    pub fn read<R: Resource>(&self) -> &R {
+        unsafe { &*self.lookup::<R>(false) }
+    }
+
+    pub fn write<R: Resource>(&mut self) -> &mut R {
+        unsafe { &mut *self.lookup::<R>(true) }
+    }
+
+    pub fn storage<T: Component>(&self) -> &Storage<T> {
+        self.read::<T::Arena>()
+    }
+
+    pub fn get<T: Component>(&self, entity: Entity) -> Option<&T> {
+        self.storage::<T>()
+            .get_by_entity(entity)
+            .map(|slot| &slot.value)
+    }
+
+    /// Iterates over every component of type `T` alongside its owner, in no particular order.
+    pub fn iter<T: Component>(&self) -> impl Iterator<Item = (Entity, &T)> + '_ {
+        self.storage::<T>()
+            .iter()
+            .map(|slot| (slot.owner, &slot.value))
+    }
+
+    /// Queues a closure to be run on the main thread once every system in the batch has finished.
+    /// Deferred closures are run in schedule order.
+    pub fn defer(&mut self, f: impl 'static + Send + FnOnce(&mut World)) {
+        self.deferred.push(Box::new(f));
+    }
+}
+
+// === Executor === //
+
+#[derive(Debug, Copy, Clone)]
+pub(crate) struct SharedSystem<'a> {
+    pub name: &'static str,
+    pub access: &'a AccessSet,
+    pub run: fn(&mut SharedWorld<'_>),
+}
+
+/// Runs a batch of systems with mutually non-conflicting access sets on scoped worker threads and
+/// then applies their deferred closures. Returns the time each system took to run.
+pub(crate) fn run_batch(world: &mut World, systems: &[SharedSystem<'_>]) -> Vec<Duration> {
+    // Resources are lazily created so this must happen on the main thread.
+    let mut ptrs = FxHashMap::default();
+
+    for system in systems {
+        for (&id, access) in &system.access.accesses {
+            ptrs.entry(id)
+                .or_insert_with(|| SharedPtr((access.fetch)(world)));
+        }
+    }
+
+    let run = |system: &SharedSystem<'_>| {
+        let _span = tracing::debug_span!("system", name = system.name).entered();
+        let start = Instant::now();
+
+        let mut shared = SharedWorld {
+            system: system.name,
+            access: system.access,
+            ptrs: &ptrs,
+            deferred: Vec::new(),
+        };
+
+        (system.run)(&mut shared);
+
+        (start.elapsed(), shared.deferred)
+    };
+
+    // Systems running on the main thread would otherwise still be able to use the tokens of the
+    // binding that started the batch.
+    let mut results = world.isolate(|| {
+        if systems.len() == 1 {
+            return vec![run(&systems[0])];
+        }
+
+        let workers = thread::available_parallelism()
+            .map_or(1, |v| v.get())
+            .min(systems.len());
+
+        let next = AtomicUsize::new(0);
+        let mut results = systems.iter().map(|_| None).collect::<Vec<_>>();
+
+        let finished = thread::scope(|s| {
+            let handles = (0..workers)
+                .map(|_| {
+                    s.spawn(|| {
+                        let mut finished = Vec::new();
+
+                        loop {
+                            let idx = next.fetch_add(1, Relaxed);
+
+                            let Some(system) = systems.get(idx) else {
+                                break;
+                            };
+
+                            finished.push((idx, run(system)));
+                        }
+
+                        finished
+                    })
+                })
+                .collect::<Vec<_>>();
+
+            handles
+                .into_iter()
+                .flat_map(|handle| handle.join().unwrap())
+                .collect::<Vec<_>>()
+        });
+
+        for (idx, result) in finished {
+            results[idx] = Some(result);
+        }
+
+        results.into_iter().map(Option::unwrap).collect()
+    });
+
+    for (_, deferred) in &mut results {
+        for f in deferred.drain(..) {
+            f(world);
+        }
+    }
+
+    results.into_iter().map(|(elapsed, _)| elapsed).collect()
+}
+
+// === Tests === //
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        bind, component, resource,
+        schedule::{Schedule, Stage, SystemDef},
+        Entity, Resource as _, World,
+    };
+
+    use super::{AccessSet, SharedWorld};
+
+    #[derive(Debug)]
+    pub struct Health(u32);
+
+    component!(Health);
+
+    #[derive(Debug, Default)]
+    pub struct Total(u32);
+
+    #[derive(Debug, Default)]
+    pub struct Wounded(Vec<Entity>);
+
+    resource!(Total, Wounded);
+
+    fn sum_health(world: &mut SharedWorld<'_>) {
+        let total = world.iter::<Health>().map(|(_, health)| health.0).sum();
+        world.write::<Total>().0 = total;
+    }
+
+    fn find_wounded(world: &mut SharedWorld<'_>) {
+        let wounded = world
+            .iter::<Health>()
+            .filter(|(_, health)| health.0 < 10)
+            .map(|(entity, _)| entity)
+            .collect::<Vec<_>>();
+
+        world.defer(move |world| {
+            bind!(world);
+            Wounded::fetch_mut().0 = wounded;
+        });
+    }
+
+    #[test]
+    fn runs_non_conflicting_systems() {
+        let mut world = World::new();
+        bind!(world);
+
+        let hurt = Entity::new(Entity::root()).with(Health(5));
+        Entity::new(Entity::root()).with(Health(10));
+
+        Schedule::fetch_mut()
+            .add(SystemDef::shared(
+                "sum_health",
+                AccessSet::new().read_comp::<Health>().write::<Total>(),
+                sum_health,
+            ))
+            .add(SystemDef::shared(
+                "find_wounded",
+                AccessSet::new().read_comp::<Health>(),
+                find_wounded,
+            ));
+
+        Schedule::run(Stage::Update);
+
+        assert_eq!(Total::fetch().0, 15);
+        assert_eq!(Wounded::fetch().0, [hurt]);
+
+        let read = AccessSet::new().read::<Total>();
+        assert!(!read.conflicts_with(&read));
+        assert!(read.conflicts_with(&AccessSet::new().write::<Total>()));
+    }
+}
@@ -0,0 +1,268 @@
+use std::{fmt, mem, time::Duration};
+
+use hg_utils::hash::FxHashMap;
+
+use crate::{entity::EntityStore, resource, Resource};
+
+// === TimeHistogram === //
+
+/// A histogram of durations with power-of-two microsecond buckets.
+#[derive(Debug, Clone, Default)]
+pub struct TimeHistogram {
+    buckets: [u64; Self::BUCKETS],
+    count: u64,
+    total: Duration,
+    max: Duration,
+}
+
+impl TimeHistogram {
+    const BUCKETS: usize = 32;
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn bucket_of(time: Duration) -> usize {
+        let micros = time.as_micros().max(1);
+        (micros.ilog2() as usize).min(Self::BUCKETS - 1)
+    }
+
+    fn bucket_upper_bound(bucket: usize) -> Duration {
+        Duration::from_micros(1 << (bucket + 1))
+    }
+
+    pub fn record(&mut self, time: Duration) {
+        self.buckets[Self::bucket_of(time)] += 1;
+        self.count += 1;
+        self.total += time;
+        self.max = self.max.max(time);
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    pub fn total(&self) -> Duration {
+        self.total
+    }
+
+    pub fn max(&self) -> Duration {
+        self.max
+    }
+
+    pub fn mean(&self) -> Duration {
+        if self.count == 0 {
+            return Duration::ZERO;
+        }
+
+        Duration::from_nanos((self.total.as_nanos() / self.count as u128) as u64)
+    }
+
+    /// Returns an upper bound on the duration below which the `percentile` (in the range `0..=1`)
+    /// of samples lie. The bound is never greater than the largest recorded sample.
+    pub fn percentile(&self, percentile: f64) -> Duration {
+        let target = (self.count as f64 * percentile).ceil().max(1.) as u64;
+        let mut seen = 0;
+
+        for (bucket, &count) in self.buckets.iter().enumerate() {
+            seen += count;
+
+            if seen >= target {
+                return Self::bucket_upper_bound(bucket).min(self.max);
+            }
+        }
+
+        self.max
+    }
+
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+}
+
+impl fmt::Display for TimeHistogram {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "n={} mean={:?} p50<={:?} p99<={:?} max={:?}",
+            self.count,
+            self.mean(),
+            self.percentile(0.5),
+            self.percentile(0.99),
+            self.max,
+        )
+    }
+}
+
+// === FrameReport === //
+
+#[derive(Debug, Clone, Default)]
+pub struct FrameReport {
+    /// The number of frames which were completed before this one.
+    pub index: u64,
+
+    /// The wall time of every system run this frame in execution order. Systems in the `Flush`
+    /// stage may appear several times.
+    pub systems: Vec<(&'static str, Duration)>,
+
+    /// The total wall time spent in `Entity::flush`, including the systems it ran.
+    pub flush_time: Duration,
+
+    /// The number of batches of queued operations processed by `Entity::flush`.
+    pub flush_batches: u32,
+
+    /// The number of live entities at the end of the frame.
+    pub entities: usize,
+
+    /// The number of archetypes which have been created.
+    pub archetypes: usize,
+
+    /// The number of archetypes with at least one member as of the last `Entity::flush`.
+    pub populated_archetypes: usize,
+}
+
+impl FrameReport {
+    pub fn system_time(&self) -> Duration {
+        self.systems.iter().map(|&(_, time)| time).sum()
+    }
+}
+
+impl fmt::Display for FrameReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "frame {}: {} entities, {} archetypes ({} populated)",
+            self.index, self.entities, self.archetypes, self.populated_archetypes,
+        )?;
+
+        for (name, time) in &self.systems {
+            writeln!(f, "  {name}: {time:?}")?;
+        }
+
+        writeln!(
+            f,
+            "  Entity::flush: {:?} over {} batch(es)",
+            self.flush_time, self.flush_batches,
+        )
+    }
+}
+
+// === Profiler === //
+
+/// Collects timings of the systems run through `Schedule` and of `Entity::flush`.
+#[derive(Debug, Default)]
+pub struct Profiler {
+    histograms: Vec<(&'static str, TimeHistogram)>,
+    histogram_indices: FxHashMap<&'static str, usize>,
+    flush_histogram: TimeHistogram,
+    curr_frame: FrameReport,
+    last_frame: FrameReport,
+}
+
+resource!(Profiler);
+
+impl Profiler {
+    pub fn record_system(&mut self, name: &'static str, time: Duration) {
+        let idx = *self.histogram_indices.entry(name).or_insert_with(|| {
+            self.histograms.push((name, TimeHistogram::new()));
+            self.histograms.len() - 1
+        });
+
+        self.histograms[idx].1.record(time);
+        self.curr_frame.systems.push((name, time));
+    }
+
+    pub fn record_flush(&mut self, time: Duration, batches: u32) {
+        self.flush_histogram.record(time);
+        self.curr_frame.flush_time += time;
+        self.curr_frame.flush_batches += batches;
+    }
+
+    /// The histograms of every system which has run at least once, in order of first execution.
+    pub fn histograms(&self) -> &[(&'static str, TimeHistogram)] {
+        &self.histograms
+    }
+
+    pub fn flush_histogram(&self) -> &TimeHistogram {
+        &self.flush_histogram
+    }
+
+    /// The report of the last frame completed by `end_frame`.
+    pub fn last_frame(&self) -> &FrameReport {
+        &self.last_frame
+    }
+
+    pub fn clear_histograms(&mut self) {
+        for (_, histogram) in &mut self.histograms {
+            histogram.clear();
+        }
+
+        self.flush_histogram.clear();
+    }
+
+    /// Completes the current frame's report and makes it available through `last_frame`.
+    pub fn end_frame() {
+        let store = EntityStore::fetch();
+        let entities = store.entity_count();
+        let archetypes = store.archetype_count();
+        let populated_archetypes = store.populated_archetype_count();
+
+        let me = Self::fetch_mut();
+        let index = me.curr_frame.index;
+
+        me.last_frame = mem::replace(
+            &mut me.curr_frame,
+            FrameReport {
+                index: index + 1,
+                ..FrameReport::default()
+            },
+        );
+
+        me.last_frame.entities = entities;
+        me.last_frame.archetypes = archetypes;
+        me.last_frame.populated_archetypes = populated_archetypes;
+    }
+}
+
+impl fmt::Display for Profiler {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (name, histogram) in &self.histograms {
+            writeln!(f, "{name}: {histogram}")?;
+        }
+
+        writeln!(f, "Entity::flush: {}", self.flush_histogram)
+    }
+}
+
+// === Tests === //
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::TimeHistogram;
+
+    #[test]
+    fn percentiles_bound_samples() {
+        let mut hist = TimeHistogram::new();
+        assert_eq!(hist.percentile(0.5), Duration::ZERO);
+
+        for (micros, count) in [(3, 90), (100, 9), (5000, 1)] {
+            for _ in 0..count {
+                hist.record(Duration::from_micros(micros));
+            }
+        }
+
+        assert_eq!(hist.count(), 100);
+        assert_eq!(hist.mean(), Duration::from_nanos(61_700));
+
+        // Samples are bucketed by powers of two so bounds are rounded up to the next one...
+        assert_eq!(hist.percentile(0.5), Duration::from_micros(4));
+        assert_eq!(hist.percentile(0.9), Duration::from_micros(4));
+        assert_eq!(hist.percentile(0.95), Duration::from_micros(128));
+        assert_eq!(hist.percentile(0.99), Duration::from_micros(128));
+
+        // ...but never past the largest sample.
+        assert_eq!(hist.percentile(1.), Duration::from_micros(5000));
+    }
+}
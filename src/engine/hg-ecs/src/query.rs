@@ -1,11 +1,12 @@
 use std::{fmt, marker::PhantomData, mem::transmute, ptr::null, rc::Rc, slice, vec};
 
+use derive_where::derive_where;
 use thunderdome::Index;
 
 use crate::{
     archetype::{ArchetypeId, ComponentId},
-    entity::{Component, EntityQueryState, EntityQueueState},
-    Entity, Obj,
+    entity::{Component, EntityQueryState, EntityQueueState, Storage, StorageSlot},
+    Entity, Obj, World, WORLD,
 };
 
 // === Query === //
@@ -22,13 +23,13 @@ impl<R: QueryResult> Query<R> {
     pub fn new() -> Self {
         let query_state = Entity::query_state().clone();
         let archetypes = Entity::archetypes()
-            .archetypes_with_set(R::comp_ids())
+            .archetypes_matching(R::comp_ids(), R::excluded_comp_ids())
             .into_iter();
 
         Self {
             query_state,
             archetypes,
-            state: R::empty_state(),
+            state: R::empty_state(&WORLD),
             leader: R::empty_leader(),
         }
     }
@@ -36,13 +37,16 @@ impl<R: QueryResult> Query<R> {
     pub fn new_with(additional: impl IntoIterator<Item = ComponentId>) -> Self {
         let query_state = Entity::query_state().clone();
         let archetypes = Entity::archetypes()
-            .archetypes_with_set(R::comp_ids().into_iter().chain(additional))
+            .archetypes_matching(
+                R::comp_ids().into_iter().chain(additional),
+                R::excluded_comp_ids(),
+            )
             .into_iter();
 
         Self {
             query_state,
             archetypes,
-            state: R::empty_state(),
+            state: R::empty_state(&WORLD),
             leader: R::empty_leader(),
         }
     }
@@ -54,7 +58,11 @@ impl<R: QueryResult> Iterator for Query<R> {
     fn next(&mut self) -> Option<Self::Item> {
         loop {
             if R::state_has_next(&self.state, &self.leader) {
-                break Some(unsafe { R::next_unchecked_leader(&mut self.state, &mut self.leader) });
+                // Rows rejected by a filter are skipped.
+                match unsafe { R::next_unchecked_leader(&mut self.state, &mut self.leader) } {
+                    Some(row) => break Some(row),
+                    None => continue,
+                }
             }
 
             let archetype = self.archetypes.next()?;
@@ -69,15 +77,58 @@ impl<R: QueryResult> Iterator for Query<R> {
     }
 }
 
+// === SubtreeQuery === //
+
+/// A [`Query`] restricted to the subtree rooted at a given entity, including the root itself.
+///
+/// Rather than scanning every matching archetype, this walks the subtree cached by
+/// [`Entity::descendants_with`] and fetches each row from the current components of its entities.
+/// Rows are yielded in pre-order.
+#[derive(Debug)]
+pub struct SubtreeQuery<R: QueryResult> {
+    rows: vec::IntoIter<R>,
+}
+
+impl<R: QueryResult> SubtreeQuery<R> {
+    pub fn new(root: Entity) -> Self {
+        let state = R::fetch_state(&WORLD);
+        let mut rows = Vec::new();
+
+        for entity in &root.descendants_with(R::comp_ids()) {
+            // The cached subtree may still list entities reshaped since the last flush.
+            if let Some(row) = unsafe { R::fetch(&state, entity) } {
+                rows.push(row);
+            }
+        }
+
+        Self {
+            rows: rows.into_iter(),
+        }
+    }
+}
+
+impl<R: QueryResult> Iterator for SubtreeQuery<R> {
+    type Item = R;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.rows.next()
+    }
+}
+
 // === QueryResult === //
 
 pub trait QueryResult: Sized {
     type State;
     type Leader;
+    type FetchState;
 
     fn comp_ids() -> impl IntoIterator<Item = ComponentId>;
 
-    fn empty_state() -> Self::State;
+    fn excluded_comp_ids() -> impl IntoIterator<Item = ComponentId> {
+        []
+    }
+
+    fn empty_state(world: &World) -> Self::State;
 
     fn empty_leader() -> Self::Leader;
 
@@ -96,20 +147,35 @@ pub trait QueryResult: Sized {
 
     fn state_has_next(state: &Self::State, leader: &Self::Leader) -> bool;
 
-    unsafe fn next_unchecked_leader(state: &mut Self::State, leader: &mut Self::Leader) -> Self;
+    /// Advances the state by one row, returning `None` if the row was rejected by a filter.
+    unsafe fn next_unchecked_leader(
+        state: &mut Self::State,
+        leader: &mut Self::Leader,
+    ) -> Option<Self>;
+
+    /// Advances the state by one row, returning `None` if the row was rejected by a filter.
+    unsafe fn next_unchecked_follower(state: &mut Self::State) -> Option<Self>;
 
-    unsafe fn next_unchecked_follower(state: &mut Self::State) -> Self;
+    /// Creates the state shared by every call to [`QueryResult::fetch`] made by a single query.
+    fn fetch_state(world: &World) -> Self::FetchState;
+
+    /// Fetches the row of a single entity from its current components, returning `None` if the
+    /// entity does not match.
+    ///
+    /// `state` must have been created by [`QueryResult::fetch_state`] for the current world.
+    unsafe fn fetch(state: &Self::FetchState, entity: Entity) -> Option<Self>;
 }
 
 impl QueryResult for Entity {
     type State = *const Entity; // (finger)
     type Leader = *const Entity; // (last element)
+    type FetchState = ();
 
     fn comp_ids() -> impl IntoIterator<Item = ComponentId> {
         []
     }
 
-    fn empty_state() -> Self::State {
+    fn empty_state(_world: &World) -> Self::State {
         null()
     }
 
@@ -147,26 +213,36 @@ impl QueryResult for Entity {
         *state != *leader
     }
 
-    unsafe fn next_unchecked_leader(state: &mut Self::State, _leader: &mut Self::Leader) -> Self {
+    unsafe fn next_unchecked_leader(
+        state: &mut Self::State,
+        _leader: &mut Self::Leader,
+    ) -> Option<Self> {
         Self::next_unchecked_follower(state)
     }
 
-    unsafe fn next_unchecked_follower(state: &mut Self::State) -> Self {
+    unsafe fn next_unchecked_follower(state: &mut Self::State) -> Option<Self> {
         let curr = **state;
         *state = state.add(1);
-        curr
+        Some(curr)
+    }
+
+    fn fetch_state(_world: &World) -> Self::FetchState {}
+
+    unsafe fn fetch(_state: &Self::FetchState, entity: Entity) -> Option<Self> {
+        Some(entity)
     }
 }
 
 impl<T: Component> QueryResult for Obj<T> {
     type State = *const Index; // (finger)
     type Leader = *const Index; // (last element)
+    type FetchState = *const Storage<T>;
 
     fn comp_ids() -> impl IntoIterator<Item = ComponentId> {
         [ComponentId::of::<T>()]
     }
 
-    fn empty_state() -> Self::State {
+    fn empty_state(_world: &World) -> Self::State {
         null()
     }
 
@@ -206,95 +282,324 @@ impl<T: Component> QueryResult for Obj<T> {
         *state != *leader
     }
 
-    unsafe fn next_unchecked_leader(state: &mut Self::State, _leader: &mut Self::Leader) -> Self {
+    unsafe fn next_unchecked_leader(
+        state: &mut Self::State,
+        _leader: &mut Self::Leader,
+    ) -> Option<Self> {
         Self::next_unchecked_follower(state)
     }
 
-    unsafe fn next_unchecked_follower(state: &mut Self::State) -> Self {
+    unsafe fn next_unchecked_follower(state: &mut Self::State) -> Option<Self> {
         let curr = **state;
         *state = state.add(1);
-        Obj::from_raw(curr)
+        Some(Obj::from_raw(curr))
+    }
+
+    fn fetch_state(world: &World) -> Self::FetchState {
+        // Resources are never deallocated before their world so this pointer remains valid for the
+        // lifetime of the query.
+        let storage: &Storage<T> = unsafe { &*world.single::<T::Arena>() };
+        storage as *const Storage<T>
+    }
+
+    unsafe fn fetch(state: &Self::FetchState, entity: Entity) -> Option<Self> {
+        match (**state).get_by_entity(entity) {
+            Some(slot) => Some(Obj::from_raw(slot.handle)),
+            None => None,
+        }
+    }
+}
+
+// === Optional Components === //
+
+/// Excludes every archetype containing `T` from the query. This yields no data.
+#[derive_where(Debug, Copy, Clone, Default)]
+pub struct Without<T: Component>(PhantomData<fn(T) -> T>);
+
+impl<T: Component> QueryResult for Without<T> {
+    type State = <Entity as QueryResult>::State;
+    type Leader = <Entity as QueryResult>::Leader;
+    type FetchState = <Obj<T> as QueryResult>::FetchState;
+
+    fn comp_ids() -> impl IntoIterator<Item = ComponentId> {
+        []
+    }
+
+    fn excluded_comp_ids() -> impl IntoIterator<Item = ComponentId> {
+        [ComponentId::of::<T>()]
+    }
+
+    fn empty_state(world: &World) -> Self::State {
+        Entity::empty_state(world)
+    }
+
+    fn empty_leader() -> Self::Leader {
+        Entity::empty_leader()
+    }
+
+    fn update_state_leader(
+        query_state: &EntityQueryState,
+        archetype: ArchetypeId,
+        state: &mut Self::State,
+        leader: &mut Self::Leader,
+    ) -> bool {
+        Entity::update_state_leader(query_state, archetype, state, leader)
+    }
+
+    fn update_state_follower(
+        query_state: &EntityQueryState,
+        archetype: ArchetypeId,
+        state: &mut Self::State,
+    ) {
+        Entity::update_state_follower(query_state, archetype, state);
+    }
+
+    fn state_has_next(state: &Self::State, leader: &Self::Leader) -> bool {
+        Entity::state_has_next(state, leader)
+    }
+
+    unsafe fn next_unchecked_leader(
+        state: &mut Self::State,
+        _leader: &mut Self::Leader,
+    ) -> Option<Self> {
+        Self::next_unchecked_follower(state)
+    }
+
+    unsafe fn next_unchecked_follower(state: &mut Self::State) -> Option<Self> {
+        Entity::next_unchecked_follower(state).map(|_| Self::default())
+    }
+
+    fn fetch_state(world: &World) -> Self::FetchState {
+        Obj::<T>::fetch_state(world)
+    }
+
+    unsafe fn fetch(state: &Self::FetchState, entity: Entity) -> Option<Self> {
+        match Obj::<T>::fetch(state, entity) {
+            Some(_) => None,
+            None => Some(Self::default()),
+        }
+    }
+}
+
+impl<T: Component> QueryResult for Option<Obj<T>> {
+    type State = (*const Entity, *const Index); // (entity finger, component finger or null)
+    type Leader = *const Entity; // (last entity)
+    type FetchState = <Obj<T> as QueryResult>::FetchState;
+
+    fn comp_ids() -> impl IntoIterator<Item = ComponentId> {
+        []
+    }
+
+    fn empty_state(world: &World) -> Self::State {
+        (Entity::empty_state(world), null())
+    }
+
+    fn empty_leader() -> Self::Leader {
+        Entity::empty_leader()
+    }
+
+    fn update_state_leader(
+        query_state: &EntityQueryState,
+        archetype: ArchetypeId,
+        state: &mut Self::State,
+        leader: &mut Self::Leader,
+    ) -> bool {
+        if !Entity::update_state_leader(query_state, archetype, &mut state.0, leader) {
+            state.1 = null();
+            return false;
+        }
+
+        state.1 = optional_comp_finger::<T>(query_state, archetype);
+        true
+    }
+
+    fn update_state_follower(
+        query_state: &EntityQueryState,
+        archetype: ArchetypeId,
+        state: &mut Self::State,
+    ) {
+        Entity::update_state_follower(query_state, archetype, &mut state.0);
+        state.1 = optional_comp_finger::<T>(query_state, archetype);
+    }
+
+    fn state_has_next(state: &Self::State, leader: &Self::Leader) -> bool {
+        Entity::state_has_next(&state.0, leader)
+    }
+
+    unsafe fn next_unchecked_leader(
+        state: &mut Self::State,
+        _leader: &mut Self::Leader,
+    ) -> Option<Self> {
+        Self::next_unchecked_follower(state)
+    }
+
+    unsafe fn next_unchecked_follower(state: &mut Self::State) -> Option<Self> {
+        let _entity = Entity::next_unchecked_follower(&mut state.0);
+
+        if state.1.is_null() {
+            return Some(None);
+        }
+
+        Some(Obj::<T>::next_unchecked_follower(&mut state.1))
+    }
+
+    fn fetch_state(world: &World) -> Self::FetchState {
+        Obj::<T>::fetch_state(world)
     }
+
+    unsafe fn fetch(state: &Self::FetchState, entity: Entity) -> Option<Self> {
+        Some(Obj::<T>::fetch(state, entity))
+    }
+}
+
+fn optional_comp_finger<T: Component>(
+    query_state: &EntityQueryState,
+    archetype: ArchetypeId,
+) -> *const Index {
+    query_state
+        .comp_members
+        .get(&(archetype, ComponentId::of::<T>()))
+        .map_or(null(), |members| members.as_ptr())
+}
+
+// === Change Detection === //
+
+// Both filters report changes made since the start of the frame ended by the last `Entity::flush`.
+// This covers the previous frame, whose insertions queries can observe since archetype membership
+// is only updated by that flush, as well as the current frame so that systems see mutations made
+// by the systems which ran before them. A component inserted before the last flush is therefore
+// reported by `Added` exactly once while a mutation may be reported by `Changed` both in the frame
+// in which it was made and in the one after it.
+
+/// Matches components inserted between the last two calls to [`Entity::flush`].
+#[derive_where(Debug, Copy, Clone)]
+pub struct Added<T: Component>(pub Obj<T>);
+
+/// Matches components inserted, overwritten or mutably dereferenced since the start of the frame
+/// ended by the last call to [`Entity::flush`]. This includes every component matched by [`Added`].
+#[derive_where(Debug, Copy, Clone)]
+pub struct Changed<T: Component>(pub Obj<T>);
+
+#[derive_where(Debug)]
+pub struct TickFilterState<T: Component> {
+    obj: <Obj<T> as QueryResult>::State,
+    storage: *const Storage<T>,
+
+    /// The tick of the frame ended by the last `Entity::flush`.
+    since: u32,
+
+    /// The tick of the current frame.
+    until: u32,
+}
+
+impl<T: Component> TickFilterState<T> {
+    fn new(world: &World) -> Self {
+        Self {
+            obj: Obj::<T>::empty_state(world),
+            storage: Obj::<T>::fetch_state(world),
+            since: world.change_tick().wrapping_sub(1),
+            until: world.change_tick(),
+        }
+    }
+
+    unsafe fn filter(&self, obj: Obj<T>, tick: fn(&StorageSlot<T>) -> u32) -> bool {
+        // Components removed since the last flush are still present in the archetype snapshot.
+        (*self.storage)
+            .get(Obj::raw(obj))
+            .is_some_and(|slot| self.in_window(tick(slot)))
+    }
+
+    fn in_window(&self, tick: u32) -> bool {
+        // Ticks wrap around so compare their distances from the start of the window instead.
+        tick.wrapping_sub(self.since) <= self.until.wrapping_sub(self.since)
+    }
+}
+
+fn added_tick<T>(slot: &StorageSlot<T>) -> u32 {
+    slot.added_tick
+}
+
+fn changed_tick<T>(slot: &StorageSlot<T>) -> u32 {
+    // Insertions also stamp `changed_tick`.
+    slot.changed_tick
 }
 
-macro_rules! impl_tuples {
-	// Internal
-	(
-		$target:path : []
-		$(| [
-			$({$($pre:tt)*})*
-		])?
-	) => { /* terminal recursion case */ };
-	(
-		$target:path : [
-			{$($next:tt)*}
-			// Remaining invocations
-			$($rest:tt)*
-		] $(| [
-			// Accumulated arguments
-			$({$($pre:tt)*})*
-		])?
-	) => {
-		$target!(
-			$($($($pre)*,)*)?
-			$($next)*
-		);
-		impl_tuples!(
-			$target : [
-				$($rest)*
-			] | [
-				$($({$($pre)*})*)?
-				{$($next)*}
-			]
-		);
-	};
-
-	// Public
-	($target:path; no_unit) => {
-		impl_tuples!(
-			$target : [
-				{A: 0}
-				{B: 1}
-				{C: 2}
-				{D: 3}
-				{E: 4}
-				{F: 5}
-				{G: 6}
-				{H: 7}
-				{I: 8}
-				{J: 9}
-				{K: 10}
-				{L: 11}
-			]
-		);
-	};
-	($target:path; only_full) => {
-		$target!(
-			A:0,
-			B:1,
-			C:2,
-			D:3,
-			E:4,
-			F:5,
-			G:6,
-			H:7,
-			I:8,
-			J:9,
-			K:10,
-			L:11
-		);
-	};
-	($target:path) => {
-		$target!();
-		impl_tuples!($target; no_unit);
-	};
+macro_rules! impl_tick_filter {
+    ($($ty:ident => $tick:ident),*$(,)?) => {$(
+        impl<T: Component> QueryResult for $ty<T> {
+            type State = TickFilterState<T>;
+            type Leader = <Obj<T> as QueryResult>::Leader;
+            type FetchState = TickFilterState<T>;
+
+            fn comp_ids() -> impl IntoIterator<Item = ComponentId> {
+                Obj::<T>::comp_ids()
+            }
+
+            fn empty_state(world: &World) -> Self::State {
+                TickFilterState::new(world)
+            }
+
+            fn empty_leader() -> Self::Leader {
+                Obj::<T>::empty_leader()
+            }
+
+            fn update_state_leader(
+                query_state: &EntityQueryState,
+                archetype: ArchetypeId,
+                state: &mut Self::State,
+                leader: &mut Self::Leader,
+            ) -> bool {
+                Obj::<T>::update_state_leader(query_state, archetype, &mut state.obj, leader)
+            }
+
+            fn update_state_follower(
+                query_state: &EntityQueryState,
+                archetype: ArchetypeId,
+                state: &mut Self::State,
+            ) {
+                Obj::<T>::update_state_follower(query_state, archetype, &mut state.obj);
+            }
+
+            fn state_has_next(state: &Self::State, leader: &Self::Leader) -> bool {
+                Obj::<T>::state_has_next(&state.obj, leader)
+            }
+
+            unsafe fn next_unchecked_leader(
+                state: &mut Self::State,
+                leader: &mut Self::Leader,
+            ) -> Option<Self> {
+                let obj = Obj::<T>::next_unchecked_leader(&mut state.obj, leader)?;
+                state.filter(obj, $tick).then_some(Self(obj))
+            }
+
+            unsafe fn next_unchecked_follower(state: &mut Self::State) -> Option<Self> {
+                let obj = Obj::<T>::next_unchecked_follower(&mut state.obj)?;
+                state.filter(obj, $tick).then_some(Self(obj))
+            }
+
+            fn fetch_state(world: &World) -> Self::FetchState {
+                TickFilterState::new(world)
+            }
+
+            unsafe fn fetch(state: &Self::FetchState, entity: Entity) -> Option<Self> {
+                let slot = (*state.storage).get_by_entity(entity)?;
+
+                state
+                    .in_window($tick(slot))
+                    .then(|| Self(Obj::from_raw(slot.handle)))
+            }
+        }
+    )*};
 }
 
+impl_tick_filter!(Added => added_tick, Changed => changed_tick);
+
 macro_rules! impl_tup_query_result {
     ($leader:ident:$ignored:tt $(, $para:ident:$field:tt)*) => {
         impl<$leader: QueryResult $(, $para: QueryResult)*> QueryResult for ($leader, $($para,)*) {
             type State = ($leader::State, $($para::State,)*);
             type Leader = $leader::Leader;
+            type FetchState = ($leader::FetchState, $($para::FetchState,)*);
 
             fn comp_ids() -> impl IntoIterator<Item = ComponentId> {
                 let iter = $leader::comp_ids().into_iter();
@@ -302,8 +607,14 @@ macro_rules! impl_tup_query_result {
                 iter
             }
 
-            fn empty_state() -> Self::State {
-                ($leader::empty_state(), $($para::empty_state(),)*)
+            fn excluded_comp_ids() -> impl IntoIterator<Item = ComponentId> {
+                let iter = $leader::excluded_comp_ids().into_iter();
+                $( let iter = iter.chain($para::excluded_comp_ids()); )*
+                iter
+            }
+
+            fn empty_state(world: &World) -> Self::State {
+                ($leader::empty_state(world), $($para::empty_state(world),)*)
             }
 
             fn empty_leader() -> Self::Leader {
@@ -338,18 +649,37 @@ macro_rules! impl_tup_query_result {
                 $leader::state_has_next(&state.0, leader)
             }
 
-            unsafe fn next_unchecked_leader(state: &mut Self::State, leader: &mut Self::Leader) -> Self {
-                (
+            unsafe fn next_unchecked_leader(
+                state: &mut Self::State,
+                leader: &mut Self::Leader,
+            ) -> Option<Self> {
+                // Every member must be advanced, even if an earlier one rejected the row.
+                let row = (
                     $leader::next_unchecked_leader(&mut state.0, leader),
                     $($para::next_unchecked_follower(&mut state.$field), )*
-                )
+                );
+
+                Some((row.0?, $(row.$field?, )*))
             }
 
-            unsafe fn next_unchecked_follower(state: &mut Self::State) -> Self {
-                (
+            unsafe fn next_unchecked_follower(state: &mut Self::State) -> Option<Self> {
+                let row = (
                     $leader::next_unchecked_follower(&mut state.0),
                     $($para::next_unchecked_follower(&mut state.$field), )*
-                )
+                );
+
+                Some((row.0?, $(row.$field?, )*))
+            }
+
+            fn fetch_state(world: &World) -> Self::FetchState {
+                ($leader::fetch_state(world), $($para::fetch_state(world),)*)
+            }
+
+            unsafe fn fetch(state: &Self::FetchState, entity: Entity) -> Option<Self> {
+                Some((
+                    $leader::fetch(&state.0, entity)?,
+                    $($para::fetch(&state.$field, entity)?, )*
+                ))
             }
         }
     };
@@ -403,3 +733,148 @@ impl<T: Component> Iterator for ComponentsRemoved<T> {
         self.iter.next().copied().map(Obj::<T>::from_raw)
     }
 }
+
+// === Tests === //
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        bind, component, resource,
+        schedule::{Schedule, Stage, SystemDef},
+        Added, Changed, Entity, Obj, Query, Resource as _, Without, World,
+    };
+
+    #[derive(Debug)]
+    pub struct Health(u32);
+
+    #[derive(Debug)]
+    pub struct Frozen;
+
+    component!(Health, Frozen);
+
+    #[derive(Debug, Default)]
+    pub struct Replicated(Vec<u32>);
+
+    resource!(Replicated);
+
+    fn damage(world: &mut World) {
+        bind!(world);
+
+        for mut health in Query::<Obj<Health>>::new() {
+            health.0 -= 1;
+        }
+    }
+
+    fn replicate(world: &mut World) {
+        bind!(world);
+
+        for Changed(health) in Query::<Changed<Health>>::new() {
+            Replicated::fetch_mut().0.push(health.0);
+        }
+    }
+
+    #[test]
+    fn skips_excluded_archetypes() {
+        let mut world = World::new();
+        bind!(world);
+
+        let thawed = Entity::new(Entity::root()).with(Health(1));
+        Entity::new(Entity::root()).with(Health(2)).with(Frozen);
+        Entity::flush(|_world| {});
+
+        let mut seen = Vec::new();
+
+        for (entity, _health, _without) in Query::<(Entity, Obj<Health>, Without<Frozen>)>::new() {
+            seen.push(entity);
+        }
+
+        assert_eq!(seen, [thawed]);
+    }
+
+    #[test]
+    fn optional_items_span_archetypes() {
+        let mut world = World::new();
+        bind!(world);
+
+        let thawing = Entity::new(Entity::root()).with(Frozen);
+        let health = thawing.add(Health(2));
+        let frozen = Entity::new(Entity::root()).with(Frozen);
+        Entity::flush(|_world| {});
+
+        let mut seen = Vec::new();
+
+        for (entity, _frozen, health) in Query::<(Entity, Obj<Frozen>, Option<Obj<Health>>)>::new()
+        {
+            seen.push((entity, health));
+        }
+
+        assert_eq!(seen.len(), 2);
+        assert!(seen.contains(&(thawing, Some(health))));
+        assert!(seen.contains(&(frozen, None)));
+    }
+
+    #[test]
+    fn reports_changes_of_the_flushed_frame() {
+        let mut world = World::new();
+        bind!(world);
+
+        // Insertions are reported once the flush has made them visible.
+        let mut health = Entity::new(Entity::root()).add(Health(3));
+        assert_eq!(Query::<Added<Health>>::new().count(), 0);
+
+        Entity::flush(|_world| {});
+        assert_eq!(Query::<Added<Health>>::new().count(), 1);
+        assert_eq!(Query::<Changed<Health>>::new().count(), 1);
+
+        // Mutations are reported after the flush ending the frame in which they were made.
+        health.0 -= 1;
+        Entity::flush(|_world| {});
+        assert_eq!(Query::<Added<Health>>::new().count(), 0);
+        assert_eq!(Query::<Changed<Health>>::new().count(), 1);
+
+        for Changed(changed) in Query::<Changed<Health>>::new() {
+            assert_eq!(changed, health);
+            assert_eq!(changed.0, 2);
+        }
+
+        // Components mutated in the frame they were inserted in are reported by both filters.
+        let mut other = Entity::new(Entity::root()).add(Health(5));
+        other.0 += 1;
+        Entity::flush(|_world| {});
+        assert_eq!(Query::<Added<Health>>::new().count(), 1);
+        assert_eq!(Query::<Changed<Health>>::new().count(), 1);
+
+        for Added(added) in Query::<Added<Health>>::new() {
+            assert_eq!(added, other);
+        }
+
+        for Changed(changed) in Query::<Changed<Health>>::new() {
+            assert_eq!(changed, other);
+        }
+
+        // Nothing is reported once a frame passes without changes.
+        Entity::flush(|_world| {});
+        assert_eq!(Query::<Changed<Health>>::new().count(), 0);
+    }
+
+    #[test]
+    fn reports_changes_made_earlier_in_the_frame() {
+        let mut world = World::new();
+        bind!(world);
+
+        Entity::new(Entity::root()).add(Health(10));
+        Entity::flush(|_world| {});
+
+        Schedule::fetch_mut()
+            .add(SystemDef::new("damage", damage))
+            .add(SystemDef::new("replicate", replicate).after("damage"));
+
+        // Each mutation overwrites the tick of the previous one before the reader gets to run.
+        for _frame in 0..3 {
+            Schedule::run(Stage::Update);
+            Entity::flush(|_world| {});
+        }
+
+        assert_eq!(Replicated::fetch().0, [9, 8, 7]);
+    }
+}
@@ -0,0 +1,266 @@
+use std::{
+    context::{unpack, Bundle},
+    fmt,
+};
+
+use serde::Serialize;
+use thiserror::Error;
+use thunderdome::Index;
+
+use crate::{
+    archetype::ComponentId,
+    bind,
+    entity::EntityStore,
+    snapshot::{bind_reflect_state, pre_order, ComponentSerde},
+    Entity, WORLD,
+};
+
+// === Reflection === //
+
+#[derive(Debug, Error)]
+pub enum ReflectError {
+    #[error("component `{0}` is not registered for reflection")]
+    NotReflected(&'static str),
+    #[error("entity 0x{:x} does not have a `{name}` component", .entity.raw().to_bits())]
+    MissingComponent { entity: Entity, name: &'static str },
+    #[error("component `{name}` has no field at `{path}`")]
+    InvalidPath { name: &'static str, path: String },
+    #[error("failed to reflect component `{name}`")]
+    Serde {
+        name: &'static str,
+        #[source]
+        error: serde_json::Error,
+    },
+}
+
+/// Components are reflected through their serialization hooks, which the `reflect` option of
+/// `component!` registers regardless of whether the `serde` option is present.
+fn reflect_of(comp: ComponentId) -> Result<&'static ComponentSerde, ReflectError> {
+    comp.reflect
+        .ok_or(ReflectError::NotReflected((comp.type_name)()))
+}
+
+fn has_component(store: &EntityStore, entity: Entity, comp: ComponentId) -> bool {
+    store.is_alive(entity) && store.components(entity).contains(&comp)
+}
+
+/// Reads the given component of `entity` as a tree of dynamic values. Structs become objects
+/// keyed by their field names while references to entities and components become their raw IDs.
+pub fn read_component(
+    entity: Entity,
+    comp: ComponentId,
+    cx: Bundle<&mut WORLD>,
+) -> Result<serde_json::Value, ReflectError> {
+    let world = unpack!(cx => &mut WORLD).reborrow();
+    let world = world.immutable();
+    let reflect = reflect_of(comp)?;
+
+    if !has_component(world.read::<EntityStore>(), entity, comp) {
+        return Err(ReflectError::MissingComponent {
+            entity,
+            name: (comp.type_name)(),
+        });
+    }
+
+    let _guard = bind_reflect_state();
+
+    world
+        .bind_tls(|| (reflect.serialize)(world, entity))
+        .map_err(|error| ReflectError::Serde {
+            name: (comp.type_name)(),
+            error,
+        })
+}
+
+/// Overwrites the part of the given component of `entity` designated by `path`, a JSON pointer
+/// into the tree returned by [`read_component`] such as `/physical/x`. An empty path replaces the
+/// entire component.
+pub fn write_component(
+    entity: Entity,
+    comp: ComponentId,
+    path: &str,
+    value: serde_json::Value,
+    cx: Bundle<&mut WORLD>,
+) -> Result<(), ReflectError> {
+    let world = unpack!(cx => &mut WORLD);
+
+    let mut tree = {
+        bind!(world);
+        read_component(entity, comp)?
+    };
+
+    let field = tree
+        .pointer_mut(path)
+        .ok_or_else(|| ReflectError::InvalidPath {
+            name: (comp.type_name)(),
+            path: path.to_string(),
+        })?;
+
+    *field = value;
+
+    let _guard = bind_reflect_state();
+
+    (reflect_of(comp)?.deserialize)(world, entity, tree).map_err(|error| ReflectError::Serde {
+        name: (comp.type_name)(),
+        error,
+    })
+}
+
+// === Inspector === //
+
+/// A listing of an entity subtree alongside the reflected values of its components, suitable
+/// for displaying in an inspector. Nodes are stored in pre-order so parents always precede their
+/// children and the first node is the root of the subtree.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct InspectorTree {
+    pub nodes: Vec<InspectorNode>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct InspectorNode {
+    /// The raw ID of the entity, as accepted by [`Entity::from_raw`].
+    pub id: u64,
+
+    /// The index of the parent node or `None` for the root of the tree.
+    pub parent: Option<u32>,
+
+    /// The number of ancestors of this node within the tree.
+    pub depth: u32,
+
+    /// Every component of this node, sorted by type name.
+    pub components: Vec<InspectorComponent>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct InspectorComponent {
+    pub name: &'static str,
+
+    /// The reflected value of the component or `None` if it isn't registered for reflection.
+    pub value: Option<serde_json::Value>,
+}
+
+impl InspectorNode {
+    pub fn entity(&self) -> Entity {
+        Entity::from_raw(Index::from_bits(self.id).expect("invalid raw ID"))
+    }
+}
+
+impl InspectorTree {
+    pub fn capture(root: Entity, cx: Bundle<&mut WORLD>) -> Result<Self, ReflectError> {
+        let world = unpack!(cx => &mut WORLD).reborrow();
+        let world = world.immutable();
+        let store = world.read::<EntityStore>();
+
+        let (order, _) = pre_order(store, root);
+        let _guard = bind_reflect_state();
+
+        world.bind_tls(|| {
+            let mut nodes = Vec::<InspectorNode>::with_capacity(order.len());
+
+            for &(entity, parent) in &order {
+                let mut components = store
+                    .components(entity)
+                    .iter()
+                    .map(|comp| {
+                        let value = comp
+                            .reflect
+                            .map(|reflect| (reflect.serialize)(world, entity))
+                            .transpose()
+                            .map_err(|error| ReflectError::Serde {
+                                name: (comp.type_name)(),
+                                error,
+                            })?;
+
+                        Ok(InspectorComponent {
+                            name: (comp.type_name)(),
+                            value,
+                        })
+                    })
+                    .collect::<Result<Vec<_>, ReflectError>>()?;
+
+                components.sort_by_key(|comp| comp.name);
+
+                nodes.push(InspectorNode {
+                    id: entity.raw().to_bits(),
+                    parent,
+                    depth: parent.map_or(0, |parent| nodes[parent as usize].depth + 1),
+                    components,
+                });
+            }
+
+            Ok(Self { nodes })
+        })
+    }
+}
+
+impl fmt::Display for InspectorTree {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for node in &self.nodes {
+            let indent = node.depth as usize * 2;
+
+            writeln!(f, "{:indent$}0x{:x}", "", node.id)?;
+
+            for comp in &node.components {
+                match &comp.value {
+                    Some(value) => writeln!(f, "{:indent$}  {}: {value}", "", comp.name)?,
+                    None => writeln!(f, "{:indent$}  {}", "", comp.name)?,
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// === Tests === //
+
+#[cfg(test)]
+mod tests {
+    use serde::{Deserialize, Serialize};
+
+    use crate::{archetype::ComponentId, bind, component, Entity, Obj, World};
+
+    use super::{read_component, write_component, InspectorTree};
+
+    #[derive(Debug, Serialize, Deserialize)]
+    pub struct Health {
+        current: u32,
+        max: u32,
+    }
+
+    #[derive(Debug, Serialize, Deserialize)]
+    pub struct Target(Obj<Health>);
+
+    component!(Health { reflect }, Target { reflect });
+
+    #[test]
+    fn reflect_and_edit() {
+        let mut world = World::new();
+        bind!(world);
+
+        let parent = Entity::new(Entity::root());
+        let health = Entity::new(parent).add(Health {
+            current: 5,
+            max: 10,
+        });
+        parent.add(Target(health));
+
+        let id = ComponentId::of::<Health>();
+        let value = read_component(health.entity(), id).unwrap();
+        assert_eq!(value, serde_json::json!({ "current": 5, "max": 10 }));
+
+        write_component(health.entity(), id, "/current", 10.into()).unwrap();
+        assert_eq!(health.current, 10);
+
+        // References survive a round-trip through reflection.
+        let id = ComponentId::of::<Target>();
+        let value = read_component(parent, id).unwrap();
+        write_component(parent, id, "", value).unwrap();
+        assert_eq!(parent.get::<Target>().0, health);
+
+        let tree = InspectorTree::capture(parent).unwrap();
+        assert_eq!(tree.nodes.len(), 2);
+        assert_eq!(tree.nodes[1].entity(), health.entity());
+        assert_eq!(tree.nodes[1].depth, 1);
+    }
+}
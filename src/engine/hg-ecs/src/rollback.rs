@@ -0,0 +1,447 @@
+use std::{
+    any::Any,
+    collections::VecDeque,
+    context::{pack, unpack, Bundle},
+    fmt,
+};
+
+use hg_utils::hash::{FxHashMap, FxHashSet};
+use thiserror::Error;
+
+use crate::{
+    archetype::ComponentId,
+    bind,
+    entity::{Component, EntityStore},
+    snapshot::pre_order,
+    world::ImmutableWorld,
+    AccessComp, Entity, Resource, World, WORLD,
+};
+
+// === ComponentRollback === //
+
+pub struct ComponentRollback {
+    /// Clones the components of the given entities into a type-erased column.
+    pub save: fn(ImmutableWorld, &[Entity]) -> Box<dyn Any>,
+
+    /// Overwrites the components of the given entities with the values in a column produced by
+    /// `save`, adding them where they're missing.
+    pub restore: fn(&mut World, &dyn Any, &[Entity]),
+
+    /// Removes the component from the given entity immediately.
+    pub remove: fn(&mut World, Entity),
+}
+
+impl fmt::Debug for ComponentRollback {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ComponentRollback").finish_non_exhaustive()
+    }
+}
+
+impl ComponentRollback {
+    pub const fn of<T: Component + Clone>() -> &'static Self {
+        struct Helper<T>(T);
+
+        impl<T: Component + Clone> Helper<T> {
+            const ROLLBACK: &'static ComponentRollback = &ComponentRollback {
+                save: |world, entities| {
+                    let storage = world.read::<T::Arena>();
+
+                    let values = entities
+                        .iter()
+                        .map(|&entity| storage[entity].value.clone())
+                        .collect::<Vec<T>>();
+
+                    Box::new(values)
+                },
+                restore: |world, values, entities| {
+                    let values = values.downcast_ref::<Vec<T>>().unwrap();
+
+                    bind!(world, let cx: &mut AccessComp<T>);
+
+                    for (&entity, value) in entities.iter().zip(values) {
+                        entity.add(value.clone(), pack!(cx));
+                    }
+                },
+                remove: |world, entity| {
+                    bind!(world, let cx: &mut AccessComp<T>);
+
+                    entity.remove_now::<T>(pack!(cx));
+                },
+            };
+        }
+
+        Helper::<T>::ROLLBACK
+    }
+}
+
+// === RollbackBuffer === //
+
+#[derive(Debug, Clone, Error)]
+pub enum RollbackError {
+    #[error("tick {0} is not in the rollback buffer")]
+    NotBuffered(u32),
+    #[error("the root of the rollback buffer, entity 0x{:x}, is dead", .0.raw().to_bits())]
+    DeadRoot(Entity),
+    #[error(
+        "entity 0x{:x} would have to be respawned but had components which aren't rolled back",
+        .0.raw().to_bits()
+    )]
+    Unrestorable(Entity),
+}
+
+/// A ring buffer of per-tick copies of an entity subtree, used to rewind the world for rollback
+/// netcode.
+///
+/// Only components registered through the `rollback` option of `component!` are saved. These are
+/// cloned rather than serialized so saving every tick is cheap. Restoring a tick brings back the
+/// shape of the subtree at that tick: entities spawned since are destroyed and entities destroyed
+/// since are respawned. Respawned entities have new IDs and references to them inside saved
+/// components are not remapped.
+///
+/// Since only rollback components are saved, an entity can only be respawned if every one of its
+/// components was registered for rollback at the time of the save. Restoring a tick which would
+/// respawn any other entity fails with [`RollbackError::Unrestorable`] without touching the world,
+/// leaving the caller to rebuild the entity.
+///
+/// Both operations expect the world to have been flushed since the last structural change.
+#[derive(Debug)]
+pub struct RollbackBuffer {
+    root: Entity,
+    capacity: usize,
+    frames: VecDeque<RollbackFrame>,
+}
+
+#[derive(Debug)]
+struct RollbackFrame {
+    tick: u32,
+
+    /// The subtree in pre-order alongside the index of each node's parent.
+    entities: Vec<(Entity, Option<u32>)>,
+
+    /// The indices of the nodes which had components not registered for rollback.
+    unrestorable: FxHashSet<u32>,
+
+    columns: Vec<RollbackColumn>,
+}
+
+#[derive(Debug)]
+struct RollbackColumn {
+    comp: ComponentId,
+
+    /// The indices of the nodes which had this component, in the order of `values`.
+    nodes: Vec<u32>,
+
+    values: Box<dyn Any>,
+}
+
+impl RollbackBuffer {
+    /// Creates a buffer tracking the subtree rooted at `root` which retains the last `capacity`
+    /// saved ticks.
+    pub fn new(root: Entity, capacity: usize) -> Self {
+        assert!(
+            capacity > 0,
+            "rollback buffers must retain at least one tick"
+        );
+
+        Self {
+            root,
+            capacity,
+            frames: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    pub fn root(&self) -> Entity {
+        self.root
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Lists the saved ticks from oldest to newest.
+    pub fn ticks(&self) -> impl Iterator<Item = u32> + '_ {
+        self.frames.iter().map(|frame| frame.tick)
+    }
+
+    pub fn contains(&self, tick: u32) -> bool {
+        self.frames.iter().any(|frame| frame.tick == tick)
+    }
+
+    pub fn clear(&mut self) {
+        self.frames.clear();
+    }
+
+    /// Saves the current state of the subtree as `tick`, evicting the oldest tick if the buffer is
+    /// full. Saving a tick which isn't newer than every other saved tick, as happens while
+    /// re-simulating, discards the existing copy of that tick and every tick after it.
+    pub fn save(&mut self, tick: u32, cx: Bundle<&mut WORLD>) -> Result<(), RollbackError> {
+        let world = unpack!(cx => &mut WORLD).reborrow();
+        let world = world.immutable();
+        let store = world.read::<EntityStore>();
+
+        if !store.is_alive(self.root) {
+            return Err(RollbackError::DeadRoot(self.root));
+        }
+
+        let (entities, _) = pre_order(store, self.root);
+
+        let mut nodes = FxHashMap::<ComponentId, Vec<u32>>::default();
+        let mut unrestorable = FxHashSet::default();
+
+        for (idx, &(entity, _)) in entities.iter().enumerate() {
+            for &comp in store.components(entity) {
+                if comp.rollback.is_some() {
+                    nodes.entry(comp).or_default().push(idx as u32);
+                } else {
+                    unrestorable.insert(idx as u32);
+                }
+            }
+        }
+
+        let columns = nodes
+            .into_iter()
+            .map(|(comp, nodes)| {
+                let targets = nodes
+                    .iter()
+                    .map(|&node| entities[node as usize].0)
+                    .collect::<Vec<_>>();
+
+                RollbackColumn {
+                    comp,
+                    values: (comp.rollback.unwrap().save)(world, &targets),
+                    nodes,
+                }
+            })
+            .collect();
+
+        while self.frames.back().is_some_and(|frame| frame.tick >= tick) {
+            self.frames.pop_back();
+        }
+
+        if self.frames.len() == self.capacity {
+            self.frames.pop_front();
+        }
+
+        self.frames.push_back(RollbackFrame {
+            tick,
+            entities,
+            unrestorable,
+            columns,
+        });
+
+        Ok(())
+    }
+
+    /// Restores the subtree to its state at `tick` and discards every later tick. Returns the
+    /// entities which had to be respawned, keyed by their ID at the time of the save. Nothing is
+    /// changed if this fails.
+    ///
+    /// Entities spawned since the tick are destroyed through `destroy` and are therefore only
+    /// removed by the next `Entity::flush`.
+    pub fn restore(
+        &mut self,
+        tick: u32,
+        cx: Bundle<&mut WORLD>,
+    ) -> Result<FxHashMap<Entity, Entity>, RollbackError> {
+        let world = unpack!(cx => &mut WORLD);
+
+        let Some(pos) = self.frames.iter().position(|frame| frame.tick == tick) else {
+            return Err(RollbackError::NotBuffered(tick));
+        };
+
+        let frame = &self.frames[pos];
+
+        let (entities, respawned) = {
+            bind!(world);
+            Self::restore_hierarchy(self.root, frame)?
+        };
+
+        // Remove components added since the tick...
+        let mut saved = FxHashSet::default();
+
+        for column in &frame.columns {
+            for &node in &column.nodes {
+                saved.insert((column.comp, node));
+            }
+        }
+
+        let mut removed = Vec::new();
+
+        {
+            bind!(world);
+            let store = EntityStore::fetch();
+
+            for (idx, &entity) in entities.iter().enumerate() {
+                for &comp in store.components(entity) {
+                    if comp.rollback.is_some() && !saved.contains(&(comp, idx as u32)) {
+                        removed.push((entity, comp));
+                    }
+                }
+            }
+        }
+
+        for (entity, comp) in removed {
+            (comp.rollback.unwrap().remove)(world, entity);
+        }
+
+        // ...and overwrite the rest.
+        for column in &frame.columns {
+            let targets = column
+                .nodes
+                .iter()
+                .map(|&node| entities[node as usize])
+                .collect::<Vec<_>>();
+
+            (column.comp.rollback.unwrap().restore)(world, &*column.values, &targets);
+        }
+
+        // Every later tick is about to be re-simulated.
+        self.frames.truncate(pos + 1);
+
+        // Older ticks should refer to respawned entities by their replacements from now on.
+        for frame in &mut self.frames {
+            for (entity, _) in &mut frame.entities {
+                if let Some(&replacement) = respawned.get(entity) {
+                    *entity = replacement;
+                }
+            }
+        }
+
+        Ok(respawned)
+    }
+
+    /// Brings the hierarchy of the subtree back to its shape in `frame`. Returns the entity now
+    /// corresponding to each node alongside the entities which had to be respawned.
+    fn restore_hierarchy(
+        root: Entity,
+        frame: &RollbackFrame,
+    ) -> Result<(Vec<Entity>, FxHashMap<Entity, Entity>), RollbackError> {
+        if !root.is_alive() || root.is_condemned() {
+            return Err(RollbackError::DeadRoot(root));
+        }
+
+        // Check every node before changing anything so that failures leave the world untouched.
+        for (idx, &(entity, _)) in frame.entities.iter().enumerate().skip(1) {
+            let respawns = !entity.is_alive() || entity.is_condemned();
+
+            if respawns && frame.unrestorable.contains(&(idx as u32)) {
+                return Err(RollbackError::Unrestorable(entity));
+            }
+        }
+
+        let mut entities = Vec::<Entity>::with_capacity(frame.entities.len());
+        let mut respawned = FxHashMap::default();
+
+        for &(entity, parent) in &frame.entities {
+            // Only the first node, which is always the root, lacks a parent.
+            let Some(parent) = parent else {
+                entities.push(root);
+                continue;
+            };
+
+            let parent = entities[parent as usize];
+
+            if entity.is_alive() && !entity.is_condemned() {
+                if entity.parent() != Some(parent) {
+                    entity.set_parent(Some(parent));
+                }
+
+                entities.push(entity);
+            } else {
+                let replacement = Entity::new(parent);
+                respawned.insert(entity, replacement);
+                entities.push(replacement);
+            }
+        }
+
+        // Every restored entity is now parented to another restored entity so destroying the
+        // remaining entities never takes any of them along.
+        let restored = entities.iter().copied().collect::<FxHashSet<_>>();
+        let (current, _) = pre_order(EntityStore::fetch(), root);
+
+        for (entity, _) in current {
+            if !restored.contains(&entity) {
+                entity.destroy();
+            }
+        }
+
+        Ok((entities, respawned))
+    }
+}
+
+// === Tests === //
+
+#[cfg(test)]
+mod tests {
+    use crate::{bind, component, Entity, World};
+
+    use super::{RollbackBuffer, RollbackError};
+
+    #[derive(Debug, Clone)]
+    pub struct Score(u32);
+
+    #[derive(Debug)]
+    pub struct Sprite;
+
+    component!(Score { rollback }, Sprite);
+
+    #[test]
+    fn restores_spawned_and_destroyed_entities() {
+        let mut world = World::new();
+        bind!(world);
+
+        let level = Entity::new(Entity::root());
+        let kept = Entity::new(level).with(Score(1));
+        let doomed = Entity::new(level).with(Score(2));
+        Entity::flush(|_world| {});
+
+        let mut buffer = RollbackBuffer::new(level, 8);
+        buffer.save(0).unwrap();
+
+        let mut score = kept.get::<Score>();
+        score.0 = 10;
+        doomed.destroy();
+        let spawned = Entity::new(level).with(Score(3));
+        Entity::flush(|_world| {});
+
+        buffer.save(1).unwrap();
+
+        let respawned = buffer.restore(0).unwrap();
+        Entity::flush(|_world| {});
+
+        assert_eq!(kept.get::<Score>().0, 1);
+        assert!(!spawned.is_alive());
+
+        let replacement = respawned[&doomed];
+        assert_eq!(replacement.get::<Score>().0, 2);
+        assert_eq!(replacement.parent(), Some(level));
+        assert_eq!(buffer.ticks().collect::<Vec<_>>(), [0]);
+    }
+
+    #[test]
+    fn refuses_to_respawn_unsaved_components() {
+        let mut world = World::new();
+        bind!(world);
+
+        let level = Entity::new(Entity::root());
+        let player = Entity::new(level).with(Score(1)).with(Sprite);
+        Entity::flush(|_world| {});
+
+        let mut buffer = RollbackBuffer::new(level, 8);
+        buffer.save(0).unwrap();
+
+        player.destroy();
+        let spawned = Entity::new(level).with(Score(2));
+        Entity::flush(|_world| {});
+
+        buffer.save(1).unwrap();
+
+        let Err(RollbackError::Unrestorable(entity)) = buffer.restore(0) else {
+            panic!("expected the restore to fail");
+        };
+
+        assert_eq!(entity, player);
+        assert!(spawned.is_alive() && !spawned.is_condemned());
+        assert_eq!(buffer.ticks().collect::<Vec<_>>(), [0, 1]);
+    }
+}
@@ -0,0 +1,564 @@
+use std::{cmp::Reverse, collections::BinaryHeap, fmt, rc::Rc, time::Instant};
+
+use hg_utils::hash::{FxHashMap, FxHashSet};
+use thiserror::Error;
+
+use crate::{
+    parallel::{run_batch, AccessSet, SharedSystem, SharedWorld},
+    profile::Profiler,
+    resource, Resource, World, WORLD,
+};
+
+// === Stage === //
+
+#[derive(Debug, Copy, Clone, Hash, Eq, PartialEq, Ord, PartialOrd)]
+pub enum Stage {
+    /// Runs before any game logic, e.g. to reset per-frame accumulators.
+    StartOfFrame,
+
+    /// Runs the bulk of the game logic.
+    Update,
+
+    /// Runs once game logic has settled, e.g. to derive cameras from final positions.
+    PostUpdate,
+
+    /// Runs inside the handler passed to `Entity::flush`. Because `flush` calls its handler once
+    /// per batch of queued operations, systems in this stage may run several times per frame.
+    Flush,
+}
+
+impl Stage {
+    pub const ALL: [Self; 4] = [
+        Self::StartOfFrame,
+        Self::Update,
+        Self::PostUpdate,
+        Self::Flush,
+    ];
+
+    /// The stages run by [`Schedule::run_frame`], in order.
+    pub const FRAME: [Self; 3] = [Self::StartOfFrame, Self::Update, Self::PostUpdate];
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+// === SystemDef === //
+
+#[derive(Debug, Clone)]
+pub struct SystemDef {
+    name: &'static str,
+    stage: Stage,
+    labels: Vec<&'static str>,
+    before: Vec<&'static str>,
+    after: Vec<&'static str>,
+    run: SystemRun,
+}
+
+#[derive(Debug, Clone)]
+enum SystemRun {
+    /// Runs on the main thread with exclusive access to the world.
+    Exclusive(fn(&mut World)),
+
+    /// Runs on a worker thread, potentially alongside other shared systems.
+    Shared {
+        access: Rc<AccessSet>,
+        run: fn(&mut SharedWorld<'_>),
+    },
+}
+
+impl SystemDef {
+    pub fn new(name: &'static str, run: fn(&mut World)) -> Self {
+        Self::new_inner(name, SystemRun::Exclusive(run))
+    }
+
+    /// Defines a system which only accesses the resources and components declared in `access`.
+    ///
+    /// Consecutive shared systems in the same stage run in parallel so long as their access sets
+    /// don't conflict and neither is constrained to run before the other.
+    pub fn shared(name: &'static str, access: AccessSet, run: fn(&mut SharedWorld<'_>)) -> Self {
+        Self::new_inner(
+            name,
+            SystemRun::Shared {
+                access: Rc::new(access),
+                run,
+            },
+        )
+    }
+
+    fn new_inner(name: &'static str, run: SystemRun) -> Self {
+        Self {
+            name,
+            stage: Stage::Update,
+            labels: Vec::new(),
+            before: Vec::new(),
+            after: Vec::new(),
+            run,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    pub fn stage(&self) -> Stage {
+        self.stage
+    }
+
+    pub fn in_stage(mut self, stage: Stage) -> Self {
+        self.stage = stage;
+        self
+    }
+
+    /// Adds a label through which constraints can refer to this system. A system's name is always
+    /// usable as a label.
+    pub fn label(mut self, label: &'static str) -> Self {
+        self.labels.push(label);
+        self
+    }
+
+    /// Requires this system to run before every system with the given name or label.
+    pub fn before(mut self, label: &'static str) -> Self {
+        self.before.push(label);
+        self
+    }
+
+    /// Requires this system to run after every system with the given name or label.
+    pub fn after(mut self, label: &'static str) -> Self {
+        self.after.push(label);
+        self
+    }
+
+    fn has_label(&self, label: &str) -> bool {
+        self.name == label || self.labels.contains(&label)
+    }
+}
+
+/// Defines a [`SystemDef`] named after the given function. By default, the function is called
+/// without arguments, but a custom call expression can be supplied after a `=>`. Supplying an
+/// [`AccessSet`] after a comma instead defines a [shared](SystemDef::shared) system.
+#[macro_export]
+macro_rules! system {
+    ($name:path) => {
+        $crate::system!($name => $name())
+    };
+    ($name:path, $access:expr) => {
+        $crate::schedule::SystemDef::shared(::std::stringify!($name), $access, $name)
+    };
+    ($name:path => $call:expr) => {
+        $crate::schedule::SystemDef::new(::std::stringify!($name), |world| {
+            $crate::bind!(world);
+            $call;
+        })
+    };
+}
+
+pub use system;
+
+// === Schedule === //
+
+#[derive(Debug, Error)]
+pub enum ScheduleError {
+    #[error("system `{0}` was registered more than once")]
+    DuplicateSystem(&'static str),
+    #[error("system `{system}` is constrained by `{label}` but no system has that name or label")]
+    UnknownLabel {
+        system: &'static str,
+        label: &'static str,
+    },
+    #[error("system `{first}` must run before `{second}` but belongs to a later stage")]
+    StageConflict {
+        first: &'static str,
+        second: &'static str,
+    },
+    #[error("system ordering constraints form a cycle: {}", .0.join(" -> "))]
+    Cycle(Vec<&'static str>),
+}
+
+#[derive(Debug, Default)]
+pub struct Schedule {
+    systems: Vec<SystemDef>,
+    resolved: Option<[Rc<[ResolvedSystem]>; 4]>,
+}
+
+#[derive(Debug, Clone)]
+struct ResolvedSystem {
+    name: &'static str,
+    run: SystemRun,
+
+    /// The positions within the stage of the systems which must run before this one.
+    preds: Vec<usize>,
+}
+
+resource!(Schedule);
+
+impl Schedule {
+    pub fn add(&mut self, system: SystemDef) -> &mut Self {
+        self.resolved = None;
+        self.systems.push(system);
+        self
+    }
+
+    pub fn is_resolved(&self) -> bool {
+        self.resolved.is_some()
+    }
+
+    /// Orders the registered systems according to their constraints. Systems which aren't ordered
+    /// relative to one another run in registration order.
+    ///
+    /// This is done lazily by `run` but calling it ahead of time allows errors to be reported
+    /// gracefully.
+    pub fn resolve(&mut self) -> Result<(), ScheduleError> {
+        if self.resolved.is_some() {
+            return Ok(());
+        }
+
+        let systems = &self.systems;
+
+        let mut names = FxHashSet::default();
+
+        for system in systems {
+            if !names.insert(system.name) {
+                return Err(ScheduleError::DuplicateSystem(system.name));
+            }
+        }
+
+        // Build the dependency graph. Edges point from each system to the systems which must run
+        // after it. Constraints across stages are already satisfied by the stage order so long as
+        // they don't contradict it.
+        let mut edges = vec![Vec::new(); systems.len()];
+        let mut in_degree = vec![0u32; systems.len()];
+
+        for (idx, system) in systems.iter().enumerate() {
+            let before = system.before.iter().map(|&label| (label, true));
+            let after = system.after.iter().map(|&label| (label, false));
+
+            for (label, is_before) in before.chain(after) {
+                let mut found = false;
+
+                for (other_idx, other) in systems.iter().enumerate() {
+                    if other_idx == idx || !other.has_label(label) {
+                        continue;
+                    }
+
+                    found = true;
+
+                    let (first, second) = if is_before {
+                        (idx, other_idx)
+                    } else {
+                        (other_idx, idx)
+                    };
+
+                    if systems[first].stage > systems[second].stage {
+                        return Err(ScheduleError::StageConflict {
+                            first: systems[first].name,
+                            second: systems[second].name,
+                        });
+                    }
+
+                    if systems[first].stage == systems[second].stage {
+                        edges[first].push(second);
+                        in_degree[second] += 1;
+                    }
+                }
+
+                if !found {
+                    return Err(ScheduleError::UnknownLabel {
+                        system: system.name,
+                        label,
+                    });
+                }
+            }
+        }
+
+        let mut preds = vec![Vec::new(); systems.len()];
+
+        for (from, targets) in edges.iter().enumerate() {
+            for &to in targets {
+                preds[to].push(from);
+            }
+        }
+
+        // Topologically sort the graph, breaking ties by registration order.
+        let mut ready = (0..systems.len())
+            .filter(|&idx| in_degree[idx] == 0)
+            .map(Reverse)
+            .collect::<BinaryHeap<_>>();
+
+        let mut stages = Stage::ALL.map(|_| Vec::new());
+        let mut positions = vec![0; systems.len()];
+        let mut visited = 0;
+
+        while let Some(Reverse(idx)) = ready.pop() {
+            let system = &systems[idx];
+            let stage = &mut stages[system.stage.index()];
+            visited += 1;
+
+            // Predecessors always belong to the same stage and have already been placed.
+            positions[idx] = stage.len();
+
+            stage.push(ResolvedSystem {
+                name: system.name,
+                run: system.run.clone(),
+                preds: preds[idx].iter().map(|&pred| positions[pred]).collect(),
+            });
+
+            for &next in &edges[idx] {
+                in_degree[next] -= 1;
+
+                if in_degree[next] == 0 {
+                    ready.push(Reverse(next));
+                }
+            }
+        }
+
+        if visited != systems.len() {
+            let cycle = Self::find_cycle(&edges, &in_degree);
+
+            return Err(ScheduleError::Cycle(
+                cycle.into_iter().map(|idx| systems[idx].name).collect(),
+            ));
+        }
+
+        self.resolved = Some(stages.map(Rc::from));
+
+        Ok(())
+    }
+
+    fn find_cycle(edges: &[Vec<usize>], in_degree: &[u32]) -> Vec<usize> {
+        // Every system left over by the topological sort has at least one predecessor which was
+        // also left over so walking predecessors must eventually revisit a system.
+        let mut preds = vec![Vec::new(); edges.len()];
+
+        for (from, targets) in edges.iter().enumerate() {
+            for &to in targets {
+                preds[to].push(from);
+            }
+        }
+
+        let mut path = Vec::new();
+        let mut path_indices = FxHashMap::default();
+        let mut curr = in_degree.iter().position(|&v| v > 0).unwrap();
+
+        loop {
+            if let Some(&start) = path_indices.get(&curr) {
+                // We walked the cycle backwards so reverse it and close it up.
+                let mut cycle = path.split_off(start);
+                cycle.reverse();
+                cycle.push(cycle[0]);
+                return cycle;
+            }
+
+            path_indices.insert(curr, path.len());
+            path.push(curr);
+
+            curr = preds[curr]
+                .iter()
+                .copied()
+                .find(|&pred| in_degree[pred] > 0)
+                .unwrap();
+        }
+    }
+
+    /// Lists the names of the systems in the given stage in the order in which they'll run.
+    pub fn order(&self, stage: Stage) -> impl Iterator<Item = &'static str> + '_ {
+        self.resolved
+            .as_ref()
+            .expect("schedule has not been resolved")[stage.index()]
+        .iter()
+        .map(|system| system.name)
+    }
+
+    pub fn run(stage: Stage) {
+        let schedule = Self::fetch_mut();
+
+        if let Err(err) = schedule.resolve() {
+            panic!("failed to resolve system schedule: {err}");
+        }
+
+        let systems = schedule.resolved.as_ref().unwrap()[stage.index()].clone();
+        let mut start_idx = 0;
+
+        while start_idx < systems.len() {
+            let system = &systems[start_idx];
+
+            match &system.run {
+                SystemRun::Exclusive(run) => {
+                    let _span = tracing::debug_span!("system", name = system.name).entered();
+                    let start = Instant::now();
+
+                    run(&mut WORLD);
+
+                    Profiler::fetch_mut().record_system(system.name, start.elapsed());
+                    start_idx += 1;
+                }
+                SystemRun::Shared { .. } => {
+                    let batch = Self::shared_batch(&systems, start_idx);
+                    let elapsed = run_batch(&mut WORLD, &batch);
+
+                    for (system, elapsed) in batch.iter().zip(elapsed) {
+                        Profiler::fetch_mut().record_system(system.name, elapsed);
+                    }
+
+                    start_idx += batch.len();
+                }
+            }
+        }
+    }
+
+    /// Collects the run of consecutive shared systems starting at `start` which can run in
+    /// parallel with one another.
+    fn shared_batch(systems: &[ResolvedSystem], start: usize) -> Vec<SharedSystem<'_>> {
+        let mut batch = Vec::<SharedSystem<'_>>::new();
+
+        for (idx, system) in systems.iter().enumerate().skip(start) {
+            let SystemRun::Shared { access, run } = &system.run else {
+                break;
+            };
+
+            let constrained = system.preds.iter().any(|&pred| pred >= start);
+            let conflicts = batch
+                .iter()
+                .any(|other| other.access.conflicts_with(access));
+
+            if idx > start && (constrained || conflicts) {
+                break;
+            }
+
+            batch.push(SharedSystem {
+                name: system.name,
+                access,
+                run: *run,
+            });
+        }
+
+        batch
+    }
+
+    pub fn run_frame() {
+        for stage in Stage::FRAME {
+            Self::run(stage);
+        }
+    }
+}
+
+impl fmt::Display for Schedule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Some(resolved) = &self.resolved else {
+            return f.write_str("<unresolved schedule>");
+        };
+
+        for stage in Stage::ALL {
+            writeln!(f, "{stage:?}:")?;
+
+            for (i, system) in resolved[stage.index()].iter().enumerate() {
+                writeln!(f, "  {}. {}", i + 1, system.name)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// === Tests === //
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Default)]
+    pub struct Score(u32);
+
+    #[derive(Debug, Default)]
+    pub struct Settings(u32);
+
+    resource!(Score, Settings);
+
+    fn noop(_world: &mut World) {}
+
+    fn shared_noop(_world: &mut SharedWorld<'_>) {}
+
+    #[test]
+    fn resolves_constraints() {
+        let mut schedule = Schedule::default();
+
+        schedule
+            .add(SystemDef::new("colliders", noop).after("physics"))
+            .add(SystemDef::new("kinematics", noop).label("physics"))
+            .add(SystemDef::new("input", noop).before("kinematics"))
+            .add(SystemDef::new("reset", noop).in_stage(Stage::StartOfFrame))
+            .add(
+                SystemDef::new("camera", noop)
+                    .in_stage(Stage::PostUpdate)
+                    .after("colliders"),
+            );
+
+        schedule.resolve().unwrap();
+
+        assert_eq!(
+            schedule.order(Stage::Update).collect::<Vec<_>>(),
+            ["input", "kinematics", "colliders"],
+        );
+        assert_eq!(
+            schedule.order(Stage::StartOfFrame).collect::<Vec<_>>(),
+            ["reset"]
+        );
+        assert_eq!(
+            schedule.order(Stage::PostUpdate).collect::<Vec<_>>(),
+            ["camera"]
+        );
+    }
+
+    #[test]
+    fn detects_cycles() {
+        let mut schedule = Schedule::default();
+
+        schedule
+            .add(SystemDef::new("unrelated", noop))
+            .add(SystemDef::new("a", noop).after("c"))
+            .add(SystemDef::new("b", noop).after("a"))
+            .add(SystemDef::new("c", noop).after("b"));
+
+        let Err(ScheduleError::Cycle(cycle)) = schedule.resolve() else {
+            panic!("expected a cycle");
+        };
+
+        assert_eq!(cycle.len(), 4);
+        assert_eq!(cycle.first(), cycle.last());
+    }
+
+    #[test]
+    fn splits_conflicting_writers() {
+        let mut schedule = Schedule::default();
+
+        schedule
+            .add(SystemDef::shared(
+                "score_a",
+                AccessSet::new().write::<Score>(),
+                shared_noop,
+            ))
+            .add(SystemDef::shared(
+                "score_b",
+                AccessSet::new().write::<Score>(),
+                shared_noop,
+            ))
+            .add(SystemDef::shared(
+                "settings",
+                AccessSet::new().read::<Settings>(),
+                shared_noop,
+            ));
+
+        schedule.resolve().unwrap();
+
+        let systems = &schedule.resolved.as_ref().unwrap()[Stage::Update.index()];
+        let batch_names = |start| {
+            Schedule::shared_batch(systems, start)
+                .iter()
+                .map(|system| system.name)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(batch_names(0), ["score_a"]);
+        assert_eq!(batch_names(1), ["score_b", "settings"]);
+    }
+}
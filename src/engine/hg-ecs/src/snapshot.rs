@@ -0,0 +1,517 @@
+use std::{
+    cell::RefCell,
+    collections::BTreeMap,
+    context::{pack, unpack, Bundle},
+    fmt,
+};
+
+use hg_utils::hash::FxHashMap;
+use serde::{de::DeserializeOwned, Deserialize, Deserializer, Serialize, Serializer};
+use thiserror::Error;
+use thunderdome::Index;
+
+use crate::{
+    archetype::ComponentId,
+    bind,
+    entity::{Component, EntityStore},
+    world::ImmutableWorld,
+    AccessComp, Entity, Obj, World, WORLD,
+};
+
+// === ComponentSerde === //
+
+pub struct ComponentSerde {
+    /// The stable name identifying the component in serialized data.
+    pub name: &'static str,
+    pub serialize: fn(ImmutableWorld, Entity) -> serde_json::Result<serde_json::Value>,
+    pub deserialize: fn(&mut World, Entity, serde_json::Value) -> serde_json::Result<()>,
+}
+
+impl fmt::Debug for ComponentSerde {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ComponentSerde")
+            .field("name", &self.name)
+            .finish_non_exhaustive()
+    }
+}
+
+impl ComponentSerde {
+    pub const fn of<T: Component + Serialize + DeserializeOwned>() -> &'static Self {
+        struct Helper<T>(T);
+
+        impl<T: Component + Serialize + DeserializeOwned> Helper<T> {
+            const SERDE: &'static ComponentSerde = &ComponentSerde {
+                name: T::SERDE_NAME,
+                serialize: |world, entity| {
+                    let storage = world.read::<T::Arena>();
+
+                    serde_json::to_value(&storage[entity].value)
+                },
+                deserialize: |world, entity, value| {
+                    let value = world
+                        .reborrow()
+                        .immutable()
+                        .bind_tls(|| serde_json::from_value::<T>(value))?;
+
+                    bind!(world, let cx: &mut AccessComp<T>);
+
+                    // If the component already exists, this overwrites it in-place, which is what
+                    // the second instantiation pass relies upon.
+                    entity.add(value, pack!(cx));
+
+                    Ok(())
+                },
+            };
+        }
+
+        Helper::<T>::SERDE
+    }
+}
+
+// === EntitySnapshot === //
+
+#[derive(Debug, Error)]
+pub enum SnapshotError {
+    #[error("component `{0}` is not registered for serialization")]
+    UnknownComponent(String),
+    #[error("snapshot node {0} does not have a valid parent")]
+    MalformedTree(usize),
+    #[error("failed to serialize component `{name}`")]
+    Serialize {
+        name: &'static str,
+        #[source]
+        error: serde_json::Error,
+    },
+    #[error("failed to deserialize component `{name}`")]
+    Deserialize {
+        name: &'static str,
+        #[source]
+        error: serde_json::Error,
+    },
+}
+
+/// A serialized entity subtree. Nodes are stored in pre-order so parents always precede their
+/// children and the first node is the root of the subtree.
+///
+/// Only components registered through the `serde` option of `component!` are captured. They are
+/// keyed by the name given to that option rather than by type name so that snapshots survive
+/// refactors and compiler upgrades. References to entities and components inside the subtree are
+/// stored relative to the snapshot and remapped upon instantiation. References to anything outside
+/// the subtree are stored by raw ID and are therefore only meaningful when instantiating back into
+/// the world they were captured from.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct EntitySnapshot {
+    pub nodes: Vec<EntitySnapshotNode>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct EntitySnapshotNode {
+    /// The index of the parent node or `None` for the root of the snapshot.
+    pub parent: Option<u32>,
+
+    /// The serialized components of this node, keyed by their stable serialization name.
+    pub components: BTreeMap<String, serde_json::Value>,
+}
+
+impl EntitySnapshot {
+    pub fn capture(root: Entity, cx: Bundle<&mut WORLD>) -> Result<Self, SnapshotError> {
+        let world = unpack!(cx => &mut WORLD).reborrow();
+        let world = world.immutable();
+        let store = world.read::<EntityStore>();
+
+        // Assign local indices in pre-order.
+        let (order, local_ids) = pre_order(store, root);
+
+        // Serialize their components.
+        let _guard = bind_capture_state(local_ids);
+
+        let nodes = world.bind_tls(|| {
+            order
+                .iter()
+                .map(|&(entity, parent)| {
+                    let mut components = BTreeMap::new();
+
+                    for comp in store.components(entity) {
+                        let Some(serde) = comp.serde else {
+                            continue;
+                        };
+
+                        let value = (serde.serialize)(world, entity).map_err(|error| {
+                            SnapshotError::Serialize {
+                                name: (comp.type_name)(),
+                                error,
+                            }
+                        })?;
+
+                        components.insert(serde.name.to_string(), value);
+                    }
+
+                    Ok(EntitySnapshotNode { parent, components })
+                })
+                .collect::<Result<Vec<_>, SnapshotError>>()
+        })?;
+
+        Ok(Self { nodes })
+    }
+
+    pub fn instantiate(
+        &self,
+        parent: Entity,
+        cx: Bundle<&mut WORLD>,
+    ) -> Result<Entity, SnapshotError> {
+        let world = unpack!(cx => &mut WORLD);
+
+        // Validate the snapshot before spawning anything so that errors here never leave a
+        // partially-constructed tree behind.
+        for (i, node) in self.nodes.iter().enumerate() {
+            let valid = match node.parent {
+                None => i == 0,
+                Some(parent) => (parent as usize) < i,
+            };
+
+            if !valid {
+                return Err(SnapshotError::MalformedTree(i));
+            }
+        }
+
+        let kinds = self
+            .nodes
+            .iter()
+            .map(|node| {
+                node.components
+                    .keys()
+                    .map(|name| {
+                        ComponentId::lookup_serde(name)
+                            .ok_or_else(|| SnapshotError::UnknownComponent(name.clone()))
+                    })
+                    .collect::<Result<Vec<_>, _>>()
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        // Spawn the entity tree.
+        let entities = {
+            bind!(world);
+
+            let mut entities = Vec::with_capacity(self.nodes.len());
+
+            for node in &self.nodes {
+                let node_parent = node.parent.map_or(parent, |idx| entities[idx as usize]);
+                entities.push(Entity::new(node_parent));
+            }
+
+            entities
+        };
+
+        let Some(&root) = entities.first() else {
+            return Err(SnapshotError::MalformedTree(0));
+        };
+
+        // Deserialize components in two passes. The first pass creates every component with
+        // references into the snapshot left dangling. The second pass, now that every component
+        // has a handle, deserializes them again and overwrites the first pass' values.
+        for resolve_objs in [false, true] {
+            let _guard = bind_snapshot_state(SnapshotState::Instantiate {
+                entities: entities.clone(),
+                resolve_objs,
+            });
+
+            for ((node, &entity), kinds) in self.nodes.iter().zip(&entities).zip(&kinds) {
+                for (comp, value) in kinds.iter().zip(node.components.values()) {
+                    let res = (comp.serde.unwrap().deserialize)(world, entity, value.clone());
+
+                    if let Err(error) = res {
+                        // Destroy the subtree right away so that queries never observe it.
+                        bind!(world);
+                        root.destroy_now();
+
+                        return Err(SnapshotError::Deserialize {
+                            name: (comp.type_name)(),
+                            error,
+                        });
+                    }
+                }
+            }
+        }
+
+        Ok(root)
+    }
+}
+
+// === Reference Remapping === //
+
+/// Lists the subtree rooted at `root` in pre-order alongside the index of each node's parent. The
+/// returned map assigns each entity its index in that list.
+pub(crate) fn pre_order(
+    store: &EntityStore,
+    root: Entity,
+) -> (Vec<(Entity, Option<u32>)>, FxHashMap<Entity, u32>) {
+    let mut order = Vec::<(Entity, Option<u32>)>::new();
+    let mut local_ids = FxHashMap::default();
+    let mut stack = vec![(root, None)];
+
+    while let Some((entity, parent)) = stack.pop() {
+        let local_id = u32::try_from(order.len()).expect("too many entities in subtree");
+        local_ids.insert(entity, local_id);
+        order.push((entity, parent));
+
+        for child in store.children(entity).into_iter().rev() {
+            stack.push((child, Some(local_id)));
+        }
+    }
+
+    (order, local_ids)
+}
+
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+enum SnapshotRef {
+    /// Refers to the node with the given index in the snapshot.
+    Local(u32),
+
+    /// Refers to something outside of the snapshot by its raw ID.
+    External(u64),
+
+    Dangling,
+}
+
+enum SnapshotState {
+    Capture {
+        local_ids: FxHashMap<Entity, u32>,
+    },
+    Instantiate {
+        entities: Vec<Entity>,
+        resolve_objs: bool,
+    },
+
+    /// Every reference is (de)serialized by its raw ID.
+    Reflect,
+}
+
+thread_local! {
+    static SNAPSHOT_STATE: RefCell<Option<SnapshotState>> = const { RefCell::new(None) };
+}
+
+fn bind_snapshot_state(state: SnapshotState) -> impl Sized {
+    let old = SNAPSHOT_STATE.replace(Some(state));
+
+    scopeguard::guard(old, |old| {
+        SNAPSHOT_STATE.set(old);
+    })
+}
+
+/// Makes `Entity` and `Obj` serialize references to the given entities as their local indices for
+/// as long as the returned guard is alive.
+pub(crate) fn bind_capture_state(local_ids: FxHashMap<Entity, u32>) -> impl Sized {
+    bind_snapshot_state(SnapshotState::Capture { local_ids })
+}
+
+/// Makes `Entity` and `Obj` (de)serialize every reference by its raw ID for as long as the
+/// returned guard is alive.
+pub(crate) fn bind_reflect_state() -> impl Sized {
+    bind_snapshot_state(SnapshotState::Reflect)
+}
+
+const NOT_IN_CAPTURE_ERR: &str =
+    "entity references can only be serialized while capturing an `EntitySnapshot`";
+
+const NOT_IN_INSTANTIATE_ERR: &str =
+    "entity references can only be deserialized while instantiating an `EntitySnapshot`";
+
+fn capture_ref(owner: Entity, raw: Index) -> Result<SnapshotRef, &'static str> {
+    SNAPSHOT_STATE.with_borrow(|state| match state {
+        Some(SnapshotState::Capture { local_ids }) => Ok(match local_ids.get(&owner) {
+            Some(&local) => SnapshotRef::Local(local),
+            None => SnapshotRef::External(raw.to_bits()),
+        }),
+        Some(SnapshotState::Reflect) => Ok(SnapshotRef::External(raw.to_bits())),
+        _ => Err(NOT_IN_CAPTURE_ERR),
+    })
+}
+
+fn resolve_ref<R>(
+    target: SnapshotRef,
+    dangling: R,
+    resolve_local: impl FnOnce(Entity, bool) -> Result<R, String>,
+    resolve_external: impl FnOnce(Index) -> R,
+) -> Result<R, String> {
+    SNAPSHOT_STATE.with_borrow(|state| {
+        let (entities, resolve_objs) = match state {
+            Some(SnapshotState::Instantiate {
+                entities,
+                resolve_objs,
+            }) => (&entities[..], *resolve_objs),
+            Some(SnapshotState::Reflect) => (&[][..], true),
+            _ => return Err(NOT_IN_INSTANTIATE_ERR.to_string()),
+        };
+
+        match target {
+            SnapshotRef::Local(idx) => {
+                let entity = *entities
+                    .get(idx as usize)
+                    .ok_or_else(|| format!("snapshot does not have a node at index {idx}"))?;
+
+                resolve_local(entity, resolve_objs)
+            }
+            SnapshotRef::External(bits) => Index::from_bits(bits)
+                .map(resolve_external)
+                .ok_or_else(|| format!("invalid raw ID 0x{bits:x}")),
+            SnapshotRef::Dangling => Ok(dangling),
+        }
+    })
+}
+
+impl Serialize for Entity {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let target = if *self == Entity::DANGLING {
+            SnapshotRef::Dangling
+        } else {
+            capture_ref(*self, self.raw()).map_err(serde::ser::Error::custom)?
+        };
+
+        target.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Entity {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        resolve_ref(
+            SnapshotRef::deserialize(deserializer)?,
+            Entity::DANGLING,
+            |entity, _resolve_objs| Ok(entity),
+            Entity::from_raw,
+        )
+        .map_err(serde::de::Error::custom)
+    }
+}
+
+impl<T: Component> Serialize for Obj<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let target = ImmutableWorld::try_use_tls(|world| {
+            let world = world.ok_or(NOT_IN_CAPTURE_ERR)?;
+
+            match world.read::<T::Arena>().get(Obj::raw(*self)) {
+                Some(slot) => capture_ref(slot.owner, Obj::raw(*self)),
+                None => Ok(SnapshotRef::Dangling),
+            }
+        })
+        .map_err(serde::ser::Error::custom)?;
+
+        target.serialize(serializer)
+    }
+}
+
+impl<'de, T: Component> Deserialize<'de> for Obj<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        resolve_ref(
+            SnapshotRef::deserialize(deserializer)?,
+            Obj::DANGLING,
+            |entity, resolve_objs| {
+                // During the first pass, the component being referenced may not exist yet.
+                if !resolve_objs {
+                    return Ok(Obj::DANGLING);
+                }
+
+                ImmutableWorld::try_use_tls(|world| {
+                    let world = world.ok_or(NOT_IN_INSTANTIATE_ERR)?;
+
+                    world
+                        .read::<T::Arena>()
+                        .get_by_entity(entity)
+                        .map(|slot| Obj::from_raw(slot.handle))
+                        .ok_or_else(|| {
+                            format!(
+                                "snapshot references a `{}` its node does not have",
+                                std::any::type_name::<T>(),
+                            )
+                        })
+                })
+            },
+            Obj::from_raw,
+        )
+        .map_err(serde::de::Error::custom)
+    }
+}
+
+// === Tests === //
+
+#[cfg(test)]
+mod tests {
+    use serde::{Deserialize, Serialize};
+
+    use crate::{bind, component, Entity, Obj, World};
+
+    use super::{EntitySnapshot, EntitySnapshotNode, SnapshotError};
+
+    #[derive(Debug, Serialize, Deserialize)]
+    pub struct Health(u32);
+
+    #[derive(Debug, Serialize, Deserialize)]
+    pub struct Target(Obj<Health>);
+
+    component!(Health { serde: "health" }, Target { serde: "target" });
+
+    fn spawn_pair(world: &mut World) -> Entity {
+        bind!(world);
+
+        let parent = Entity::new(Entity::root());
+        let health = Entity::new(parent).add(Health(42));
+        parent.add(Target(health));
+        parent
+    }
+
+    fn check_pair(world: &mut World, parent: Entity) {
+        bind!(world);
+
+        let target = parent.get::<Target>().0;
+        assert_eq!(target.entity().parent(), Some(parent));
+        assert_eq!(target.0, 42);
+    }
+
+    #[test]
+    fn round_trip_remaps_objs() {
+        let mut world = World::new();
+        let original = spawn_pair(&mut world);
+
+        let snapshot = {
+            bind!(&mut world);
+            EntitySnapshot::capture(original).unwrap()
+        };
+
+        let snapshot = serde_json::from_str::<EntitySnapshot>(
+            &serde_json::to_string(&snapshot).unwrap(),
+        )
+        .unwrap();
+
+        let copy = {
+            bind!(&mut world);
+            snapshot.instantiate(Entity::root()).unwrap()
+        };
+
+        assert_ne!(original, copy);
+        check_pair(&mut world, original);
+        check_pair(&mut world, copy);
+    }
+
+    #[test]
+    fn failed_instantiation_leaves_nothing_behind() {
+        let mut world = World::new();
+        bind!(world);
+
+        let node = |parent, health| EntitySnapshotNode {
+            parent,
+            components: [("health".to_string(), health)].into(),
+        };
+
+        let snapshot = EntitySnapshot {
+            nodes: vec![
+                node(None, serde_json::json!(1)),
+                node(Some(0), serde_json::json!("not a number")),
+            ],
+        };
+
+        let res = snapshot.instantiate(Entity::root());
+        assert!(matches!(res, Err(SnapshotError::Deserialize { .. })));
+
+        // Deferred destruction would have left the partial subtree attached until the next flush.
+        assert_eq!(Entity::root().children().len(), 0);
+    }
+}
@@ -0,0 +1,276 @@
+use std::{
+    context::{unpack, Bundle},
+    fmt, mem, ops,
+};
+
+use hg_utils::hash::{FxHashMap, FxHashSet};
+use thunderdome::Arena;
+
+use crate::{
+    archetype::{ArchetypeId, ComponentId},
+    entity::{EntityStore, StorageKind},
+    WORLD,
+};
+
+// === FlushChurn === //
+
+/// Counts the structural changes applied to the archetype members by `Entity::flush`.
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+pub struct FlushChurn {
+    /// The number of flushes over which these changes were counted.
+    pub flushes: u64,
+
+    /// The number of live entities moved from one archetype to another.
+    pub reshaped: u64,
+
+    /// The number of destroyed entities removed from a non-empty archetype.
+    pub destroyed: u64,
+}
+
+impl ops::AddAssign for FlushChurn {
+    fn add_assign(&mut self, rhs: Self) {
+        self.flushes += rhs.flushes;
+        self.reshaped += rhs.reshaped;
+        self.destroyed += rhs.destroyed;
+    }
+}
+
+impl fmt::Display for FlushChurn {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} reshaped and {} destroyed over {} flush(es)",
+            self.reshaped, self.destroyed, self.flushes,
+        )
+    }
+}
+
+// === MemoryStats === //
+
+#[derive(Debug, Copy, Clone, Default)]
+pub struct StorageStats {
+    pub kind: StorageKind,
+
+    /// The number of live components.
+    pub len: usize,
+
+    /// The number of components the arena can hold without reallocating.
+    pub capacity: usize,
+
+    /// An estimate of the heap memory used by the storage, excluding memory owned by the
+    /// components themselves.
+    pub bytes: usize,
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct ComponentStats {
+    pub comp: ComponentId,
+    pub storage: StorageStats,
+
+    /// The number of archetypes containing this component.
+    pub archetypes: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct ArchetypeStats {
+    pub id: ArchetypeId,
+    pub comps: Vec<ComponentId>,
+
+    /// The number of entities in the archetype as of the last `Entity::flush`.
+    pub members: usize,
+}
+
+/// A snapshot of the memory used by the entity store and by every component storage, meant to
+/// help track down leaks.
+///
+/// Byte counts are estimates derived from the capacity of each collection and the size of its
+/// elements. They do not account for allocator overhead or for memory owned by the components.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryStats {
+    /// The number of live entities.
+    pub entities: usize,
+
+    /// An estimate of the heap memory used by the `EntityStore`, including its archetypes.
+    pub store_bytes: usize,
+
+    /// The number of `Entity::descendants_with` results currently cached.
+    pub cached_subtrees: usize,
+
+    /// Every component storage which has been used, from largest to smallest.
+    pub comps: Vec<ComponentStats>,
+
+    /// Every archetype which has been created, excluding the empty archetype, in creation order.
+    pub archetypes: Vec<ArchetypeStats>,
+
+    /// The churn of the last `Entity::flush`.
+    pub last_flush: FlushChurn,
+
+    /// The churn of every `Entity::flush` since the world was created.
+    pub total_churn: FlushChurn,
+}
+
+impl MemoryStats {
+    pub fn collect(cx: Bundle<&mut WORLD>) -> Self {
+        let world = unpack!(cx => &mut WORLD).reborrow();
+        let world = world.immutable();
+        let store = world.read::<EntityStore>();
+        let archetypes = store.archetypes();
+
+        let mut comps = Vec::new();
+
+        for comp in ComponentId::all() {
+            let storage = (comp.storage_stats)(world);
+
+            // Skip storages which were never used rather than listing every registered component.
+            if storage.capacity == 0 && archetypes.archetypes_with(comp).is_empty() {
+                continue;
+            }
+
+            comps.push(ComponentStats {
+                comp,
+                storage,
+                archetypes: archetypes.archetypes_with(comp).len(),
+            });
+        }
+
+        comps.sort_by(|a, b| b.storage.bytes.cmp(&a.storage.bytes));
+
+        let archetypes = archetypes
+            .ids()
+            .filter(|&id| id != ArchetypeId::EMPTY)
+            .map(|id| ArchetypeStats {
+                id,
+                comps: archetypes.components(id).to_vec(),
+                members: store.archetype_member_count(id),
+            })
+            .collect();
+
+        Self {
+            entities: store.entity_count(),
+            store_bytes: store.estimated_bytes(),
+            cached_subtrees: store.cached_subtree_count(),
+            comps,
+            archetypes,
+            last_flush: store.last_flush_churn(),
+            total_churn: store.total_flush_churn(),
+        }
+    }
+
+    /// The estimated heap memory used by the store and by every component storage.
+    pub fn total_bytes(&self) -> usize {
+        self.store_bytes
+            + self
+                .comps
+                .iter()
+                .map(|comp| comp.storage.bytes)
+                .sum::<usize>()
+    }
+}
+
+impl fmt::Display for MemoryStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} entities, {} archetypes, {} cached subtrees, ~{} bytes total (~{} in the store)",
+            self.entities,
+            self.archetypes.len() + 1,
+            self.cached_subtrees,
+            self.total_bytes(),
+            self.store_bytes,
+        )?;
+
+        writeln!(f, "last flush: {}", self.last_flush)?;
+        writeln!(f, "all flushes: {}", self.total_churn)?;
+
+        writeln!(f, "components:")?;
+
+        for comp in &self.comps {
+            writeln!(
+                f,
+                "  {}: {} of {} slots ({:?}), ~{} bytes, in {} archetype(s)",
+                (comp.comp.type_name)(),
+                comp.storage.len,
+                comp.storage.capacity,
+                comp.storage.kind,
+                comp.storage.bytes,
+                comp.archetypes,
+            )?;
+        }
+
+        writeln!(f, "archetypes:")?;
+
+        for arch in &self.archetypes {
+            write!(f, "  {:?}: {} member(s) with", arch.id, arch.members)?;
+
+            for comp in &arch.comps {
+                write!(f, " {}", (comp.type_name)())?;
+            }
+
+            writeln!(f)?;
+        }
+
+        Ok(())
+    }
+}
+
+// === Estimates === //
+
+// These are rough: hash tables are assumed to have one control byte per bucket and arena entries
+// are assumed to be their value alongside a 64-bit tag and generation.
+
+pub(crate) fn vec_bytes<T>(vec: &Vec<T>) -> usize {
+    vec.capacity() * mem::size_of::<T>()
+}
+
+pub(crate) fn map_bytes<K, V>(map: &FxHashMap<K, V>) -> usize {
+    map.capacity() * (mem::size_of::<(K, V)>() + 1)
+}
+
+pub(crate) fn set_bytes<T>(set: &FxHashSet<T>) -> usize {
+    set.capacity() * (mem::size_of::<T>() + 1)
+}
+
+pub(crate) fn arena_bytes<T>(arena: &Arena<T>) -> usize {
+    arena.capacity() * mem::size_of::<(u64, T)>()
+}
+
+// === Tests === //
+
+#[cfg(test)]
+mod tests {
+    use crate::{bind, component, Entity, World};
+
+    use super::MemoryStats;
+
+    #[derive(Debug)]
+    pub struct Crate(u32);
+
+    component!(Crate);
+
+    #[test]
+    fn counts_members_and_churn() {
+        let mut world = World::new();
+        bind!(world);
+
+        let first = Entity::new(Entity::root()).with(Crate(1));
+        Entity::new(Entity::root()).with(Crate(2));
+        Entity::flush(|_world| {});
+
+        first.destroy();
+        Entity::flush(|_world| {});
+
+        let stats = MemoryStats::collect();
+        let crates = stats
+            .comps
+            .iter()
+            .find(|comp| (comp.comp.type_name)().ends_with("Crate"))
+            .unwrap();
+
+        assert_eq!(crates.storage.len, 1);
+        assert_eq!(stats.archetypes.len(), 1);
+        assert_eq!(stats.archetypes[0].members, 1);
+        assert_eq!(stats.last_flush.destroyed, 1);
+        assert_eq!(stats.total_churn.reshaped, 2);
+        assert_eq!(stats.total_churn.flushes, 2);
+    }
+}
@@ -1,18 +1,20 @@
 use std::{
-    any::{type_name, TypeId},
+    any::{type_name, Any, TypeId},
     cell::{Cell, RefCell, UnsafeCell},
     context::{
         unpack, Bundle, BundleItemRequest, BundleItemResponse, BundleItemSetFor, ContextItem,
     },
     fmt,
     marker::PhantomData,
+    mem,
     num::NonZeroUsize,
-    ptr::NonNull,
+    ptr::{self, NonNull},
     rc::Rc,
     sync::{
-        atomic::{AtomicBool, AtomicPtr, Ordering::*},
+        atomic::{AtomicUsize, Ordering::*},
         OnceLock,
     },
+    thread::LocalKey,
 };
 
 use hg_utils::hash::{hash_map::Entry, FxHashMap, FxHashSet};
@@ -27,7 +29,17 @@ use crate::{archetype::ComponentId, entity::Component, Entity, Obj};
 #[context]
 pub static WORLD: World;
 
-static HAS_WORLD: AtomicBool = AtomicBool::new(false);
+/// The source of `World::curr_origin` values. These are allocated from a process-wide counter so
+/// that the [`AccessToken`]s of one world can never be mistaken for those of another.
+static NEXT_ORIGIN: AtomicUsize = AtomicUsize::new(1);
+
+fn alloc_origin() -> NonZeroUsize {
+    NEXT_ORIGIN
+        .fetch_update(Relaxed, Relaxed, |v| v.checked_add(1))
+        .ok()
+        .and_then(NonZeroUsize::new)
+        .expect("allocated too many world and reborrow origins")
+}
 
 pub struct World {
     /// This is a single-threaded object.
@@ -38,6 +50,17 @@ pub struct World {
 
     /// This is the set of lazily-initialized resources that this world provides.
     resources: UnsafeCell<FxHashMap<TypeId, Rc<dyn ErasedResourceValue>>>,
+
+    /// The order in which `resources` were created. They are dropped in the reverse order.
+    resource_order: UnsafeCell<Vec<TypeId>>,
+
+    /// The number of live [`WorldReborrow`]s. Bindings cache pointers to resources so these can
+    /// only be removed while this is zero.
+    bind_depth: u32,
+
+    /// The tick with which component insertions and mutable accesses are stamped for change
+    /// detection. This is advanced at the end of every `Entity::flush`.
+    change_tick: u32,
 }
 
 impl fmt::Debug for World {
@@ -48,48 +71,120 @@ impl fmt::Debug for World {
 
 impl World {
     pub fn new() -> Self {
-        let singleton_ok = HAS_WORLD
-            .compare_exchange(false, true, Relaxed, Relaxed)
-            .is_ok();
-
-        assert!(singleton_ok, "process already has a `World`");
-
         Self {
             _no_send_sync: PhantomData,
-            curr_origin: NonZeroUsize::new(1).unwrap(),
+            curr_origin: alloc_origin(),
             resources: UnsafeCell::default(),
+            resource_order: UnsafeCell::default(),
+            bind_depth: 0,
+            change_tick: 0,
         }
     }
 
+    pub fn change_tick(&self) -> u32 {
+        self.change_tick
+    }
+
+    pub(crate) fn advance_change_tick(&mut self) {
+        self.change_tick = self.change_tick.wrapping_add(1);
+    }
+
     pub fn single<T: Resource>(&self) -> *mut T {
+        let res = self.resource_value(TypeId::of::<T::Cx>(), ResourceValue::<T>::create);
+
+        // Okay because `ResourceValue<T>` is `repr(transparent)` around `T`
+        res as *const T as *mut T
+    }
+
+    fn resource_value(
+        &self,
+        ctx_ty: TypeId,
+        ctor: impl FnOnce() -> Rc<dyn ErasedResourceValue>,
+    ) -> *const dyn ErasedResourceValue {
         let resources = unsafe { &mut *self.resources.get() };
 
-        let res = resources
-            .entry(TypeId::of::<T::Cx>())
-            .or_insert_with(|| Rc::<ResourceValue<T>>::default());
+        let res = resources.entry(ctx_ty).or_insert_with(|| {
+            unsafe { &mut *self.resource_order.get() }.push(ctx_ty);
+            ctor()
+        });
 
-        // Okay because `ResourceValue<T>` is `repr(transparent)` around `T`
-        Rc::as_ptr(res) as *const T as *mut T
+        Rc::as_ptr(res)
+    }
+
+    /// Inserts a resource, overriding the existing instance if there is one. Resources defined
+    /// with the `explicit` option of `resource!` must be inserted this way before they're used.
+    ///
+    /// Existing instances are overwritten in-place and returned rather than dropped.
+    pub fn insert_resource<T: Resource>(&mut self, value: T) -> Option<T> {
+        let ctx_ty = TypeId::of::<T::Cx>();
+
+        if let Some(res) = self.resources.get_mut().get(&ctx_ty) {
+            // Bindings may have cached pointers to the existing instance so we can't replace it.
+            let res = Rc::as_ptr(res) as *const T as *mut T;
+
+            return Some(mem::replace(unsafe { &mut *res }, value));
+        }
+
+        self.resources
+            .get_mut()
+            .insert(ctx_ty, Rc::new(ResourceValue::new(value)));
+
+        self.resource_order.get_mut().push(ctx_ty);
+
+        None
+    }
+
+    /// Removes a resource and returns it. Accessing it afterwards creates a new instance or panics
+    /// if the resource has no default value.
+    pub fn remove_resource<T: Resource>(&mut self) -> Option<T> {
+        assert_eq!(
+            self.bind_depth,
+            0,
+            "cannot remove `{}` while the world is bound",
+            type_name::<T>(),
+        );
+
+        let ctx_ty = TypeId::of::<T::Cx>();
+        let res = self.resources.get_mut().remove(&ctx_ty)?;
+
+        self.resource_order
+            .get_mut()
+            .retain(|&other| other != ctx_ty);
+
+        let res = res.into_any().downcast::<ResourceValue<T>>().unwrap();
+
+        Some(Rc::into_inner(res).unwrap().value.into_inner())
     }
 
     pub fn reborrow(&mut self) -> WorldReborrow<'_> {
         // Invalidate all previous tokens.
-        let prev_token = self.curr_origin;
-        self.curr_origin = self
-            .curr_origin
-            .checked_add(1)
-            .expect("too many nested bundle creations");
+        let prev_origin = self.curr_origin;
+        let origin = alloc_origin();
+        self.curr_origin = origin;
+        self.bind_depth += 1;
 
         WorldReborrow {
             world: self,
-            prev_origin: prev_token,
+            prev_origin,
+            origin,
+            clobbered_slots: Vec::new(),
         }
     }
-}
 
-impl Drop for World {
-    fn drop(&mut self) {
-        HAS_WORLD.store(false, Relaxed);
+    /// Runs `f` under a fresh origin so that every [`AccessToken`] fetched from the enclosing
+    /// bindings is rejected until it returns. The world is also unbound from the thread's
+    /// [`ImmutableWorld`] so that `f` cannot reach resources through it either.
+    pub(crate) fn isolate<R>(&mut self, f: impl FnOnce() -> R) -> R {
+        let prev_origin = mem::replace(&mut self.curr_origin, alloc_origin());
+        let _restore_origin = scopeguard::guard(self, |world| {
+            world.curr_origin = prev_origin;
+        });
+
+        let _restore_tls = scopeguard::guard(ImmutableWorld::TLS_WORLD.take(), |old| {
+            ImmutableWorld::TLS_WORLD.set(old);
+        });
+
+        f()
     }
 }
 
@@ -97,6 +192,12 @@ impl Drop for World {
 pub struct WorldReborrow<'a> {
     world: &'a mut World,
     prev_origin: NonZeroUsize,
+    origin: NonZeroUsize,
+
+    /// The resource slots overwritten by `bundle` alongside their previous state. These are
+    /// restored in reverse order when the reborrow is dropped so that enclosing
+    /// bindings—potentially to other worlds—observe their own resources again.
+    clobbered_slots: Vec<(*const dyn ErasedResourceValue, ResourceSlotState)>,
 }
 
 impl WorldReborrow<'_> {
@@ -123,25 +224,19 @@ impl WorldReborrow<'_> {
                 }
 
                 // Otherwise, fetch the resource.
-                let comp = self
-                    .world
-                    .resources
-                    .get_mut()
-                    .entry(req.marker_type_id())
-                    .or_insert_with(|| {
-                        let info =
-                            ResourceInfo::lookup(req.marker_type_id()).unwrap_or_else(|| {
-                                panic!(
-                                    "cannot provide `{}` (pointee `{}`): not a resource",
-                                    req.marker_name(),
-                                    req.pointee_name()
-                                );
-                            });
-
-                        (info.ctor)()
+                let comp = self.world.resource_value(req.marker_type_id(), || {
+                    let info = ResourceInfo::lookup(req.marker_type_id()).unwrap_or_else(|| {
+                        panic!(
+                            "cannot provide `{}` (pointee `{}`): not a resource",
+                            req.marker_name(),
+                            req.pointee_name()
+                        );
                     });
 
-                Provider::Storage(Rc::as_ptr(comp))
+                    (info.ctor)()
+                });
+
+                Provider::Storage(comp)
             })
             .collect::<Vec<_>>();
 
@@ -149,6 +244,7 @@ impl WorldReborrow<'_> {
         let curr_origin = self.world.curr_origin;
         let mut world = Some(&mut *self.world);
         let mut borrows = FxHashMap::default();
+        let clobbered_slots = &mut self.clobbered_slots;
 
         let mut providers = providers.into_iter();
 
@@ -181,7 +277,9 @@ impl WorldReborrow<'_> {
                 }
 
                 // Provide the actual value
-                unsafe { (*val).provide(req, curr_origin) }
+                let (resp, prev_slot) = unsafe { (*val).provide(req, curr_origin) };
+                clobbered_slots.push((val, prev_slot));
+                resp
             }
         })
     }
@@ -194,12 +292,15 @@ impl<'a> Drop for WorldReborrow<'a> {
         // be reused.
 
         // Of course, we need to ensure that we're not being dropped out of order.
-        assert_eq!(
-            self.prev_origin.checked_add(1),
-            Some(self.world.curr_origin)
-        );
+        assert_eq!(self.origin, self.world.curr_origin);
+
+        for (res, prev_slot) in self.clobbered_slots.drain(..).rev() {
+            // Resources live for as long as their world, which outlives us.
+            unsafe { (*res).restore(prev_slot) };
+        }
 
         self.world.curr_origin = self.prev_origin;
+        self.world.bind_depth -= 1;
     }
 }
 
@@ -221,6 +322,10 @@ impl<'a> ImmutableWorld<'a> {
         f()
     }
 
+    pub(crate) fn id(self) -> usize {
+        self.0 as *const UnsafeCell<World> as usize
+    }
+
     pub fn try_use_tls<R>(f: impl FnOnce(Option<ImmutableWorld<'_>>) -> R) -> R {
         let world = Self::TLS_WORLD
             .get()
@@ -237,33 +342,140 @@ impl<'a> ImmutableWorld<'a> {
     }
 }
 
+impl Drop for World {
+    fn drop(&mut self) {
+        // Resources may depend upon the resources created before them so tear them down in the
+        // reverse order.
+        let resources = self.resources.get_mut();
+
+        for ctx_ty in self.resource_order.get_mut().drain(..).rev() {
+            drop(resources.remove(&ctx_ty));
+        }
+    }
+}
+
 // ResourceValue
-#[derive(Default)]
 #[repr(transparent)]
 struct ResourceValue<T: Resource> {
     value: UnsafeCell<T>,
 }
 
+impl<T: Resource> ResourceValue<T> {
+    fn new(value: T) -> Self {
+        Self {
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    fn create() -> Rc<dyn ErasedResourceValue> {
+        let ctor = T::DEFAULT.unwrap_or_else(|| {
+            panic!(
+                "`{}` has no default value and must be inserted through `World::insert_resource` \
+                 before it's used",
+                type_name::<T>(),
+            )
+        });
+
+        Rc::new(Self::new(ctor()))
+    }
+}
+
 trait ErasedResourceValue {
+    fn into_any(self: Rc<Self>) -> Rc<dyn Any>;
+
     unsafe fn provide<'a, 'm>(
         &'a self,
         req: BundleItemRequest<'a, 'm>,
         curr_token: NonZeroUsize,
-    ) -> BundleItemResponse<'m>;
+    ) -> (BundleItemResponse<'m>, ResourceSlotState);
+
+    unsafe fn restore(&self, state: ResourceSlotState);
 }
 
 impl<T: Resource> ErasedResourceValue for ResourceValue<T> {
+    fn into_any(self: Rc<Self>) -> Rc<dyn Any> {
+        self
+    }
+
     unsafe fn provide<'a, 'm>(
         &'a self,
         req: BundleItemRequest<'a, 'm>,
         curr_token: NonZeroUsize,
-    ) -> BundleItemResponse<'m> {
-        // It's okay to clobber this value because it is either dangling and inaccessible or already
-        // points to the same thing we just replaced it with.
-        T::slot().store(self.value.get(), Relaxed);
+    ) -> (BundleItemResponse<'m>, ResourceSlotState) {
+        // The previous slot state is handed back to the `WorldReborrow` so that it can be restored
+        // once this binding ends.
+        let prev = T::slot().with(|slot| {
+            slot.replace(ResourceSlotState {
+                origin: Some(curr_token),
+                value: self.value.get().cast(),
+            })
+        });
 
         // Safety provided by caller.
-        req.provide_mut(AccessToken::<T>::new(curr_token))
+        (req.provide_mut(AccessToken::<T>::new(curr_token)), prev)
+    }
+
+    unsafe fn restore(&self, state: ResourceSlotState) {
+        T::slot().with(|slot| slot.replace(state));
+    }
+}
+
+// ResourceSlot
+#[derive(Debug, Copy, Clone)]
+struct ResourceSlotState {
+    origin: Option<NonZeroUsize>,
+    value: *mut (),
+}
+
+/// A thread-local cache of the resource most recently provided by a bundle, tagged with the origin
+/// of the [`AccessToken`] it was provided alongside.
+pub struct ResourceSlot<T> {
+    origin: Cell<Option<NonZeroUsize>>,
+    value: Cell<*mut T>,
+}
+
+impl<T> fmt::Debug for ResourceSlot<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ResourceSlot")
+            .field("origin", &self.origin.get())
+            .field("value", &self.value.get())
+            .finish()
+    }
+}
+
+impl<T> ResourceSlot<T> {
+    pub const fn new() -> Self {
+        Self {
+            origin: Cell::new(None),
+            value: Cell::new(ptr::null_mut()),
+        }
+    }
+
+    fn replace(&self, state: ResourceSlotState) -> ResourceSlotState {
+        ResourceSlotState {
+            origin: self.origin.replace(state.origin),
+            value: self.value.replace(state.value.cast()).cast(),
+        }
+    }
+
+    fn get(&self, origin: NonZeroUsize) -> *mut T {
+        // Worker threads never have resources bound to them so this is most likely a shared system
+        // trying to use implicit context.
+        assert!(
+            self.origin.get().is_some(),
+            "`{}` is not bound on this thread; shared systems must access it through their \
+             `SharedWorld`",
+            type_name::<T>(),
+        );
+
+        assert_eq!(
+            self.origin.get(),
+            Some(origin),
+            "`{}` was provided by a different binding than the one being accessed",
+            type_name::<T>(),
+        );
+
+        self.value.get()
     }
 }
 
@@ -299,10 +511,15 @@ pub type AccessRes<R> = <R as Resource>::Cx;
 pub type AccessResRef<'a, R> = (&'a WORLD, &'a AccessRes<R>);
 pub type AccessResMut<'a, R> = (&'a WORLD, &'a mut AccessRes<R>);
 
-pub unsafe trait Resource: Sized + 'static + Default {
+pub unsafe trait Resource: Sized + 'static {
     type Cx: ContextItem<Item = AccessToken<Self>>;
 
-    fn slot() -> &'static AtomicPtr<Self>;
+    /// Creates the resource upon first use if it hasn't been inserted through
+    /// [`World::insert_resource`]. This is `None` for resources defined with the `explicit` option
+    /// of `resource!`.
+    const DEFAULT: Option<fn() -> Self>;
+
+    fn slot() -> &'static LocalKey<ResourceSlot<Self>>;
 
     fn fetch<'a>(cx: Bundle<AccessResRef<'a, Self>>) -> &'a Self {
         let world = unpack!(cx => &WORLD);
@@ -310,7 +527,7 @@ pub unsafe trait Resource: Sized + 'static + Default {
 
         assert_eq!(world.curr_origin, token.id());
 
-        unsafe { &*Self::slot().load(Relaxed) }
+        unsafe { &*Self::slot().with(|slot| slot.get(token.id())) }
     }
 
     fn fetch_mut<'a>(cx: Bundle<AccessResMut<'a, Self>>) -> &'a mut Self {
@@ -319,7 +536,7 @@ pub unsafe trait Resource: Sized + 'static + Default {
 
         assert_eq!(world.curr_origin, token.id());
 
-        unsafe { &mut *Self::slot().load(Relaxed) }
+        unsafe { &mut *Self::slot().with(|slot| slot.get(token.id())) }
     }
 }
 
@@ -338,7 +555,7 @@ impl ResourceInfo {
         Self {
             name: type_name::<T>(),
             ctx_ty: TypeId::of::<T::Cx>(),
-            ctor: || Rc::<ResourceValue<T>>::default(),
+            ctor: ResourceValue::<T>::create,
         }
     }
 
@@ -362,15 +579,24 @@ impl ResourceInfo {
 #[doc(hidden)]
 pub mod resource_internals {
     pub use {
-        super::{AccessToken, Resource, ResourceInfo, RESOURCES},
+        super::{AccessToken, Resource, ResourceInfo, ResourceSlot, RESOURCES},
+        crate::resource_default,
         linkme::{self, distributed_slice},
-        std::{ptr::null_mut, sync::atomic::AtomicPtr},
+        std::{
+            default::Default,
+            option::Option::{self, None, Some},
+            thread::LocalKey,
+            thread_local,
+        },
     };
 }
 
+/// Defines one or more resource types. By default, resources are created through their `Default`
+/// implementation upon first use. Resources followed by `{ explicit }` have no default value and
+/// must be inserted through [`World::insert_resource`] before they're used.
 #[macro_export]
 macro_rules! resource {
-    ($($ty:ty),*$(,)?) => {$(
+    ($($ty:ty $({ $($opt:tt)* })?),*$(,)?) => {$(
         const _: () = {
             #[context]
             pub static CX: $crate::world::resource_internals::AccessToken<$ty>;
@@ -378,11 +604,15 @@ macro_rules! resource {
             unsafe impl $crate::world::resource_internals::Resource for $ty {
                 type Cx = CX;
 
-                fn slot() -> &'static $crate::world::resource_internals::AtomicPtr<$ty> {
-                    static SLOT: $crate::world::resource_internals::AtomicPtr<$ty> =
-                        $crate::world::resource_internals::AtomicPtr::new(
-                            $crate::world::resource_internals::null_mut(),
-                        );
+                $crate::world::resource_internals::resource_default!($ty $(; $($opt)*)?);
+
+                fn slot() -> &'static $crate::world::resource_internals::LocalKey<
+                    $crate::world::resource_internals::ResourceSlot<$ty>,
+                > {
+                    $crate::world::resource_internals::thread_local! {
+                        static SLOT: $crate::world::resource_internals::ResourceSlot<$ty> =
+                            const { $crate::world::resource_internals::ResourceSlot::new() };
+                    }
 
                     &SLOT
                 }
@@ -398,6 +628,21 @@ macro_rules! resource {
 
 pub use resource;
 
+#[doc(hidden)]
+#[macro_export]
+macro_rules! resource_default {
+    ($ty:ty) => {
+        const DEFAULT: $crate::world::resource_internals::Option<fn() -> Self> =
+            $crate::world::resource_internals::Some(
+                <$ty as $crate::world::resource_internals::Default>::default,
+            );
+    };
+    ($ty:ty; explicit) => {
+        const DEFAULT: $crate::world::resource_internals::Option<fn() -> Self> =
+            $crate::world::resource_internals::None;
+    };
+}
+
 // === AccessToken === //
 
 #[repr(align(1))]
@@ -424,11 +669,13 @@ impl<T: Resource> AccessToken<T> {
 
 // === WorldFmt === //
 
+/// Entities and objects are keyed by the [`ImmutableWorld::id`] of the world they were formatted in
+/// since nested bindings may format several worlds at once.
 #[derive(Debug, Default)]
 struct FmtReentrancyState {
     depth: u32,
-    entities: FxHashSet<Entity>,
-    objects: FxHashSet<(ComponentId, Index)>,
+    entities: FxHashSet<(usize, Entity)>,
+    objects: FxHashSet<(usize, ComponentId, Index)>,
 }
 
 thread_local! {
@@ -456,25 +703,26 @@ fn guard_entity_reentrancy_checks() -> impl Sized {
     })
 }
 
-pub(crate) fn can_format_entity(entity: Entity) -> bool {
+pub(crate) fn can_format_entity(world: ImmutableWorld, entity: Entity) -> bool {
     FMT_REENTRANCY.with(|v| {
         let mut v = v.borrow_mut();
         let v = v
             .as_mut()
             .expect("cannot call `can_format_entity` without a bound immutable world");
 
-        v.entities.insert(entity)
+        v.entities.insert((world.id(), entity))
     })
 }
 
-pub(crate) fn can_format_obj<T: Component>(obj: Obj<T>) -> bool {
+pub(crate) fn can_format_obj<T: Component>(world: ImmutableWorld, obj: Obj<T>) -> bool {
     FMT_REENTRANCY.with(|v| {
         let mut v = v.borrow_mut();
         let v = v
             .as_mut()
             .expect("cannot call `can_format_obj` without a bound immutable world");
 
-        v.objects.insert((ComponentId::of::<T>(), Obj::raw(obj)))
+        v.objects
+            .insert((world.id(), ComponentId::of::<T>(), Obj::raw(obj)))
     })
 }
 
@@ -507,3 +755,112 @@ impl<'a, T: fmt::Display> fmt::Display for WorldFmt<'a, T> {
         self.world.immutable().bind_tls(|| self.value.fmt(f))
     }
 }
+
+// === Tests === //
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, thread};
+
+    use crate::{bind, component, resource, Entity, Resource as _, World};
+
+    #[derive(Debug)]
+    pub struct Counter(u32);
+
+    component!(Counter);
+
+    fn spawn_counter(world: &mut World, value: u32) -> Entity {
+        bind!(world);
+
+        Entity::new(Entity::root()).with(Counter(value))
+    }
+
+    fn read_counter(world: &mut World, entity: Entity) -> u32 {
+        bind!(world);
+
+        entity.get::<Counter>().0
+    }
+
+    fn read_nested(outer: &mut World, inner: &mut World, outer_ent: Entity, inner_ent: Entity) {
+        bind!(outer);
+
+        assert_eq!(read_counter(inner, inner_ent), 2);
+        assert_eq!(outer_ent.get::<Counter>().0, 1);
+    }
+
+    #[test]
+    fn coexisting_worlds() {
+        let mut a = World::new();
+        let mut b = World::new();
+
+        let ea = spawn_counter(&mut a, 1);
+        let eb = spawn_counter(&mut b, 2);
+
+        assert_eq!(read_counter(&mut a, ea), 1);
+        assert_eq!(read_counter(&mut b, eb), 2);
+
+        read_nested(&mut a, &mut b, ea, eb);
+    }
+
+    thread_local! {
+        static DROPPED: RefCell<Vec<&'static str>> = const { RefCell::new(Vec::new()) };
+    }
+
+    #[derive(Debug)]
+    pub struct Config(&'static str);
+
+    #[derive(Debug, Default)]
+    pub struct Cache;
+
+    resource!(Config { explicit }, Cache);
+
+    impl Drop for Config {
+        fn drop(&mut self) {
+            DROPPED.with_borrow_mut(|v| v.push(self.0));
+        }
+    }
+
+    impl Drop for Cache {
+        fn drop(&mut self) {
+            DROPPED.with_borrow_mut(|v| v.push("cache"));
+        }
+    }
+
+    #[test]
+    fn resource_lifecycle() {
+        let mut world = World::new();
+        world.insert_resource(Config("a"));
+
+        {
+            bind!(world);
+            assert_eq!(Config::fetch().0, "a");
+            let _ = Cache::fetch();
+        }
+
+        // Overrides replace the existing instance without dropping it.
+        let old = world.insert_resource(Config("b")).unwrap();
+        assert_eq!(old.0, "a");
+        drop(old);
+
+        DROPPED.take();
+        drop(world);
+
+        assert_eq!(DROPPED.take(), ["cache", "b"]);
+    }
+
+    #[test]
+    fn worlds_on_many_threads() {
+        thread::scope(|s| {
+            for i in 0..4 {
+                s.spawn(move || {
+                    let mut world = World::new();
+
+                    for _ in 0..100 {
+                        let entity = spawn_counter(&mut world, i);
+                        assert_eq!(read_counter(&mut world, entity), i);
+                    }
+                });
+            }
+        });
+    }
+}
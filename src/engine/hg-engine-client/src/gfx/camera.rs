@@ -1,4 +1,4 @@
-use hg_ecs::{component, Obj, Query};
+use hg_ecs::{component, Obj, Query, WeakObj};
 use hg_engine_common::{kinematic::Pos, utils::math::Aabb};
 use macroquad::{
     camera::{pop_camera_state, push_camera_state, set_camera, Camera},
@@ -11,25 +11,18 @@ use macroquad::{
 
 #[derive(Debug, Clone, Default)]
 pub struct VirtualCameraSelector {
-    current: Option<Obj<VirtualCamera>>,
+    current: Option<WeakObj<VirtualCamera>>,
 }
 
 component!(VirtualCameraSelector);
 
 impl VirtualCameraSelector {
-    pub fn current(&mut self) -> Option<Obj<VirtualCamera>> {
-        let current = self.current?;
-
-        if !Obj::is_alive(current) {
-            self.current = None;
-            return None;
-        }
-
-        Some(current)
+    pub fn current(&self) -> Option<Obj<VirtualCamera>> {
+        self.current?.get()
     }
 
     pub fn set_current(&mut self, camera: Obj<VirtualCamera>) {
-        self.current = Some(camera);
+        self.current = Some(camera.downgrade());
     }
 }
 
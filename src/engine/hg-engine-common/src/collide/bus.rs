@@ -3,7 +3,7 @@ use std::{
     ops::{BitAnd, BitAndAssign, BitOr, BitOrAssign, BitXor, BitXorAssign, ControlFlow, Not},
 };
 
-use hg_ecs::{bind, component, query::query_removed, Entity, Obj, World, WORLD};
+use hg_ecs::{bind, component, Entity, Obj, World, WORLD};
 
 use crate::utils::math::{Aabb, Bhv, BvhNodeIdx, HullCastRequest, HullCastResult};
 
@@ -182,7 +182,12 @@ impl Collider {
     }
 }
 
-component!(Collider);
+component!(Collider {
+    on_remove: |world, mut collider| {
+        bind!(world);
+        collider.unregister();
+    },
+});
 
 pub fn register_collider(collider: Obj<Collider>) {
     collider
@@ -313,11 +318,3 @@ impl fmt::Debug for CustomColliderMat {
             .finish_non_exhaustive()
     }
 }
-
-// === Systems === //
-
-pub fn sys_flush_colliders() {
-    for mut collider in query_removed::<Collider>() {
-        collider.unregister();
-    }
-}
@@ -1,5 +1,6 @@
 use glam::Vec2;
 use hg_ecs::{component, Entity, Obj, Query};
+use serde::{Deserialize, Serialize};
 
 use crate::utils::math::{cancel_normal, MoveAndSlide};
 
@@ -7,12 +8,29 @@ use super::collide::{bus::collide_everything, group::ColliderGroup};
 
 // === Components === //
 
-component!(Pos, Vel, KinematicProps, CollisionChecker);
-
-#[derive(Debug, Clone, Default)]
+component!(
+    Pos {
+        hash: "pos",
+        reflect,
+        rollback
+    },
+    Vel {
+        hash: "vel",
+        reflect,
+        rollback
+    },
+    KinematicProps {
+        hash: "kinematic_props",
+        reflect,
+        rollback
+    },
+    CollisionChecker,
+);
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct Pos(pub Vec2);
 
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct Vel {
     pub physical: Vec2,
     pub artificial: Vec2,
@@ -24,7 +42,7 @@ impl Vel {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct KinematicProps {
     pub gravity: Vec2,
     pub friction: f32,
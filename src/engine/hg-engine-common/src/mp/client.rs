@@ -2,8 +2,8 @@ use bytes::Bytes;
 use hg_ecs::{component, Obj, Query};
 
 use crate::{
-    net::{ClientTransport, ClientTransportEvent, ErasedTaskGuard, FrameEncoder},
-    rpc::RpcClient,
+    net::{ClientTransport, ClientTransportEvent, ErasedTaskGuard, FrameEncoder, TransportStream},
+    rpc::{RpcChannel, RpcClient},
 };
 
 use super::MpSbHello;
@@ -29,9 +29,17 @@ impl MpClient {
             self.transport.disconnect(Bytes::new());
         }
 
-        for packet in self.rpc.flush_sends() {
-            self.transport
-                .send(packet.finish(), ErasedTaskGuard::noop());
+        for (channel, packet) in self.rpc.flush_sends() {
+            match channel {
+                RpcChannel::Reliable(stream) => {
+                    self.transport
+                        .send(stream, packet.finish(), ErasedTaskGuard::noop());
+                }
+                RpcChannel::Unreliable(stream) => {
+                    self.transport
+                        .send_unreliable(stream, packet.finish_unframed());
+                }
+            }
         }
 
         while let Some(ev) = self.transport.process() {
@@ -39,6 +47,7 @@ impl MpClient {
                 ClientTransportEvent::Connected => {
                     // Send login packet
                     self.transport.send(
+                        TransportStream::Normal,
                         FrameEncoder::single(&MpSbHello {
                             username: "player_mc_playerface".to_string(),
                         }),
@@ -50,6 +59,9 @@ impl MpClient {
                     self.rpc.recv_packet(packet);
                     drop(task);
                 }
+                ClientTransportEvent::DatagramReceived { packet } => {
+                    self.rpc.recv_packet(packet);
+                }
             }
         }
 
@@ -1,14 +1,16 @@
 use bytes::Bytes;
 use hg_ecs::{
     bind, component,
-    signal::{DeferSignal, DeferSignalReader},
-    Entity, Obj, Query, World,
+    event::{event, EventBus, Retention},
+    Entity, Obj, Query, Resource as _, World,
 };
 use hg_utils::hash::FxHashMap;
 
 use crate::{
     mp::MpSbHello,
-    net::{ErasedTaskGuard, PeerId, RpcPacket, ServerTransport, ServerTransportEvent},
+    net::{
+        ErasedTaskGuard, PeerId, RpcPacket, ServerTransport, ServerTransportEvent, TransportStream,
+    },
     rpc::{RpcGroup, RpcServer, RpcServerFlushTransport, RpcServerPeer},
     time::RunLoop,
 };
@@ -21,12 +23,25 @@ pub struct MpServer {
     rpc: Obj<RpcServer>,
     all_players: Obj<RpcGroup>,
     sessions: FxHashMap<PeerId, Obj<MpServerSession>>,
-    on_join: DeferSignal<Obj<MpServerSession>>,
-    on_quit: DeferSignal<Obj<MpServerSession>>,
 }
 
 component!(MpServer);
 
+/// Sent once a session has logged in. Readers which lag behind should check that the session is
+/// still alive.
+#[derive(Debug, Copy, Clone)]
+pub struct MpServerJoined(pub Obj<MpServerSession>);
+
+/// Sent once a session which had logged in disconnects. The session is destroyed by the next
+/// `Entity::flush` so this event is only retained for the frame in which it was sent.
+#[derive(Debug, Copy, Clone)]
+pub struct MpServerQuit(pub Obj<MpServerSession>);
+
+event!(
+    MpServerJoined { retention: Retention::UntilConsumed },
+    MpServerQuit { retention: Retention::Frames(1) },
+);
+
 impl MpServer {
     pub fn new(me: Entity, transport: Box<dyn ServerTransport>, rpc: Obj<RpcServer>) -> Self {
         Self {
@@ -34,26 +49,14 @@ impl MpServer {
             rpc,
             all_players: Entity::new(me).add(RpcGroup::new()),
             sessions: FxHashMap::default(),
-            on_join: DeferSignal::new(),
-            on_quit: DeferSignal::new(),
         }
     }
 
-    pub fn on_join(&self) -> DeferSignalReader<Obj<MpServerSession>> {
-        self.on_join.reader()
-    }
-
-    pub fn on_quit(&self) -> DeferSignalReader<Obj<MpServerSession>> {
-        self.on_quit.reader()
-    }
-
     pub fn all_players(&self) -> Obj<RpcGroup> {
         self.all_players
     }
 
     pub fn process(mut self: Obj<Self>) {
-        self.on_join.reset();
-        self.on_quit.reset();
         self.rpc.flush(&mut ServerFlushTrans);
 
         while let Some(ev) = self.transport.process() {
@@ -68,7 +71,7 @@ impl MpServer {
                     let sess = self.sessions.remove(&peer).unwrap();
                     if let SessionState::Play { peer, .. } = sess.state {
                         peer.disconnect();
-                        self.on_quit.fire(sess);
+                        EventBus::fetch_mut().send(MpServerQuit(sess));
                         self.all_players.remove_peer(peer);
                     }
                     sess.entity().destroy();
@@ -84,28 +87,62 @@ impl MpServer {
 
                     drop(task);
                 }
+                ServerTransportEvent::DatagramReceived { peer, packet } => {
+                    // Datagrams are only ever sent once a session is playing but can still
+                    // overtake its login packet.
+                    let sess = self.sessions[&peer];
+                    if !matches!(sess.state, SessionState::Play { .. }) {
+                        continue;
+                    }
+
+                    if let Err(err) = sess.process_recv(packet) {
+                        tracing::error!("failed to process datagram sent by peer {peer}: {err:?}");
+
+                        self.transport
+                            .peer_kick(peer, Bytes::from_static(b"protocol error"));
+                    }
+                }
                 ServerTransportEvent::Shutdown { cause: _ } => {
                     Entity::service::<RunLoop>().request_exit();
                 }
             }
         }
-
-        self.on_join.freeze();
-        self.on_quit.freeze();
     }
 }
 
 struct ServerFlushTrans;
 
 impl RpcServerFlushTransport for ServerFlushTrans {
-    fn send_packet(&mut self, world: &mut World, target: Obj<RpcServerPeer>, packet: Bytes) {
+    fn send_packet(
+        &mut self,
+        world: &mut World,
+        target: Obj<RpcServerPeer>,
+        stream: TransportStream,
+        packet: Bytes,
+    ) {
+        bind!(world);
+
+        let mut target = target.entity().get::<MpServerSession>();
+        target
+            .manager
+            .transport
+            .peer_send(target.peer, stream, packet, ErasedTaskGuard::noop());
+    }
+
+    fn send_unreliable_packet(
+        &mut self,
+        world: &mut World,
+        target: Obj<RpcServerPeer>,
+        stream: TransportStream,
+        packet: Bytes,
+    ) {
         bind!(world);
 
         let mut target = target.entity().get::<MpServerSession>();
         target
             .manager
             .transport
-            .peer_send(target.peer, packet, ErasedTaskGuard::noop());
+            .peer_send_unreliable(target.peer, stream, packet);
     }
 }
 
@@ -164,7 +201,7 @@ impl MpServerSession {
                 let packet = MpSbHello::decode(&packet)?;
                 tracing::info!("Peer {} logged in with {packet:?}", self.peer);
                 let peer = self.manager.rpc.register_peer(self.entity());
-                self.manager.on_join.fire(self);
+                EventBus::fetch_mut().send(MpServerJoined(self));
                 self.manager.all_players.add_peer(peer);
                 self.state = SessionState::Play {
                     peer,
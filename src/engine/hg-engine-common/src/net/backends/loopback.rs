@@ -0,0 +1,634 @@
+use std::{
+    collections::VecDeque,
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    num::NonZeroU64,
+    sync::{Arc, Mutex},
+};
+
+use bytes::{Bytes, BytesMut};
+use hg_utils::hash::FxHashMap;
+use thiserror::Error;
+use tokio_util::codec::Decoder as _;
+
+use crate::{
+    net::{
+        back_pressure::ErasedTaskGuard,
+        codec::FrameDecoder,
+        transport::{
+            ClientTransport, ClientTransportEvent, PeerDisconnectError, PeerId, ServerTransport,
+            ServerTransportEvent, TransportStream,
+        },
+    },
+    utils::lang::absorb_result_std,
+};
+
+/// The address every loopback peer reports as its remote address.
+const LOOPBACK_ADDR: SocketAddr = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 0);
+
+/// The largest datagram either end can send by default, matching the smallest datagram size QUIC
+/// connections are guaranteed to support.
+const LOOPBACK_MAX_DATAGRAM_SIZE: usize = 1200;
+
+// === Network === //
+
+/// An in-process network through which [`LoopbackClientTransport`]s connect to a single
+/// [`LoopbackServerTransport`].
+///
+/// Unlike the QUIC backends, this requires no sockets, async runtime or certificates and
+/// delivers packets as soon as they're sent, making it suitable for deterministic tests. Both
+/// ends emit the same sequences of events as their QUIC counterparts. In particular, the data
+/// passed to `disconnect` and `peer_kick` is not surfaced to the other end and packets received
+/// from a peer before it was kicked are dropped. Every stream is delivered in the order in which it
+/// was sent, as if the connection had unlimited bandwidth. Datagrams are never lost or reordered
+/// but are limited in size all the same.
+#[derive(Debug, Clone)]
+pub struct LoopbackNetwork {
+    state: Arc<Mutex<NetworkState>>,
+}
+
+#[derive(Debug)]
+struct NetworkState {
+    next_peer_id: NonZeroU64,
+    has_server: bool,
+    shut_down: bool,
+    max_datagram_size: usize,
+    server_events: VecDeque<ServerTransportEvent>,
+    conns: FxHashMap<PeerId, Conn>,
+}
+
+#[derive(Debug)]
+struct Conn {
+    /// Closed connections are kept around until their client transport is dropped so that it can
+    /// drain its remaining events.
+    open: bool,
+    client_events: VecDeque<ClientTransportEvent>,
+
+    /// Bytes sent to the server over each stream which have yet to form a complete frame.
+    server_rx: [BytesMut; TransportStream::ALL.len()],
+
+    /// Bytes sent to the client over each stream which have yet to form a complete frame.
+    client_rx: [BytesMut; TransportStream::ALL.len()],
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum Direction {
+    ToServer,
+    ToClient,
+}
+
+impl Default for LoopbackNetwork {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LoopbackNetwork {
+    pub fn new() -> Self {
+        Self {
+            state: Arc::new(Mutex::new(NetworkState {
+                next_peer_id: NonZeroU64::new(1).unwrap(),
+                has_server: false,
+                shut_down: false,
+                max_datagram_size: LOOPBACK_MAX_DATAGRAM_SIZE,
+                server_events: VecDeque::new(),
+                conns: FxHashMap::default(),
+            })),
+        }
+    }
+
+    /// Creates the transport of the network's server. Clients may connect before this is called.
+    pub fn server(&self) -> LoopbackServerTransport {
+        let mut state = self.state.lock().unwrap();
+        assert!(
+            !state.has_server,
+            "loopback networks only support one server"
+        );
+        state.has_server = true;
+
+        LoopbackServerTransport {
+            state: self.state.clone(),
+        }
+    }
+
+    /// Connects a new client to the server. Connections made after the server has shut down are
+    /// refused, which the client reports as an erroneous `Disconnected` event.
+    pub fn connect(&self) -> LoopbackClientTransport {
+        let mut state = self.state.lock().unwrap();
+
+        if state.shut_down {
+            return LoopbackClientTransport {
+                state: self.state.clone(),
+                peer: None,
+                refusal: Some(anyhow::anyhow!(
+                    "failed to connect: the loopback server has shut down"
+                )),
+            };
+        }
+
+        let peer = PeerId(state.next_peer_id);
+        state.next_peer_id = state
+            .next_peer_id
+            .checked_add(1)
+            .expect("created too many peers");
+
+        state.conns.insert(
+            peer,
+            Conn {
+                open: true,
+                client_events: VecDeque::from([ClientTransportEvent::Connected]),
+                server_rx: Default::default(),
+                client_rx: Default::default(),
+            },
+        );
+
+        state
+            .server_events
+            .push_back(ServerTransportEvent::Connected {
+                peer,
+                task: ErasedTaskGuard::noop(),
+            });
+
+        tracing::info!("Got loopback connection from peer {peer}");
+
+        LoopbackClientTransport {
+            state: self.state.clone(),
+            peer: Some(peer),
+            refusal: None,
+        }
+    }
+
+    /// Limits the size of the datagrams either end can send, e.g. to emulate a path which can't fit
+    /// every unreliable packet in a datagram. This defaults to 1200 bytes.
+    pub fn set_max_datagram_size(&self, size: usize) {
+        self.state.lock().unwrap().max_datagram_size = size;
+    }
+
+    /// Disconnects every peer and shuts the server down, emitting a `Shutdown` event once the
+    /// server has processed the disconnections.
+    pub fn shutdown(&self) {
+        self.state.lock().unwrap().shutdown();
+    }
+}
+
+impl NetworkState {
+    fn is_open(&self, peer: PeerId) -> bool {
+        self.conns.get(&peer).is_some_and(|conn| conn.open)
+    }
+
+    fn deliver(&mut self, peer: PeerId, stream: TransportStream, framed: &[u8], dir: Direction) {
+        let conn = self.conns.get_mut(&peer).unwrap();
+        let rx = match dir {
+            Direction::ToServer => &mut conn.server_rx[stream.index()],
+            Direction::ToClient => &mut conn.client_rx[stream.index()],
+        };
+
+        rx.extend_from_slice(framed);
+
+        // Frames are limited to the same size as in the QUIC backends.
+        let mut decoder = FrameDecoder {
+            max_packet_size: 1024,
+            compression: None,
+        };
+
+        let mut packets = Vec::new();
+        let result = loop {
+            match decoder.decode(rx) {
+                Ok(Some(packet)) => packets.push(packet),
+                Ok(None) => break Ok(()),
+                Err(err) => break Err(err),
+            }
+        };
+
+        for packet in packets {
+            match dir {
+                Direction::ToServer => {
+                    self.server_events
+                        .push_back(ServerTransportEvent::DataReceived {
+                            peer,
+                            packet,
+                            task: ErasedTaskGuard::noop(),
+                        })
+                }
+                Direction::ToClient => {
+                    conn.client_events
+                        .push_back(ClientTransportEvent::DataReceived {
+                            packet,
+                            task: ErasedTaskGuard::noop(),
+                        })
+                }
+            }
+        }
+
+        // Malformed frames crash the connection on both ends.
+        if let Err(err) = result {
+            tracing::error!("Loopback connection to peer {peer} crashed:\n{err:?}");
+            self.close(peer, Some(err.context("failed to decode frame")));
+        }
+    }
+
+    fn deliver_datagram(
+        &mut self,
+        peer: PeerId,
+        packet: Bytes,
+        dir: Direction,
+    ) -> Result<(), DatagramError> {
+        if !self.is_open(peer) {
+            return Err(DatagramError::Disconnected);
+        }
+
+        if packet.len() > self.max_datagram_size {
+            return Err(DatagramError::TooLarge(
+                packet.len(),
+                self.max_datagram_size,
+            ));
+        }
+
+        match dir {
+            Direction::ToServer => self
+                .server_events
+                .push_back(ServerTransportEvent::DatagramReceived { peer, packet }),
+            Direction::ToClient => self
+                .conns
+                .get_mut(&peer)
+                .unwrap()
+                .client_events
+                .push_back(ClientTransportEvent::DatagramReceived { packet }),
+        }
+
+        Ok(())
+    }
+
+    fn close(&mut self, peer: PeerId, error: Option<anyhow::Error>) {
+        let Some(conn) = self.conns.get_mut(&peer).filter(|conn| conn.open) else {
+            return;
+        };
+
+        conn.open = false;
+
+        let (server_cause, client_cause) = match error {
+            Some(err) => (Err(anyhow::anyhow!("{err:#}")), Err(err)),
+            None => (Ok(()), Ok(())),
+        };
+
+        conn.client_events
+            .push_back(ClientTransportEvent::Disconnected {
+                cause: client_cause,
+            });
+
+        self.server_events
+            .push_back(ServerTransportEvent::Disconnected {
+                peer,
+                cause: server_cause,
+            });
+    }
+
+    fn shutdown(&mut self) {
+        if self.shut_down {
+            return;
+        }
+
+        self.shut_down = true;
+
+        let mut peers = self.conns.keys().copied().collect::<Vec<_>>();
+        peers.sort();
+
+        for peer in peers {
+            self.close(peer, None);
+        }
+
+        self.server_events
+            .push_back(ServerTransportEvent::Shutdown { cause: Ok(()) });
+    }
+}
+
+#[derive(Debug, Error)]
+enum DatagramError {
+    #[error("peer disconnected")]
+    Disconnected,
+    #[error("datagram is too large ({0} > {1})")]
+    TooLarge(usize, usize),
+}
+
+// === Server === //
+
+#[derive(Debug)]
+pub struct LoopbackServerTransport {
+    state: Arc<Mutex<NetworkState>>,
+}
+
+impl ServerTransport for LoopbackServerTransport {
+    fn process(&mut self) -> Option<ServerTransportEvent> {
+        self.state.lock().unwrap().server_events.pop_front()
+    }
+
+    fn peer_remote_addr(&mut self, id: PeerId) -> Result<SocketAddr, PeerDisconnectError> {
+        if self.peer_alive(id) {
+            Ok(LOOPBACK_ADDR)
+        } else {
+            Err(PeerDisconnectError)
+        }
+    }
+
+    fn peer_alive(&mut self, id: PeerId) -> bool {
+        self.state.lock().unwrap().is_open(id)
+    }
+
+    fn peer_send(
+        &mut self,
+        id: PeerId,
+        stream: TransportStream,
+        framed: Bytes,
+        task_guard: ErasedTaskGuard,
+    ) {
+        absorb_result_std("send a packet", || {
+            let mut state = self.state.lock().unwrap();
+
+            if !state.is_open(id) {
+                return Err(PeerDisconnectError);
+            }
+
+            state.deliver(id, stream, &framed, Direction::ToClient);
+            Ok(())
+        });
+
+        drop(task_guard);
+    }
+
+    fn peer_send_datagram(&mut self, id: PeerId, packet: Bytes) {
+        absorb_result_std("send a datagram", || {
+            self.state
+                .lock()
+                .unwrap()
+                .deliver_datagram(id, packet, Direction::ToClient)
+        });
+    }
+
+    fn peer_max_datagram_size(&mut self, id: PeerId) -> Option<usize> {
+        let state = self.state.lock().unwrap();
+        state.is_open(id).then_some(state.max_datagram_size)
+    }
+
+    fn peer_kick(&mut self, id: PeerId, _data: Bytes) {
+        absorb_result_std("kick a peer", || {
+            let mut state = self.state.lock().unwrap();
+
+            if !state.is_open(id) {
+                return Err(PeerDisconnectError);
+            }
+
+            tracing::info!("Kicked peer {id}");
+
+            // (drop incoming packets from kicked peer)
+            state.server_events.retain(|ev| {
+                !matches!(
+                    ev,
+                    ServerTransportEvent::DataReceived { peer, .. }
+                    | ServerTransportEvent::DatagramReceived { peer, .. }
+                        if *peer == id,
+                )
+            });
+
+            state.close(id, None);
+            Ok(())
+        });
+    }
+}
+
+impl Drop for LoopbackServerTransport {
+    fn drop(&mut self) {
+        let mut state = self.state.lock().unwrap();
+        state.shutdown();
+        state.server_events.clear();
+    }
+}
+
+// === Client === //
+
+#[derive(Debug)]
+pub struct LoopbackClientTransport {
+    state: Arc<Mutex<NetworkState>>,
+
+    /// This is `None` if the connection was refused.
+    peer: Option<PeerId>,
+
+    /// The reason for which the connection was refused, taken once it has been reported.
+    refusal: Option<anyhow::Error>,
+}
+
+impl ClientTransport for LoopbackClientTransport {
+    fn process(&mut self) -> Option<ClientTransportEvent> {
+        let Some(peer) = self.peer else {
+            return self
+                .refusal
+                .take()
+                .map(|err| ClientTransportEvent::Disconnected { cause: Err(err) });
+        };
+
+        self.state
+            .lock()
+            .unwrap()
+            .conns
+            .get_mut(&peer)
+            .unwrap()
+            .client_events
+            .pop_front()
+    }
+
+    fn send(&mut self, stream: TransportStream, framed: Bytes, task_guard: ErasedTaskGuard) {
+        absorb_result_std("send a packet", || {
+            let peer = self.peer.ok_or(PeerDisconnectError)?;
+            let mut state = self.state.lock().unwrap();
+
+            if !state.is_open(peer) {
+                return Err(PeerDisconnectError);
+            }
+
+            state.deliver(peer, stream, &framed, Direction::ToServer);
+            Ok(())
+        });
+
+        drop(task_guard);
+    }
+
+    fn send_datagram(&mut self, packet: Bytes) {
+        absorb_result_std("send a datagram", || {
+            let peer = self.peer.ok_or(DatagramError::Disconnected)?;
+
+            self.state
+                .lock()
+                .unwrap()
+                .deliver_datagram(peer, packet, Direction::ToServer)
+        });
+    }
+
+    fn max_datagram_size(&mut self) -> Option<usize> {
+        let peer = self.peer?;
+        let state = self.state.lock().unwrap();
+
+        state.is_open(peer).then_some(state.max_datagram_size)
+    }
+
+    fn disconnect(&mut self, _data: Bytes) {
+        absorb_result_std("disconnect", || {
+            let peer = self.peer.ok_or(PeerDisconnectError)?;
+            let mut state = self.state.lock().unwrap();
+
+            if !state.is_open(peer) {
+                return Err(PeerDisconnectError);
+            }
+
+            state.close(peer, None);
+            Ok(())
+        });
+    }
+}
+
+impl Drop for LoopbackClientTransport {
+    fn drop(&mut self) {
+        let Some(peer) = self.peer else {
+            return;
+        };
+
+        let mut state = self.state.lock().unwrap();
+        state.close(peer, None);
+        state.conns.remove(&peer);
+    }
+}
+
+// === Tests === //
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+
+    use crate::net::{
+        ClientTransport as _, ClientTransportEvent, ErasedTaskGuard, FrameEncoder,
+        ServerTransport as _, ServerTransportEvent, TransportStream,
+    };
+
+    use super::LoopbackNetwork;
+
+    #[test]
+    fn emits_quic_event_sequences() {
+        let net = LoopbackNetwork::new();
+        let mut server = net.server();
+        let mut client = net.connect();
+
+        assert!(matches!(
+            client.process(),
+            Some(ClientTransportEvent::Connected)
+        ));
+
+        let Some(ServerTransportEvent::Connected { peer, .. }) = server.process() else {
+            panic!("expected the server to see the connection");
+        };
+
+        // Frames split across several sends are only received once complete, even if other
+        // streams are used in the meantime.
+        let mut encoder = FrameEncoder::new();
+        encoder.extend_from_slice(b"hello");
+        let framed = encoder.finish();
+
+        client.send(
+            TransportStream::Bulk,
+            framed.slice(..2),
+            ErasedTaskGuard::noop(),
+        );
+        client.send(
+            TransportStream::Normal,
+            framed.clone(),
+            ErasedTaskGuard::noop(),
+        );
+        assert!(matches!(
+            server.process(),
+            Some(ServerTransportEvent::DataReceived { packet, .. }) if packet == b"hello"[..],
+        ));
+        assert!(server.process().is_none());
+
+        client.send(
+            TransportStream::Bulk,
+            framed.slice(2..),
+            ErasedTaskGuard::noop(),
+        );
+        assert!(matches!(
+            server.process(),
+            Some(ServerTransportEvent::DataReceived { packet, .. }) if packet == b"hello"[..],
+        ));
+
+        // Datagrams are unframed and oversized ones are dropped.
+        assert_eq!(client.max_datagram_size(), Some(1200));
+        client.send_datagram(Bytes::from(vec![0; 1201]));
+        client.send_datagram(Bytes::from_static(b"world"));
+        assert!(matches!(
+            server.process(),
+            Some(ServerTransportEvent::DatagramReceived { packet, .. }) if packet == b"world"[..],
+        ));
+
+        // Kicking disconnects both ends.
+        server.peer_kick(peer, Bytes::new());
+        assert!(!server.peer_alive(peer));
+
+        assert!(matches!(
+            client.process(),
+            Some(ClientTransportEvent::Disconnected { cause: Ok(()) }),
+        ));
+        assert!(matches!(
+            server.process(),
+            Some(ServerTransportEvent::Disconnected { peer: other, cause: Ok(()) })
+                if other == peer,
+        ));
+
+        // Clients connecting after a shutdown are refused.
+        net.shutdown();
+        assert!(matches!(
+            server.process(),
+            Some(ServerTransportEvent::Shutdown { cause: Ok(()) }),
+        ));
+
+        let mut late = net.connect();
+        assert!(matches!(
+            late.process(),
+            Some(ClientTransportEvent::Disconnected { cause: Err(_) }),
+        ));
+    }
+
+    #[test]
+    fn sends_oversized_unreliable_packets_reliably() {
+        let net = LoopbackNetwork::new();
+        net.set_max_datagram_size(64);
+
+        let mut server = net.server();
+        let mut client = net.connect();
+
+        assert!(matches!(
+            client.process(),
+            Some(ClientTransportEvent::Connected)
+        ));
+
+        let Some(ServerTransportEvent::Connected { peer, .. }) = server.process() else {
+            panic!("expected the server to see the connection");
+        };
+
+        // Packets which fit are sent as datagrams...
+        client.send_unreliable(TransportStream::Realtime, Bytes::from_static(b"small"));
+        assert!(matches!(
+            server.process(),
+            Some(ServerTransportEvent::DatagramReceived { packet, .. }) if packet == b"small"[..],
+        ));
+
+        // ...while larger ones are framed onto the stream instead of being dropped.
+        let large = Bytes::from(vec![42; 65]);
+
+        client.send_unreliable(TransportStream::Realtime, large.clone());
+        assert!(matches!(
+            server.process(),
+            Some(ServerTransportEvent::DataReceived { packet, .. }) if packet == large,
+        ));
+
+        server.peer_send_unreliable(peer, TransportStream::Realtime, large.clone());
+        assert!(matches!(
+            client.process(),
+            Some(ClientTransportEvent::DataReceived { packet, .. }) if packet == large,
+        ));
+    }
+}
@@ -1,3 +1,5 @@
+pub mod loopback;
 pub mod quic_client;
 pub mod quic_server;
 mod quic_shared;
+pub mod simulated;
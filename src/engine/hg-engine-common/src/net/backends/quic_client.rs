@@ -1,8 +1,13 @@
-use std::{net::SocketAddr, pin::pin, str::FromStr, sync::Arc};
+use std::{
+    net::SocketAddr,
+    pin::pin,
+    str::FromStr,
+    sync::{Arc, OnceLock},
+};
 
 use anyhow::Context as _;
 use bytes::Bytes;
-use futures::StreamExt as _;
+use futures::{future, StreamExt as _};
 use tokio::sync::mpsc;
 use tokio_util::codec::FramedRead;
 use tracing::{instrument, Instrument as _};
@@ -10,15 +15,18 @@ use tracing::{instrument, Instrument as _};
 use crate::{
     net::{
         back_pressure::{BackPressureAsync, ErasedTaskGuard},
-        codec::FrameDecoder,
-        transport::{ClientTransport, ClientTransportEvent},
+        codec::{FrameCompression, FrameDecoder},
+        transport::{ClientTransport, ClientTransportEvent, TransportStream},
     },
     try_async,
-    utils::lang::{absorb_result_std, catch_termination_async, worker_panic_error},
+    utils::lang::{
+        absorb_result_anyhow, absorb_result_std, catch_termination_async, worker_panic_error,
+    },
 };
 
 use super::quic_shared::{
-    filter_framed_read_failure, run_transport_data_handler, SocketCloseReason,
+    filter_framed_read_failure, offer_compression, open_streams, run_stream_tx,
+    run_transport_data_handler, SocketCloseReason,
 };
 
 // === Transport === //
@@ -34,13 +42,18 @@ struct TransportState {
     server_addr: SocketAddr,
     server_name: String,
     config: quinn::ClientConfig,
+    compression: Option<FrameCompression>,
     event_tx: mpsc::UnboundedSender<ClientTransportEvent>,
     send_action_tx: mpsc::UnboundedSender<PeerSendAction>,
+
+    /// The connection to the server, set once it has been established.
+    conn: OnceLock<quinn::Connection>,
 }
 
 #[derive(Debug)]
 enum PeerSendAction {
     Reliable {
+        stream: TransportStream,
         framed: Bytes,
         task_guard: ErasedTaskGuard,
     },
@@ -48,7 +61,14 @@ enum PeerSendAction {
 }
 
 impl QuicClientTransport {
-    pub fn new(config: quinn::ClientConfig, server_addr: SocketAddr, server_name: &str) -> Self {
+    /// Creates a transport connecting to `server_addr`. Packets are compressed with `compression`
+    /// if the server enables compression with the same dictionary.
+    pub fn new(
+        config: quinn::ClientConfig,
+        server_addr: SocketAddr,
+        server_name: &str,
+        compression: Option<FrameCompression>,
+    ) -> Self {
         let (event_tx, event_rx) = mpsc::unbounded_channel();
         let (send_action_tx, send_action_rx) = mpsc::unbounded_channel();
 
@@ -56,8 +76,10 @@ impl QuicClientTransport {
             server_addr,
             server_name: server_name.to_owned(),
             config,
+            compression,
             event_tx,
             send_action_tx,
+            conn: OnceLock::new(),
         });
 
         tokio::spawn(TransportWorker::run(state.clone(), send_action_rx));
@@ -67,14 +89,28 @@ impl QuicClientTransport {
 }
 
 impl ClientTransport for QuicClientTransport {
-    fn send(&mut self, framed: Bytes, task_guard: ErasedTaskGuard) {
+    fn send(&mut self, stream: TransportStream, framed: Bytes, task_guard: ErasedTaskGuard) {
         absorb_result_std::<_, _>("send a packet", || {
-            self.state
-                .send_action_tx
-                .send(PeerSendAction::Reliable { framed, task_guard })
+            self.state.send_action_tx.send(PeerSendAction::Reliable {
+                stream,
+                framed,
+                task_guard,
+            })
+        });
+    }
+
+    fn send_datagram(&mut self, packet: Bytes) {
+        absorb_result_anyhow("send a datagram", || {
+            let conn = self.state.conn.get().context("not connected yet")?;
+            conn.send_datagram(packet)?;
+            Ok(())
         });
     }
 
+    fn max_datagram_size(&mut self) -> Option<usize> {
+        self.state.conn.get()?.max_datagram_size()
+    }
+
     fn disconnect(&mut self, data: Bytes) {
         absorb_result_std::<_, _>("disconnect", || {
             self.state
@@ -140,39 +176,88 @@ impl TransportWorker {
 
         tracing::info!("Connected!");
 
+        // Unwrap OK: this worker is the only one to ever connect.
+        state.conn.set(conn.clone()).unwrap();
         state.send_event(ClientTransportEvent::Connected);
 
         let worker = Arc::new(TransportWorker { state, conn });
 
-        // Open the main stream
-        let (tx, rx) = worker
-            .conn
-            .open_bi()
-            .await
-            .context("failed to open main stream")?;
+        // Open the streams
+        let mut streams = open_streams(&worker.conn).await?;
+        let compression = offer_compression(
+            &mut streams[TransportStream::Normal.index()],
+            worker.state.compression.as_ref(),
+        )
+        .await?;
+
+        tracing::info!("Compression enabled: {}", compression.is_some());
+
+        let (txs, rxs) = streams.into_iter().unzip();
+
+        // Process the streams!
+        let datagram_task = tokio::spawn(worker.clone().run_conn_datagrams().in_current_span());
 
-        // Process the stream!
-        run_transport_data_handler(
+        let res = run_transport_data_handler(
             worker.conn.clone(),
-            tokio::spawn(worker.clone().run_conn_rx(rx).in_current_span()),
             tokio::spawn(
                 worker
                     .clone()
-                    .run_conn_tx(tx, send_action_rx)
+                    .run_conn_rxs(rxs, compression.clone())
+                    .in_current_span(),
+            ),
+            tokio::spawn(
+                worker
+                    .clone()
+                    .run_conn_tx(txs, send_action_rx, compression)
                     .in_current_span(),
             ),
         )
+        .await;
+
+        // The connection is closed by now so this finishes promptly. Waiting on it ensures that no
+        // datagram is reported after the disconnection.
+        let _ = datagram_task.await;
+
+        res?;
+
+        Ok(())
+    }
+
+    async fn run_conn_datagrams(self: Arc<Self>) {
+        // Datagrams can be dropped at any time so they bypass the back-pressure mechanism.
+        while let Ok(packet) = self.conn.read_datagram().await {
+            self.state
+                .send_event(ClientTransportEvent::DatagramReceived { packet });
+        }
+
+        // (the `run_conn_inner` driver interprets the `close_reason()` for us)
+    }
+
+    async fn run_conn_rxs(
+        self: Arc<Self>,
+        rxs: Vec<quinn::RecvStream>,
+        compression: Option<FrameCompression>,
+    ) -> anyhow::Result<()> {
+        future::try_join_all(
+            rxs.into_iter()
+                .map(|rx| self.clone().run_conn_rx(rx, compression.clone())),
+        )
         .await?;
 
         Ok(())
     }
 
-    async fn run_conn_rx(self: Arc<Self>, rx: quinn::RecvStream) -> anyhow::Result<()> {
+    async fn run_conn_rx(
+        self: Arc<Self>,
+        rx: quinn::RecvStream,
+        compression: Option<FrameCompression>,
+    ) -> anyhow::Result<()> {
         let mut pressure = BackPressureAsync::new(1024);
         let mut rx = pin!(FramedRead::new(
             rx,
             FrameDecoder {
                 max_packet_size: 1024,
+                compression,
             },
         ));
 
@@ -204,42 +289,59 @@ impl TransportWorker {
 
     async fn run_conn_tx(
         self: Arc<Self>,
-        mut tx: quinn::SendStream,
+        txs: Vec<quinn::SendStream>,
         mut send_action_rx: mpsc::UnboundedReceiver<PeerSendAction>,
+        compression: Option<FrameCompression>,
     ) -> anyhow::Result<()> {
-        loop {
-            // Wait for the next send request.
-            let send_action = tokio::select! {
-                send_action = send_action_rx.recv() => {
-                    // Unwrap OK: The sender is stored in the `TransportPeerState` associated with
-                    // this connection and that object isn't destroyed until this task exists.
-                    send_action.unwrap()
-                },
-                _err = self.conn.closed() => {
-                    // The `run_conn_inner` driver will interpret the `close_reason()` for us. We
-                    // should only return `Err(())` if some novel kind of error occurs.
-                    return Ok(());
+        let (queues, writers): (Vec<_>, Vec<_>) = txs
+            .into_iter()
+            .map(|tx| {
+                let (queue_tx, queue_rx) = mpsc::unbounded_channel();
+                (queue_tx, run_stream_tx(tx, queue_rx, compression.clone()))
+            })
+            .unzip();
+
+        let dispatch = async move {
+            loop {
+                // Wait for the next send request.
+                let send_action = tokio::select! {
+                    send_action = send_action_rx.recv() => {
+                        // Unwrap OK: The sender is stored in the `TransportState` associated with
+                        // this connection and that object isn't destroyed until this task exists.
+                        send_action.unwrap()
+                    },
+                    _err = self.conn.closed() => {
+                        // The `run_conn_inner` driver will interpret the `close_reason()` for us.
+                        // We should only return `Err(())` if some novel kind of error occurs.
+                        break;
+                    }
+                };
+
+                // Process it!
+                match send_action {
+                    PeerSendAction::Reliable {
+                        stream,
+                        framed,
+                        task_guard,
+                    } => {
+                        // (writers only stop early if they fail, which aborts this task anyways)
+                        let _ = queues[stream.index()].send((framed, task_guard));
+                    }
+                    PeerSendAction::Disconnect(bytes) => {
+                        self.conn
+                            .close(SocketCloseReason::Application.code().into(), &bytes);
+
+                        break;
+                    }
                 }
-            };
+            }
 
-            // Process it!
-            match send_action {
-                PeerSendAction::Reliable {
-                    framed: data,
-                    task_guard,
-                } => {
-                    // TODO: parse error
-                    tx.write_all(&data).await?;
-                    drop(task_guard);
-                }
-                PeerSendAction::Disconnect(bytes) => {
-                    self.conn
-                        .close(SocketCloseReason::Application.code().into(), &bytes);
+            // Dropping the queues lets the writers finish.
+            drop(queues);
+            anyhow::Ok(())
+        };
 
-                    break;
-                }
-            }
-        }
+        tokio::try_join!(dispatch, future::try_join_all(writers))?;
 
         Ok(())
     }
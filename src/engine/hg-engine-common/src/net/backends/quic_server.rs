@@ -10,7 +10,7 @@ use std::{
 
 use anyhow::Context as _;
 use bytes::Bytes;
-use futures::StreamExt as _;
+use futures::{future, StreamExt as _};
 use hg_utils::hash::FxHashMap;
 use tokio::sync::mpsc;
 use tokio_util::codec::FramedRead;
@@ -19,17 +19,20 @@ use tracing::{instrument, Instrument};
 use crate::{
     net::{
         back_pressure::{BackPressureAsync, ErasedTaskGuard},
-        codec::FrameDecoder,
-        transport::{PeerDisconnectError, PeerId, ServerTransport, ServerTransportEvent},
+        codec::{FrameCompression, FrameDecoder},
+        transport::{
+            PeerDisconnectError, PeerId, ServerTransport, ServerTransportEvent, TransportStream,
+        },
     },
     utils::lang::{
         absorb_result_anyhow, absorb_result_std, catch_termination_async, worker_panic_error,
-        MultiError, MultiResult,
+        MultiResult,
     },
 };
 
 use super::quic_shared::{
-    filter_framed_read_failure, run_transport_data_handler, SocketCloseReason,
+    accept_streams, answer_compression, filter_framed_read_failure, run_stream_tx,
+    run_transport_data_handler, SocketCloseReason,
 };
 
 // === Transport === //
@@ -43,6 +46,7 @@ pub struct QuicServerTransport {
 #[derive(Debug)]
 enum PeerSendAction {
     Reliable {
+        stream: TransportStream,
         framed: Bytes,
         task_guard: ErasedTaskGuard,
     },
@@ -51,6 +55,7 @@ enum PeerSendAction {
 
 #[derive(Debug)]
 struct TransportListenState {
+    compression: Option<FrameCompression>,
     event_tx: mpsc::UnboundedSender<ServerTransportEvent>,
     peer_map: Mutex<FxHashMap<PeerId, Arc<TransportPeerState>>>,
 }
@@ -60,14 +65,22 @@ struct TransportPeerState {
     peer_id: PeerId,
     remote_addr: SocketAddr,
     send_action_tx: mpsc::UnboundedSender<PeerSendAction>,
+    conn: quinn::Connection,
     kicked: AtomicBool,
 }
 
 impl QuicServerTransport {
-    pub fn new(config: quinn::ServerConfig, bind_addr: SocketAddr) -> Self {
+    /// Creates a transport listening on `bind_addr`. Packets are compressed with `compression` on
+    /// connections whose client enables compression with the same dictionary.
+    pub fn new(
+        config: quinn::ServerConfig,
+        bind_addr: SocketAddr,
+        compression: Option<FrameCompression>,
+    ) -> Self {
         let (event_tx, event_rx) = mpsc::unbounded_channel();
 
         let listen_state = Arc::new(TransportListenState {
+            compression,
             event_tx,
             peer_map: Mutex::default(),
         });
@@ -103,6 +116,7 @@ impl ServerTransport for QuicServerTransport {
             if matches!(
                 ev,
                 ServerTransportEvent::DataReceived { peer, .. }
+                | ServerTransportEvent::DatagramReceived { peer, .. }
                     if !self.peer_alive(peer),
             ) {
                 // (drop incoming packet from kicked peer)
@@ -123,17 +137,38 @@ impl ServerTransport for QuicServerTransport {
         self.peer(id).is_ok()
     }
 
-    fn peer_send(&mut self, id: PeerId, framed: Bytes, task_guard: ErasedTaskGuard) {
+    fn peer_send(
+        &mut self,
+        id: PeerId,
+        stream: TransportStream,
+        framed: Bytes,
+        task_guard: ErasedTaskGuard,
+    ) {
         absorb_result_std::<_, PeerDisconnectError>("send a packet", || {
             self.peer(id)?
                 .send_action_tx
-                .send(PeerSendAction::Reliable { framed, task_guard })
+                .send(PeerSendAction::Reliable {
+                    stream,
+                    framed,
+                    task_guard,
+                })
                 .map_err(|_| PeerDisconnectError)?;
 
             Ok(())
         });
     }
 
+    fn peer_send_datagram(&mut self, id: PeerId, packet: Bytes) {
+        absorb_result_anyhow("send a datagram", || {
+            self.peer(id)?.conn.send_datagram(packet)?;
+            Ok(())
+        });
+    }
+
+    fn peer_max_datagram_size(&mut self, id: PeerId) -> Option<usize> {
+        self.peer(id).ok()?.conn.max_datagram_size()
+    }
+
     fn peer_kick(&mut self, id: PeerId, data: Bytes) {
         absorb_result_anyhow("kick a peer", || {
             let peer = self.peer(id)?;
@@ -213,6 +248,7 @@ impl TransportListenWorker {
                 peer_id,
                 remote_addr,
                 send_action_tx,
+                conn: conn.clone(),
                 kicked: AtomicBool::new(false),
             });
 
@@ -295,37 +331,86 @@ impl TransportPeerWorker {
                 task: accept_task,
             });
 
-        // We ask the user to send the initial packet.
-        let (tx, rx) = self
-            .conn
-            .accept_bi()
-            .await
-            .map_err(MultiError::new)
-            .context("failed to open main stream")?;
+        // We ask the user to open the streams.
+        let mut streams = accept_streams(&self.conn).await?;
+        let compression = answer_compression(
+            &mut streams[TransportStream::Normal.index()],
+            self.listen_state.compression.as_ref(),
+        )
+        .await?;
+
+        tracing::info!(
+            "Accepted streams! Compression enabled: {}",
+            compression.is_some(),
+        );
 
-        tracing::info!("Received initial packet!");
+        let (txs, rxs) = streams.into_iter().unzip();
 
-        // Process the stream!
-        run_transport_data_handler(
+        // Process the streams!
+        let datagram_task = tokio::spawn(self.clone().run_conn_datagrams().in_current_span());
+
+        let res = run_transport_data_handler(
             self.conn.clone(),
-            tokio::spawn(self.clone().run_conn_rx(rx).in_current_span()),
             tokio::spawn(
                 self.clone()
-                    .run_conn_tx(tx, send_action_rx)
+                    .run_conn_rxs(rxs, compression.clone())
+                    .in_current_span(),
+            ),
+            tokio::spawn(
+                self.clone()
+                    .run_conn_tx(txs, send_action_rx, compression)
                     .in_current_span(),
             ),
         )
+        .await;
+
+        // The connection is closed by now so this finishes promptly. Waiting on it ensures that no
+        // datagram is reported after the disconnection.
+        let _ = datagram_task.await;
+
+        res?;
+
+        Ok(())
+    }
+
+    async fn run_conn_datagrams(self) {
+        // Datagrams can be dropped at any time so they bypass the back-pressure mechanism.
+        while let Ok(packet) = self.conn.read_datagram().await {
+            self.listen_state
+                .send_event(ServerTransportEvent::DatagramReceived {
+                    peer: self.peer_state.peer_id,
+                    packet,
+                });
+        }
+
+        // (the `run_conn_inner` driver interprets the `close_reason()` for us)
+    }
+
+    async fn run_conn_rxs(
+        self,
+        rxs: Vec<quinn::RecvStream>,
+        compression: Option<FrameCompression>,
+    ) -> anyhow::Result<()> {
+        future::try_join_all(
+            rxs.into_iter()
+                .map(|rx| self.clone().run_conn_rx(rx, compression.clone())),
+        )
         .await?;
 
         Ok(())
     }
 
-    async fn run_conn_rx(self, rx: quinn::RecvStream) -> anyhow::Result<()> {
+    async fn run_conn_rx(
+        self,
+        rx: quinn::RecvStream,
+        compression: Option<FrameCompression>,
+    ) -> anyhow::Result<()> {
         let mut pressure = BackPressureAsync::new(1024);
         let mut rx = pin!(FramedRead::new(
             rx,
             FrameDecoder {
                 max_packet_size: 1024,
+                compression,
             },
         ));
 
@@ -361,39 +446,60 @@ impl TransportPeerWorker {
 
     async fn run_conn_tx(
         self,
-        mut tx: quinn::SendStream,
+        txs: Vec<quinn::SendStream>,
         mut send_action_rx: mpsc::UnboundedReceiver<PeerSendAction>,
+        compression: Option<FrameCompression>,
     ) -> anyhow::Result<()> {
-        loop {
-            // Wait for the next send request.
-            let send_action = tokio::select! {
-                send_action = send_action_rx.recv() => {
-                    // Unwrap OK: The sender is stored in the `TransportPeerState` associated with
-                    // this connection and that object isn't destroyed until this task exists.
-                    send_action.unwrap()
-                },
-                _err = self.conn.closed() => {
-                    // The `run_conn_inner` driver will interpret the `close_reason()` for us. We
-                    // should only return `Err(())` if some novel kind of error occurs.
-                    return Ok(());
+        let (queues, writers): (Vec<_>, Vec<_>) = txs
+            .into_iter()
+            .map(|tx| {
+                let (queue_tx, queue_rx) = mpsc::unbounded_channel();
+                (queue_tx, run_stream_tx(tx, queue_rx, compression.clone()))
+            })
+            .unzip();
+
+        let dispatch = async move {
+            loop {
+                // Wait for the next send request.
+                let send_action = tokio::select! {
+                    send_action = send_action_rx.recv() => {
+                        // Unwrap OK: The sender is stored in the `TransportPeerState` associated
+                        // with this connection and that object isn't destroyed until this task
+                        // exists.
+                        send_action.unwrap()
+                    },
+                    _err = self.conn.closed() => {
+                        // The `run_conn_inner` driver will interpret the `close_reason()` for us.
+                        // We should only return `Err(())` if some novel kind of error occurs.
+                        break;
+                    }
+                };
+
+                // Process it!
+                match send_action {
+                    PeerSendAction::Reliable {
+                        stream,
+                        framed,
+                        task_guard,
+                    } => {
+                        // (writers only stop early if they fail, which aborts this task anyways)
+                        let _ = queues[stream.index()].send((framed, task_guard));
+                    }
+                    PeerSendAction::Disconnect(bytes) => {
+                        self.conn
+                            .close(SocketCloseReason::Application.code().into(), &bytes);
+
+                        break;
+                    }
                 }
-            };
+            }
 
-            // Process it!
-            match send_action {
-                PeerSendAction::Reliable { framed, task_guard } => {
-                    // TODO: parse error
-                    tx.write_all(&framed).await?;
-                    drop(task_guard);
-                }
-                PeerSendAction::Disconnect(bytes) => {
-                    self.conn
-                        .close(SocketCloseReason::Application.code().into(), &bytes);
+            // Dropping the queues lets the writers finish.
+            drop(queues);
+            anyhow::Ok(())
+        };
 
-                    break;
-                }
-            }
-        }
+        tokio::try_join!(dispatch, future::try_join_all(writers))?;
 
         Ok(())
     }
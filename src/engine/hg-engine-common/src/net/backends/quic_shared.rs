@@ -1,10 +1,14 @@
 use std::{io, pin::pin};
 
 use anyhow::Context as _;
+use bytes::Bytes;
 use futures::FutureExt as _;
-use tokio::task;
+use tokio::{sync::mpsc, task};
 
-use crate::utils::lang::{flatten_tokio_join_result, FusedFuture, MultiError};
+use crate::{
+    net::{back_pressure::ErasedTaskGuard, codec::FrameCompression, transport::TransportStream},
+    utils::lang::{flatten_tokio_join_result, FusedFuture, MultiError},
+};
 
 #[derive(Debug, Copy, Clone, Hash, Eq, PartialEq, Ord, PartialOrd)]
 pub enum SocketCloseReason {
@@ -78,6 +82,131 @@ pub async fn run_transport_data_handler(
     MultiError::from_iter([first, second, third])
 }
 
+// === Streams === //
+
+pub type StreamPair = (quinn::SendStream, quinn::RecvStream);
+
+/// Opens one bidirectional stream for each [`TransportStream`], in the order of
+/// [`TransportStream::ALL`].
+///
+/// Peers only learn of a stream once data has been sent over it so each stream starts with a byte
+/// identifying it, which also lets [`accept_streams`] tell them apart.
+pub async fn open_streams(conn: &quinn::Connection) -> anyhow::Result<Vec<StreamPair>> {
+    let mut streams = Vec::new();
+
+    for stream in TransportStream::ALL {
+        let (mut tx, rx) = conn
+            .open_bi()
+            .await
+            .with_context(|| format!("failed to open {stream:?} stream"))?;
+
+        tx.set_priority(stream.priority())?;
+        tx.write_all(&[stream.index() as u8]).await?;
+
+        streams.push((tx, rx));
+    }
+
+    Ok(streams)
+}
+
+/// Accepts the streams opened by [`open_streams`], returning them in the order of
+/// [`TransportStream::ALL`].
+pub async fn accept_streams(conn: &quinn::Connection) -> anyhow::Result<Vec<StreamPair>> {
+    let mut streams = TransportStream::ALL.map(|_| None);
+
+    for _ in TransportStream::ALL {
+        let (tx, mut rx) = conn.accept_bi().await.context("failed to accept stream")?;
+
+        let mut tag = [0u8];
+        rx.read_exact(&mut tag)
+            .await
+            .context("failed to read stream tag")?;
+
+        let stream = TransportStream::from_index(usize::from(tag[0]))
+            .with_context(|| format!("unknown stream tag {}", tag[0]))?;
+
+        let slot = &mut streams[stream.index()];
+        anyhow::ensure!(
+            slot.is_none(),
+            "{stream:?} stream was opened more than once"
+        );
+
+        tx.set_priority(stream.priority())?;
+        *slot = Some((tx, rx));
+    }
+
+    Ok(streams.into_iter().map(Option::unwrap).collect())
+}
+
+/// Offers the client's compression settings to the server, returning the settings to use if the
+/// server accepted them.
+pub async fn offer_compression(
+    (tx, rx): &mut StreamPair,
+    local: Option<&FrameCompression>,
+) -> anyhow::Result<Option<FrameCompression>> {
+    let mut offer = [0u8; 9];
+
+    if let Some(local) = local {
+        offer[0] = 1;
+        offer[1..].copy_from_slice(&local.dictionary_hash().to_le_bytes());
+    }
+
+    tx.write_all(&offer).await?;
+
+    let mut answer = [0u8];
+    rx.read_exact(&mut answer)
+        .await
+        .context("failed to read compression answer")?;
+
+    Ok(local.filter(|_| answer[0] == 1).cloned())
+}
+
+/// Answers the offer sent by [`offer_compression`], accepting it if both ends enable compression
+/// with the same dictionary.
+pub async fn answer_compression(
+    (tx, rx): &mut StreamPair,
+    local: Option<&FrameCompression>,
+) -> anyhow::Result<Option<FrameCompression>> {
+    let mut offer = [0u8; 9];
+    rx.read_exact(&mut offer)
+        .await
+        .context("failed to read compression offer")?;
+
+    let remote_hash = (offer[0] == 1).then(|| u64::from_le_bytes(offer[1..].try_into().unwrap()));
+    let accepted = local.filter(|local| remote_hash == Some(local.dictionary_hash()));
+
+    tx.write_all(&[u8::from(accepted.is_some())]).await?;
+
+    Ok(accepted.cloned())
+}
+
+/// Writes the packets queued for a single stream until its queue is closed. Every stream gets its
+/// own writer so that a large write to one stream doesn't hold up the others.
+pub async fn run_stream_tx(
+    mut tx: quinn::SendStream,
+    mut queue: mpsc::UnboundedReceiver<(Bytes, ErasedTaskGuard)>,
+    compression: Option<FrameCompression>,
+) -> anyhow::Result<()> {
+    while let Some((framed, task_guard)) = queue.recv().await {
+        let framed = match &compression {
+            Some(compression) => compression.compress(framed),
+            None => framed,
+        };
+
+        match tx.write_all(&framed).await {
+            Ok(()) => {}
+            // This will already be reported by `conn.close_reason()`.
+            Err(quinn::WriteError::ConnectionLost(_)) => return Ok(()),
+            // TODO: parse other errors
+            Err(err) => return Err(err.into()),
+        }
+
+        drop(task_guard);
+    }
+
+    Ok(())
+}
+
 pub fn filter_framed_read_failure(e: anyhow::Error) -> anyhow::Result<()> {
     use quinn::ReadError::*;
 
@@ -99,3 +228,177 @@ pub fn filter_framed_read_failure(e: anyhow::Error) -> anyhow::Result<()> {
         None => Err(e),
     };
 }
+
+// === Tests === //
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use bytes::Bytes;
+    use quinn::crypto::rustls::{QuicClientConfig, QuicServerConfig};
+    use rustls::pki_types::{CertificateDer, PrivatePkcs8KeyDer};
+
+    use crate::net::{FrameCompression, TransportStream};
+
+    use super::{accept_streams, answer_compression, offer_compression, open_streams, StreamPair};
+
+    struct Connection {
+        _endpoints: [quinn::Endpoint; 2],
+        client: quinn::Connection,
+        server: quinn::Connection,
+    }
+
+    async fn connect() -> Connection {
+        // Other tests may have installed the provider already.
+        let _ = rustls::crypto::aws_lc_rs::default_provider().install_default();
+
+        let cert = rcgen::generate_simple_self_signed(vec!["localhost".into()]).unwrap();
+        let key = PrivatePkcs8KeyDer::from(cert.key_pair.serialize_der());
+        let cert = CertificateDer::from(cert.cert);
+
+        let crypto = rustls::ServerConfig::builder()
+            .with_no_client_auth()
+            .with_single_cert(vec![cert.clone()], key.into())
+            .unwrap();
+
+        let config = QuicServerConfig::try_from(crypto).unwrap();
+        let config = quinn::ServerConfig::with_crypto(Arc::new(config));
+        let server = quinn::Endpoint::server(config, "127.0.0.1:0".parse().unwrap()).unwrap();
+
+        let mut roots = rustls::RootCertStore::empty();
+        roots.add(cert).unwrap();
+
+        let crypto = rustls::ClientConfig::builder()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+
+        let config = QuicClientConfig::try_from(crypto).unwrap();
+        let config = quinn::ClientConfig::new(Arc::new(config));
+        let client = quinn::Endpoint::client("127.0.0.1:0".parse().unwrap()).unwrap();
+
+        let connecting = client
+            .connect_with(config, server.local_addr().unwrap(), "localhost")
+            .unwrap();
+
+        let (client_conn, server_conn) =
+            tokio::join!(connecting, async { server.accept().await.unwrap().await });
+
+        Connection {
+            client: client_conn.unwrap(),
+            server: server_conn.unwrap(),
+            _endpoints: [client, server],
+        }
+    }
+
+    async fn open_tagged(conn: &quinn::Connection, tags: &[u8]) -> Vec<StreamPair> {
+        let mut streams = Vec::new();
+
+        for &tag in tags {
+            let (mut tx, rx) = conn.open_bi().await.unwrap();
+            tx.write_all(&[tag]).await.unwrap();
+            streams.push((tx, rx));
+        }
+
+        streams
+    }
+
+    #[tokio::test]
+    async fn matches_streams_by_tag() {
+        let conn = connect().await;
+
+        let (opened, accepted) =
+            tokio::join!(open_streams(&conn.client), accept_streams(&conn.server));
+        let (mut opened, mut accepted) = (opened.unwrap(), accepted.unwrap());
+
+        assert_eq!(opened.len(), TransportStream::ALL.len());
+        assert_eq!(accepted.len(), TransportStream::ALL.len());
+
+        for (idx, ((tx, _), (_, rx))) in opened.iter_mut().zip(&mut accepted).enumerate() {
+            tx.write_all(&[idx as u8]).await.unwrap();
+
+            let mut data = [0u8];
+            rx.read_exact(&mut data).await.unwrap();
+            assert_eq!(data[0], idx as u8);
+        }
+
+        // Streams are told apart by their tags rather than the order in which they arrive.
+        let conn = connect().await;
+
+        let (mut opened, accepted) = tokio::join!(
+            open_tagged(&conn.client, &[2, 0, 1]),
+            accept_streams(&conn.server)
+        );
+        let mut accepted = accepted.unwrap();
+
+        for (tag, (tx, _)) in [2, 0, 1].into_iter().zip(&mut opened) {
+            tx.write_all(&[tag]).await.unwrap();
+
+            let (_, rx) = &mut accepted[usize::from(tag)];
+
+            let mut data = [0u8];
+            rx.read_exact(&mut data).await.unwrap();
+            assert_eq!(data[0], tag);
+        }
+    }
+
+    #[tokio::test]
+    async fn rejects_bad_stream_tags() {
+        let conn = connect().await;
+
+        let (_opened, accepted) = tokio::join!(
+            open_tagged(&conn.client, &[0, 7]),
+            accept_streams(&conn.server)
+        );
+        let Err(err) = accepted else {
+            panic!("expected an unknown tag to be rejected");
+        };
+        assert!(format!("{err:#}").contains("unknown stream tag 7"));
+
+        let conn = connect().await;
+
+        let (_opened, accepted) = tokio::join!(
+            open_tagged(&conn.client, &[1, 1]),
+            accept_streams(&conn.server)
+        );
+        let Err(err) = accepted else {
+            panic!("expected a duplicate stream to be rejected");
+        };
+        assert!(format!("{err:#}").contains("opened more than once"));
+    }
+
+    async fn negotiate(
+        client: Option<&FrameCompression>,
+        server: Option<&FrameCompression>,
+    ) -> [bool; 2] {
+        let conn = connect().await;
+
+        let (offered, answered) = tokio::join!(
+            async {
+                let mut stream = conn.client.open_bi().await.unwrap();
+                offer_compression(&mut stream, client).await.unwrap()
+            },
+            async {
+                let mut stream = conn.server.accept_bi().await.unwrap();
+                answer_compression(&mut stream, server).await.unwrap()
+            },
+        );
+
+        [offered.is_some(), answered.is_some()]
+    }
+
+    #[tokio::test]
+    async fn negotiates_compression() {
+        let plain = FrameCompression::default();
+        let other = FrameCompression {
+            dictionary: Bytes::from_static(b"tile map"),
+            ..FrameCompression::default()
+        };
+
+        // Both ends must enable compression with the same dictionary and agree on the outcome.
+        assert_eq!(negotiate(Some(&plain), Some(&plain)).await, [true, true]);
+        assert_eq!(negotiate(Some(&plain), Some(&other)).await, [false, false]);
+        assert_eq!(negotiate(Some(&plain), None).await, [false, false]);
+        assert_eq!(negotiate(None, Some(&plain)).await, [false, false]);
+    }
+}
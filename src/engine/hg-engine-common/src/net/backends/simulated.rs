@@ -0,0 +1,754 @@
+use std::{
+    collections::BTreeMap,
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicU64, Ordering::*},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use bytes::Bytes;
+use hg_utils::hash::{FxHashMap, FxHashSet};
+
+use crate::{
+    net::{
+        back_pressure::ErasedTaskGuard,
+        transport::{
+            ClientTransport, ClientTransportEvent, PeerDisconnectError, PeerId, ServerTransport,
+            ServerTransportEvent, TransportStream,
+        },
+    },
+    utils::lang::absorb_result_std,
+};
+
+// === Conditions === //
+
+/// The conditions of one direction of a simulated connection.
+///
+/// The underlying streams are reliable and ordered so their packets are never dropped or
+/// reordered: jitter and stalls only ever push a packet's delivery back along with every packet
+/// after it. For simplicity, this applies across streams as well, as if every stream had the same
+/// priority. Datagrams, on the other hand, can be lost and are reordered by jitter, although they
+/// never overtake a packet sent before them.
+#[derive(Debug, Clone)]
+pub struct LinkConditions {
+    /// The delay added to every packet.
+    pub latency: Duration,
+
+    /// The upper bound of a uniformly distributed delay added on top of `latency`.
+    pub jitter: Duration,
+
+    /// The throughput of the link in bytes per second or `None` for an unlimited throughput.
+    pub bandwidth: Option<u64>,
+
+    /// The chance that sending a packet stalls the link for `stall_duration` beforehand.
+    pub stall_chance: f64,
+
+    pub stall_duration: Duration,
+
+    /// The chance that a datagram is lost.
+    pub datagram_loss: f64,
+}
+
+impl Default for LinkConditions {
+    fn default() -> Self {
+        Self {
+            latency: Duration::ZERO,
+            jitter: Duration::ZERO,
+            bandwidth: None,
+            stall_chance: 0.,
+            stall_duration: Duration::ZERO,
+            datagram_loss: 0.,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct NetworkConditions {
+    /// The conditions applied to packets and connection events received from the inner transport.
+    pub inbound: LinkConditions,
+
+    /// The conditions applied to packets, kicks and disconnections sent through the simulator.
+    pub outbound: LinkConditions,
+
+    /// The chance that receiving a packet forcibly closes its connection. Servers kick the sending
+    /// peer while clients disconnect.
+    pub kick_chance: f64,
+
+    /// The seed of the simulator's random number generator. Simulators wrapping both ends of a
+    /// connection should be given different seeds.
+    pub seed: u64,
+}
+
+impl NetworkConditions {
+    /// Applies the same conditions to both directions.
+    pub fn symmetric(link: LinkConditions, seed: u64) -> Self {
+        Self {
+            inbound: link.clone(),
+            outbound: link,
+            kick_chance: 0.,
+            seed,
+        }
+    }
+}
+
+// === SimClock === //
+
+/// The clock against which simulated delays are measured.
+///
+/// Reproducing a failure requires both the same seed and the same sequence of clock readings, so
+/// tests should use a [`SimClock::manual`] clock and advance it themselves.
+#[derive(Debug, Clone)]
+pub struct SimClock(ClockKind);
+
+#[derive(Debug, Clone)]
+enum ClockKind {
+    Real(Instant),
+    Manual(Arc<AtomicU64>),
+}
+
+impl SimClock {
+    pub fn real() -> Self {
+        Self(ClockKind::Real(Instant::now()))
+    }
+
+    /// Creates a clock which only advances through [`SimClock::advance`]. Clones of the clock
+    /// share the same time.
+    pub fn manual() -> Self {
+        Self(ClockKind::Manual(Arc::default()))
+    }
+
+    pub fn now(&self) -> Duration {
+        match &self.0 {
+            ClockKind::Real(start) => start.elapsed(),
+            ClockKind::Manual(nanos) => Duration::from_nanos(nanos.load(Relaxed)),
+        }
+    }
+
+    pub fn advance(&self, dt: Duration) {
+        let ClockKind::Manual(nanos) = &self.0 else {
+            panic!("cannot advance a real-time clock");
+        };
+
+        let dt = u64::try_from(dt.as_nanos()).expect("advanced the clock too far");
+        nanos.fetch_add(dt, Relaxed);
+    }
+}
+
+// === Link === //
+
+#[derive(Debug, Default)]
+struct LinkState {
+    /// The time at which the link finishes transmitting the packets sent thus far.
+    busy_until: Duration,
+
+    /// The delivery time of the last packet sent over the link.
+    last_delivery: Duration,
+}
+
+impl LinkState {
+    /// Determines when a packet of `len` bytes sent `now` will be delivered.
+    fn schedule(
+        &mut self,
+        cond: &LinkConditions,
+        rng: &mut fastrand::Rng,
+        now: Duration,
+        len: usize,
+    ) -> Duration {
+        let mut start = now.max(self.busy_until);
+
+        if roll(rng, cond.stall_chance) {
+            start += cond.stall_duration;
+        }
+
+        self.busy_until = start + transmit_time(cond, len);
+
+        let jitter = cond.jitter.mul_f64(rng.f64());
+        let delivery = (self.busy_until + cond.latency + jitter).max(self.last_delivery);
+        self.last_delivery = delivery;
+
+        delivery
+    }
+
+    /// Determines when a datagram of `len` bytes sent `now` will be delivered or returns `None` if
+    /// it is lost. Datagrams take up bandwidth but later packets don't wait on them.
+    fn schedule_datagram(
+        &mut self,
+        cond: &LinkConditions,
+        rng: &mut fastrand::Rng,
+        now: Duration,
+        len: usize,
+    ) -> Option<Duration> {
+        if roll(rng, cond.datagram_loss) {
+            return None;
+        }
+
+        self.busy_until = now.max(self.busy_until) + transmit_time(cond, len);
+
+        let jitter = cond.jitter.mul_f64(rng.f64());
+        Some((self.busy_until + cond.latency + jitter).max(self.last_delivery))
+    }
+}
+
+fn transmit_time(cond: &LinkConditions, len: usize) -> Duration {
+    match cond.bandwidth {
+        Some(bandwidth) => Duration::from_secs_f64(len as f64 / bandwidth.max(1) as f64),
+        None => Duration::ZERO,
+    }
+}
+
+#[derive(Debug)]
+struct DelayQueue<T> {
+    /// Items keyed by their delivery time and then by their insertion order.
+    items: BTreeMap<(Duration, u64), T>,
+    next_seq: u64,
+}
+
+impl<T> Default for DelayQueue<T> {
+    fn default() -> Self {
+        Self {
+            items: BTreeMap::new(),
+            next_seq: 0,
+        }
+    }
+}
+
+impl<T> DelayQueue<T> {
+    fn push(&mut self, at: Duration, item: T) {
+        self.items.insert((at, self.next_seq), item);
+        self.next_seq += 1;
+    }
+
+    fn pop_due(&mut self, now: Duration) -> Option<T> {
+        let entry = self.items.first_entry()?;
+
+        if entry.key().0 > now {
+            return None;
+        }
+
+        Some(entry.remove())
+    }
+}
+
+fn roll(rng: &mut fastrand::Rng, chance: f64) -> bool {
+    chance > 0. && rng.f64() < chance
+}
+
+// === Server === //
+
+/// Wraps a [`ServerTransport`] to simulate the given [`NetworkConditions`] on every peer's
+/// connection. Delayed events are only delivered and delayed sends are only forwarded while the
+/// transport is being used so it should be processed at least once per tick.
+#[derive(Debug)]
+pub struct SimulatedServerTransport {
+    inner: Box<dyn ServerTransport>,
+    conditions: NetworkConditions,
+    clock: SimClock,
+    rng: fastrand::Rng,
+
+    /// The inbound and outbound links of every peer.
+    links: FxHashMap<PeerId, (LinkState, LinkState)>,
+
+    inbound: DelayQueue<ServerTransportEvent>,
+    outbound: DelayQueue<(PeerId, ServerSendAction)>,
+
+    /// Peers which have been kicked but whose `Disconnected` event has yet to be delivered.
+    kicked: FxHashSet<PeerId>,
+}
+
+#[derive(Debug)]
+enum ServerSendAction {
+    Reliable {
+        stream: TransportStream,
+        framed: Bytes,
+        task_guard: ErasedTaskGuard,
+    },
+    Datagram(Bytes),
+    Kick(Bytes),
+}
+
+impl SimulatedServerTransport {
+    pub fn new(
+        inner: Box<dyn ServerTransport>,
+        conditions: NetworkConditions,
+        clock: SimClock,
+    ) -> Self {
+        Self {
+            inner,
+            rng: fastrand::Rng::with_seed(conditions.seed),
+            conditions,
+            clock,
+            links: FxHashMap::default(),
+            inbound: DelayQueue::default(),
+            outbound: DelayQueue::default(),
+            kicked: FxHashSet::default(),
+        }
+    }
+
+    pub fn conditions(&self) -> &NetworkConditions {
+        &self.conditions
+    }
+
+    /// Changes the conditions of the simulation. Packets which have already been scheduled keep
+    /// their delivery times.
+    pub fn set_conditions(&mut self, conditions: NetworkConditions) {
+        self.conditions = conditions;
+    }
+
+    fn schedule_inbound(&mut self, now: Duration, ev: ServerTransportEvent) {
+        let (peer, len) = match &ev {
+            ServerTransportEvent::Connected { peer, .. }
+            | ServerTransportEvent::Disconnected { peer, .. } => (Some(*peer), 0),
+            ServerTransportEvent::DataReceived { peer, packet, .. } => (Some(*peer), packet.len()),
+            &ServerTransportEvent::DatagramReceived { peer, ref packet } => {
+                let at = self.links.entry(peer).or_default().0.schedule_datagram(
+                    &self.conditions.inbound,
+                    &mut self.rng,
+                    now,
+                    packet.len(),
+                );
+
+                if let Some(at) = at {
+                    self.inbound.push(at, ev);
+                }
+
+                return;
+            }
+            ServerTransportEvent::Shutdown { .. } => (None, 0),
+        };
+
+        let at = match peer {
+            Some(peer) => self.links.entry(peer).or_default().0.schedule(
+                &self.conditions.inbound,
+                &mut self.rng,
+                now,
+                len,
+            ),
+            // Shutdowns must not overtake the events of any peer.
+            None => self
+                .links
+                .values()
+                .map(|(inbound, _)| inbound.last_delivery)
+                .fold(now, Duration::max),
+        };
+
+        if let &ServerTransportEvent::DataReceived { peer, .. } = &ev {
+            if !self.kicked.contains(&peer) && roll(&mut self.rng, self.conditions.kick_chance) {
+                tracing::info!("Simulating a forced kick of peer {peer}");
+                self.kicked.insert(peer);
+                self.inner
+                    .peer_kick(peer, Bytes::from_static(b"simulated kick"));
+            }
+        }
+
+        self.inbound.push(at, ev);
+    }
+
+    fn schedule_outbound(&mut self, now: Duration, peer: PeerId, action: ServerSendAction) {
+        let link = &mut self.links.entry(peer).or_default().1;
+        let cond = &self.conditions.outbound;
+
+        let at = match &action {
+            ServerSendAction::Reliable { framed, .. } => {
+                Some(link.schedule(cond, &mut self.rng, now, framed.len()))
+            }
+            ServerSendAction::Datagram(packet) => {
+                link.schedule_datagram(cond, &mut self.rng, now, packet.len())
+            }
+            ServerSendAction::Kick(_) => Some(link.schedule(cond, &mut self.rng, now, 0)),
+        };
+
+        if let Some(at) = at {
+            self.outbound.push(at, (peer, action));
+        }
+
+        self.flush_outbound(now);
+    }
+
+    fn flush_outbound(&mut self, now: Duration) {
+        while let Some((peer, action)) = self.outbound.pop_due(now) {
+            match action {
+                ServerSendAction::Reliable {
+                    stream,
+                    framed,
+                    task_guard,
+                } => {
+                    self.inner.peer_send(peer, stream, framed, task_guard);
+                }
+                ServerSendAction::Datagram(packet) => {
+                    // (drop outgoing datagrams to kicked peers)
+                    if !self.kicked.contains(&peer) {
+                        self.inner.peer_send_datagram(peer, packet);
+                    }
+                }
+                ServerSendAction::Kick(data) => {
+                    self.inner.peer_kick(peer, data);
+                }
+            }
+        }
+    }
+}
+
+impl ServerTransport for SimulatedServerTransport {
+    fn process(&mut self) -> Option<ServerTransportEvent> {
+        let now = self.clock.now();
+        self.flush_outbound(now);
+
+        while let Some(ev) = self.inner.process() {
+            self.schedule_inbound(now, ev);
+        }
+
+        while let Some(ev) = self.inbound.pop_due(now) {
+            match &ev {
+                ServerTransportEvent::DataReceived { peer, .. } if self.kicked.contains(peer) => {
+                    // (drop incoming packet from kicked peer)
+                    continue;
+                }
+                ServerTransportEvent::DatagramReceived { peer, .. }
+                    if self.kicked.contains(peer) || !self.links.contains_key(peer) =>
+                {
+                    // (drop incoming datagram from kicked or disconnected peer)
+                    continue;
+                }
+                ServerTransportEvent::Disconnected { peer, .. } => {
+                    self.kicked.remove(peer);
+                    self.links.remove(peer);
+                }
+                _ => {}
+            }
+
+            return Some(ev);
+        }
+
+        None
+    }
+
+    fn peer_remote_addr(&mut self, id: PeerId) -> Result<SocketAddr, PeerDisconnectError> {
+        if self.kicked.contains(&id) {
+            return Err(PeerDisconnectError);
+        }
+
+        self.inner.peer_remote_addr(id)
+    }
+
+    fn peer_alive(&mut self, id: PeerId) -> bool {
+        !self.kicked.contains(&id) && self.inner.peer_alive(id)
+    }
+
+    fn peer_send(
+        &mut self,
+        id: PeerId,
+        stream: TransportStream,
+        framed: Bytes,
+        task_guard: ErasedTaskGuard,
+    ) {
+        absorb_result_std("send a packet", || {
+            if !self.peer_alive(id) {
+                return Err(PeerDisconnectError);
+            }
+
+            let now = self.clock.now();
+            let action = ServerSendAction::Reliable {
+                stream,
+                framed,
+                task_guard,
+            };
+
+            self.schedule_outbound(now, id, action);
+            Ok(())
+        });
+    }
+
+    fn peer_send_datagram(&mut self, id: PeerId, packet: Bytes) {
+        absorb_result_std("send a datagram", || {
+            if !self.peer_alive(id) {
+                return Err(PeerDisconnectError);
+            }
+
+            let now = self.clock.now();
+            self.schedule_outbound(now, id, ServerSendAction::Datagram(packet));
+            Ok(())
+        });
+    }
+
+    fn peer_max_datagram_size(&mut self, id: PeerId) -> Option<usize> {
+        if self.kicked.contains(&id) {
+            return None;
+        }
+
+        self.inner.peer_max_datagram_size(id)
+    }
+
+    fn peer_kick(&mut self, id: PeerId, data: Bytes) {
+        absorb_result_std("kick a peer", || {
+            if !self.peer_alive(id) {
+                return Err(PeerDisconnectError);
+            }
+
+            // The kick is delayed like any other send but the peer is considered dead immediately.
+            self.kicked.insert(id);
+
+            let now = self.clock.now();
+            self.schedule_outbound(now, id, ServerSendAction::Kick(data));
+            Ok(())
+        });
+    }
+}
+
+// === Client === //
+
+/// Wraps a [`ClientTransport`] to simulate the given [`NetworkConditions`] on its connection. As
+/// with [`SimulatedServerTransport`], the transport should be processed at least once per tick.
+#[derive(Debug)]
+pub struct SimulatedClientTransport {
+    inner: Box<dyn ClientTransport>,
+    conditions: NetworkConditions,
+    clock: SimClock,
+    rng: fastrand::Rng,
+    inbound_link: LinkState,
+    outbound_link: LinkState,
+    inbound: DelayQueue<ClientTransportEvent>,
+    outbound: DelayQueue<ClientSendAction>,
+
+    /// Whether the connection has been closed on either end, after which no more packets are
+    /// delivered.
+    disconnected: bool,
+}
+
+#[derive(Debug)]
+enum ClientSendAction {
+    Reliable {
+        stream: TransportStream,
+        framed: Bytes,
+        task_guard: ErasedTaskGuard,
+    },
+    Datagram(Bytes),
+    Disconnect(Bytes),
+}
+
+impl SimulatedClientTransport {
+    pub fn new(
+        inner: Box<dyn ClientTransport>,
+        conditions: NetworkConditions,
+        clock: SimClock,
+    ) -> Self {
+        Self {
+            inner,
+            rng: fastrand::Rng::with_seed(conditions.seed),
+            conditions,
+            clock,
+            inbound_link: LinkState::default(),
+            outbound_link: LinkState::default(),
+            inbound: DelayQueue::default(),
+            outbound: DelayQueue::default(),
+            disconnected: false,
+        }
+    }
+
+    pub fn conditions(&self) -> &NetworkConditions {
+        &self.conditions
+    }
+
+    /// Changes the conditions of the simulation. Packets which have already been scheduled keep
+    /// their delivery times.
+    pub fn set_conditions(&mut self, conditions: NetworkConditions) {
+        self.conditions = conditions;
+    }
+
+    fn schedule_outbound(&mut self, action: ClientSendAction) {
+        let now = self.clock.now();
+        let link = &mut self.outbound_link;
+        let cond = &self.conditions.outbound;
+
+        let at = match &action {
+            ClientSendAction::Reliable { framed, .. } => {
+                Some(link.schedule(cond, &mut self.rng, now, framed.len()))
+            }
+            ClientSendAction::Datagram(packet) => {
+                link.schedule_datagram(cond, &mut self.rng, now, packet.len())
+            }
+            ClientSendAction::Disconnect(_) => Some(link.schedule(cond, &mut self.rng, now, 0)),
+        };
+
+        if let Some(at) = at {
+            self.outbound.push(at, action);
+        }
+
+        self.flush_outbound(now);
+    }
+
+    fn flush_outbound(&mut self, now: Duration) {
+        while let Some(action) = self.outbound.pop_due(now) {
+            match action {
+                ClientSendAction::Reliable {
+                    stream,
+                    framed,
+                    task_guard,
+                } => {
+                    self.inner.send(stream, framed, task_guard);
+                }
+                ClientSendAction::Datagram(packet) => {
+                    if !self.disconnected {
+                        self.inner.send_datagram(packet);
+                    }
+                }
+                ClientSendAction::Disconnect(data) => {
+                    self.inner.disconnect(data);
+                }
+            }
+        }
+    }
+}
+
+impl ClientTransport for SimulatedClientTransport {
+    fn process(&mut self) -> Option<ClientTransportEvent> {
+        let now = self.clock.now();
+        self.flush_outbound(now);
+
+        while let Some(ev) = self.inner.process() {
+            let (is_data, len) = match &ev {
+                ClientTransportEvent::DataReceived { packet, .. } => (true, packet.len()),
+                ClientTransportEvent::DatagramReceived { packet } => {
+                    let at = self.inbound_link.schedule_datagram(
+                        &self.conditions.inbound,
+                        &mut self.rng,
+                        now,
+                        packet.len(),
+                    );
+
+                    if let Some(at) = at {
+                        self.inbound.push(at, ev);
+                    }
+
+                    continue;
+                }
+                _ => (false, 0),
+            };
+
+            if is_data && !self.disconnected && roll(&mut self.rng, self.conditions.kick_chance) {
+                tracing::info!("Simulating a forced disconnect");
+                self.disconnected = true;
+                self.inner.disconnect(Bytes::new());
+            }
+
+            let at = self
+                .inbound_link
+                .schedule(&self.conditions.inbound, &mut self.rng, now, len);
+
+            self.inbound.push(at, ev);
+        }
+
+        while let Some(ev) = self.inbound.pop_due(now) {
+            match ev {
+                ClientTransportEvent::DataReceived { .. }
+                | ClientTransportEvent::DatagramReceived { .. }
+                    if self.disconnected =>
+                {
+                    continue;
+                }
+                ClientTransportEvent::Disconnected { .. } => {
+                    self.disconnected = true;
+                }
+                _ => {}
+            }
+
+            return Some(ev);
+        }
+
+        None
+    }
+
+    fn send(&mut self, stream: TransportStream, framed: Bytes, task_guard: ErasedTaskGuard) {
+        self.schedule_outbound(ClientSendAction::Reliable {
+            stream,
+            framed,
+            task_guard,
+        });
+    }
+
+    fn send_datagram(&mut self, packet: Bytes) {
+        self.schedule_outbound(ClientSendAction::Datagram(packet));
+    }
+
+    fn max_datagram_size(&mut self) -> Option<usize> {
+        if self.disconnected {
+            return None;
+        }
+
+        self.inner.max_datagram_size()
+    }
+
+    fn disconnect(&mut self, data: Bytes) {
+        self.disconnected = true;
+        self.schedule_outbound(ClientSendAction::Disconnect(data));
+    }
+}
+
+// === Tests === //
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::net::{
+        loopback::LoopbackNetwork, ClientTransport as _, ErasedTaskGuard, FrameEncoder,
+        ServerTransport as _, ServerTransportEvent, TransportStream,
+    };
+
+    use super::{LinkConditions, NetworkConditions, SimClock, SimulatedServerTransport};
+
+    #[test]
+    fn delays_packets_in_order() {
+        let net = LoopbackNetwork::new();
+        let clock = SimClock::manual();
+        let mut server = SimulatedServerTransport::new(
+            Box::new(net.server()),
+            NetworkConditions {
+                inbound: LinkConditions {
+                    latency: Duration::from_millis(50),
+                    jitter: Duration::from_millis(30),
+                    bandwidth: Some(1000),
+                    ..LinkConditions::default()
+                },
+                ..NetworkConditions::default()
+            },
+            clock.clone(),
+        );
+
+        let mut client = net.connect();
+
+        for i in 0..3u8 {
+            let mut encoder = FrameEncoder::new();
+            encoder.extend_from_slice(&[i; 10]);
+            client.send(
+                TransportStream::Normal,
+                encoder.finish(),
+                ErasedTaskGuard::noop(),
+            );
+        }
+
+        // Nothing arrives before the latency has elapsed.
+        assert!(server.process().is_none());
+        clock.advance(Duration::from_millis(49));
+        assert!(server.process().is_none());
+
+        // Everything arrives in order once the worst-case delay has elapsed: 33 bytes at 1000
+        // bytes per second plus the latency and the jitter.
+        clock.advance(Duration::from_millis(71));
+
+        assert!(matches!(
+            server.process(),
+            Some(ServerTransportEvent::Connected { .. }),
+        ));
+
+        for i in 0..3u8 {
+            assert!(matches!(
+                server.process(),
+                Some(ServerTransportEvent::DataReceived { packet, .. }) if packet == [i; 10][..],
+            ));
+        }
+
+        assert!(server.process().is_none());
+    }
+}
@@ -3,12 +3,17 @@ use std::{
     ops::{Deref, DerefMut},
 };
 
+use anyhow::Context as _;
 use bytes::{Buf, BufMut, Bytes, BytesMut};
 use tokio_util::codec::Decoder;
 use varuint::{Deserializable as _, Serializable as _, Varint};
 
 use super::RpcPacket;
 
+// Every frame starts with a varint header holding the length of its body shifted left by one. The
+// low bit is set if the body is compressed, in which case the body starts with a varint holding
+// its decompressed length followed by the LZ4 block itself.
+
 // === Encoder === //
 
 pub struct FrameEncoder {
@@ -39,7 +44,7 @@ impl Clone for FrameEncoder {
 impl FrameEncoder {
     pub fn new() -> Self {
         let mut data = BytesMut::new();
-        data.put_bytes(0u8, Varint(isize::MAX as u64).size_hint());
+        data.put_bytes(0u8, Varint(u64::MAX).size_hint());
         let header = data.split();
 
         Self { header, data }
@@ -53,7 +58,7 @@ impl FrameEncoder {
 
     pub fn finish(mut self) -> Bytes {
         // Write header
-        let packet_len = Varint(self.data.len() as u64);
+        let packet_len = Varint((self.data.len() as u64) << 1);
         let header_len = packet_len.size_hint();
         let header_start = self.header.len() - header_len;
         let mut packet_len_buf = &mut self.header[header_start..];
@@ -66,6 +71,18 @@ impl FrameEncoder {
         self.header.advance(header_start);
         self.header.freeze()
     }
+
+    /// Produces the packet without its length header, e.g. to send it as a datagram.
+    pub fn finish_unframed(self) -> Bytes {
+        self.data.freeze()
+    }
+
+    /// Adds a length header to a packet produced by [`FrameEncoder::finish_unframed`].
+    pub fn frame(packet: &[u8]) -> Bytes {
+        let mut encoder = Self::new();
+        encoder.extend_from_slice(packet);
+        encoder.finish()
+    }
 }
 
 impl Deref for FrameEncoder {
@@ -82,11 +99,106 @@ impl DerefMut for FrameEncoder {
     }
 }
 
+// === Compression === //
+
+/// The settings of the optional compression stage of the frame codec. Compression is only used on
+/// connections whose ends both enable it with the same dictionary.
+#[derive(Debug, Clone)]
+pub struct FrameCompression {
+    /// Packets whose bodies are smaller than this many bytes are never compressed.
+    pub threshold: usize,
+
+    /// Data resembling the packets being sent, which helps compress smaller packets.
+    pub dictionary: Bytes,
+}
+
+impl Default for FrameCompression {
+    fn default() -> Self {
+        Self {
+            threshold: 256,
+            dictionary: Bytes::new(),
+        }
+    }
+}
+
+impl FrameCompression {
+    /// Identifies the dictionary during negotiation. This is the 64-bit FNV-1a hash of its bytes so
+    /// peers agree on it regardless of their build or platform.
+    pub fn dictionary_hash(&self) -> u64 {
+        const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+        const PRIME: u64 = 0x0000_0100_0000_01b3;
+
+        self.dictionary.iter().fold(OFFSET_BASIS, |hash, &byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(PRIME)
+        })
+    }
+
+    /// Compresses a single frame produced by [`FrameEncoder::finish`] if it is large enough and if
+    /// doing so actually makes it smaller. Anything else is returned as-is.
+    pub fn compress(&self, framed: Bytes) -> Bytes {
+        let mut body = &framed[..];
+        let Ok(Varint(header)) = Varint::<u64>::deserialize(&mut body) else {
+            return framed;
+        };
+
+        // Skip partial frames, multiple frames and compressed frames.
+        if header != (body.len() as u64) << 1 || body.len() < self.threshold {
+            return framed;
+        }
+
+        let compressed = lz4_flex::block::compress_with_dict(body, &self.dictionary);
+        let decompressed_len = Varint(body.len() as u64);
+        let compressed_len = decompressed_len.size_hint() + compressed.len();
+
+        if compressed_len >= body.len() {
+            return framed;
+        }
+
+        let header = Varint(((compressed_len as u64) << 1) | 1);
+        let mut out = Vec::with_capacity(header.size_hint() + compressed_len);
+        header.serialize(&mut out).unwrap();
+        decompressed_len.serialize(&mut out).unwrap();
+        out.extend_from_slice(&compressed);
+
+        Bytes::from(out)
+    }
+
+    fn decompress(&self, mut body: &[u8], max_packet_size: usize) -> anyhow::Result<Bytes> {
+        let Varint(packet_len) = Varint::<u64>::deserialize(&mut body)
+            .context("failed to read decompressed packet length")?;
+
+        // Check the decompressed size before allocating anything to prevent decompression bombs.
+        let Some(packet_len) = usize::try_from(packet_len)
+            .ok()
+            .filter(|&v| v <= max_packet_size)
+        else {
+            anyhow::bail!("decompressed packet is too large ({packet_len} > {max_packet_size})");
+        };
+
+        let mut packet = vec![0; packet_len];
+        let written =
+            lz4_flex::block::decompress_into_with_dict(body, &mut packet, &self.dictionary)
+                .context("failed to decompress packet")?;
+
+        anyhow::ensure!(
+            written == packet_len,
+            "decompressed packet is shorter than advertised ({written} < {packet_len})",
+        );
+
+        Ok(Bytes::from(packet))
+    }
+}
+
 // === Decoder === //
 
 #[derive(Debug)]
 pub struct FrameDecoder {
+    /// The maximum size of a packet, checked against both the size of its frame and, for
+    /// compressed packets, its decompressed size.
     pub max_packet_size: usize,
+
+    /// The negotiated compression settings or `None` if compressed packets should be rejected.
+    pub compression: Option<FrameCompression>,
 }
 
 impl Decoder for FrameDecoder {
@@ -96,11 +208,12 @@ impl Decoder for FrameDecoder {
     fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
         // Decode header
         let mut cursor = &src[..];
-        let Ok(Varint(packet_len)) = Varint::<u64>::deserialize(&mut cursor) else {
+        let Ok(Varint(header)) = Varint::<u64>::deserialize(&mut cursor) else {
             return Ok(None);
         };
 
         let header_len = src.len() - cursor.len();
+        let (packet_len, is_compressed) = (header >> 1, header & 1 == 1);
 
         let Some(packet_len) = usize::try_from(packet_len)
             .ok()
@@ -119,6 +232,85 @@ impl Decoder for FrameDecoder {
 
         src.advance(header_len);
 
-        Ok(Some(src.split_to(packet_len).freeze()))
+        let packet = src.split_to(packet_len);
+
+        if !is_compressed {
+            return Ok(Some(packet.freeze()));
+        }
+
+        let Some(compression) = &self.compression else {
+            anyhow::bail!("received a compressed packet but compression was not negotiated");
+        };
+
+        compression
+            .decompress(&packet, self.max_packet_size)
+            .map(Some)
+    }
+}
+
+// === Tests === //
+
+#[cfg(test)]
+mod tests {
+    use bytes::{Bytes, BytesMut};
+    use tokio_util::codec::Decoder as _;
+
+    use super::{FrameCompression, FrameDecoder, FrameEncoder};
+
+    #[test]
+    fn compresses_large_frames() {
+        let compression = FrameCompression::default();
+        let mut decoder = FrameDecoder {
+            max_packet_size: 1024,
+            compression: Some(compression.clone()),
+        };
+
+        let mut small = FrameEncoder::new();
+        small.extend_from_slice(b"hello");
+        let small = small.finish();
+
+        let mut large = FrameEncoder::new();
+        large.extend_from_slice(&[42; 1000]);
+        let large = large.finish();
+
+        // Only frames above the threshold get compressed.
+        assert_eq!(compression.compress(small.clone()), small);
+
+        let compressed = compression.compress(large.clone());
+        assert!(compressed.len() < large.len());
+
+        let mut src = BytesMut::from(&compressed[..]);
+        src.extend_from_slice(&small);
+
+        assert_eq!(decoder.decode(&mut src).unwrap().unwrap(), large[2..]);
+        assert_eq!(decoder.decode(&mut src).unwrap().unwrap(), small[1..]);
+
+        // Packets which decompress past the limit are rejected even if their frames are small.
+        decoder.max_packet_size = 999;
+        assert!(decoder
+            .decode(&mut BytesMut::from(&compressed[..]))
+            .is_err());
+
+        // So are compressed packets on connections which didn't negotiate compression.
+        decoder.compression = None;
+        assert!(decoder
+            .decode(&mut BytesMut::from(&compressed[..]))
+            .is_err());
+    }
+
+    #[test]
+    fn hashes_dictionaries_stably() {
+        let hash = |dictionary: &'static [u8]| {
+            FrameCompression {
+                threshold: 0,
+                dictionary: Bytes::from_static(dictionary),
+            }
+            .dictionary_hash()
+        };
+
+        // These are the published FNV-1a test vectors.
+        assert_eq!(hash(b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(hash(b"a"), 0xaf63_dc4c_8601_ec8c);
+        assert_eq!(hash(b"foobar"), 0x8594_4171_f739_67e8);
     }
 }
@@ -3,7 +3,7 @@ use std::{fmt, net::SocketAddr, num::NonZeroU64};
 use bytes::Bytes;
 use thiserror::Error;
 
-use super::back_pressure::ErasedTaskGuard;
+use super::{back_pressure::ErasedTaskGuard, codec::FrameEncoder};
 
 #[derive(Debug, Copy, Clone, Hash, Eq, PartialEq, Ord, PartialOrd)]
 pub struct PeerId(pub NonZeroU64);
@@ -14,6 +14,43 @@ impl fmt::Display for PeerId {
     }
 }
 
+/// One of the reliable streams of a connection. Packets sent over the same stream arrive in the
+/// order in which they were sent but packets sent over different streams may overtake one another.
+#[derive(Debug, Copy, Clone, Hash, Eq, PartialEq, Ord, PartialOrd, Default)]
+pub enum TransportStream {
+    /// Latency-sensitive traffic such as movement, sent ahead of every other stream.
+    Realtime,
+
+    /// Everything else, including the login handshake.
+    #[default]
+    Normal,
+
+    /// Large transfers which would otherwise hold up the other streams, sent last.
+    Bulk,
+}
+
+impl TransportStream {
+    pub const ALL: [Self; 3] = [Self::Realtime, Self::Normal, Self::Bulk];
+
+    pub const fn index(self) -> usize {
+        self as usize
+    }
+
+    pub fn from_index(index: usize) -> Option<Self> {
+        Self::ALL.get(index).copied()
+    }
+
+    /// The priority of the stream relative to the others, with higher priorities being sent
+    /// first.
+    pub const fn priority(self) -> i32 {
+        match self {
+            Self::Realtime => 1,
+            Self::Normal => 0,
+            Self::Bulk => -1,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Error)]
 #[error("peer disconnected")]
 pub struct ShutdownError;
@@ -32,6 +69,9 @@ pub enum ClientTransportEvent {
         packet: Bytes,
         task: ErasedTaskGuard,
     },
+    DatagramReceived {
+        packet: Bytes,
+    },
 }
 
 #[derive(Debug)]
@@ -49,6 +89,10 @@ pub enum ServerTransportEvent {
         packet: Bytes,
         task: ErasedTaskGuard,
     },
+    DatagramReceived {
+        peer: PeerId,
+        packet: Bytes,
+    },
     Shutdown {
         cause: anyhow::Result<()>,
     },
@@ -57,7 +101,33 @@ pub enum ServerTransportEvent {
 pub trait ClientTransport: fmt::Debug {
     fn process(&mut self) -> Option<ClientTransportEvent>;
 
-    fn send(&mut self, framed: Bytes, task_guard: ErasedTaskGuard);
+    fn send(&mut self, stream: TransportStream, framed: Bytes, task_guard: ErasedTaskGuard);
+
+    /// Sends an unframed packet which may be lost or reordered relative to every other packet.
+    /// Packets larger than [`ClientTransport::max_datagram_size`] are dropped.
+    fn send_datagram(&mut self, packet: Bytes);
+
+    /// The size of the largest datagram which can currently be sent or `None` if datagrams cannot
+    /// be sent at all, e.g. because the connection has yet to be established.
+    fn max_datagram_size(&mut self) -> Option<usize>;
+
+    /// Sends an unframed packet as a datagram if it fits in one and frames it onto `stream`
+    /// otherwise.
+    fn send_unreliable(&mut self, stream: TransportStream, packet: Bytes) {
+        let fits_datagram = self
+            .max_datagram_size()
+            .is_some_and(|max| packet.len() <= max);
+
+        if fits_datagram {
+            self.send_datagram(packet);
+        } else {
+            self.send(
+                stream,
+                FrameEncoder::frame(&packet),
+                ErasedTaskGuard::noop(),
+            );
+        }
+    }
 
     fn disconnect(&mut self, data: Bytes);
 }
@@ -69,7 +139,38 @@ pub trait ServerTransport: fmt::Debug {
 
     fn peer_alive(&mut self, id: PeerId) -> bool;
 
-    fn peer_send(&mut self, id: PeerId, framed: Bytes, task_guard: ErasedTaskGuard);
+    fn peer_send(
+        &mut self,
+        id: PeerId,
+        stream: TransportStream,
+        framed: Bytes,
+        task_guard: ErasedTaskGuard,
+    );
+
+    /// Sends an unframed packet which may be lost or reordered relative to every other packet.
+    /// Packets larger than [`ServerTransport::peer_max_datagram_size`] are dropped.
+    fn peer_send_datagram(&mut self, id: PeerId, packet: Bytes);
+
+    fn peer_max_datagram_size(&mut self, id: PeerId) -> Option<usize>;
+
+    /// Sends an unframed packet as a datagram if it fits in one and frames it onto `stream`
+    /// otherwise.
+    fn peer_send_unreliable(&mut self, id: PeerId, stream: TransportStream, packet: Bytes) {
+        let fits_datagram = self
+            .peer_max_datagram_size(id)
+            .is_some_and(|max| packet.len() <= max);
+
+        if fits_datagram {
+            self.peer_send_datagram(id, packet);
+        } else {
+            self.peer_send(
+                id,
+                stream,
+                FrameEncoder::frame(&packet),
+                ErasedTaskGuard::noop(),
+            );
+        }
+    }
 
     fn peer_kick(&mut self, id: PeerId, data: Bytes);
 }
@@ -23,7 +23,8 @@ use crate::{
 };
 
 use super::{
-    BadRpcNodeKindError, NoSuchRpcNodeError, RpcCbHeader, RpcKind, RpcNodeId, RpcNodeLookupError,
+    is_newer_unreliable_seq, BadRpcNodeKindError, NoSuchRpcNodeError, RpcCbHeader, RpcChannel,
+    RpcKind, RpcNodeId, RpcNodeLookupError,
 };
 
 // === RpcClient === //
@@ -35,7 +36,7 @@ pub struct RpcClient {
     node_id_map: FxHashMap<RpcNodeId, Obj<RpcClientNode>>,
     kinds_by_name: FxHashMap<&'static str, NamedTypeId>,
     kinds_by_ty: FxHashMap<NamedTypeId, Arc<dyn KindStateErased>>,
-    send_queue: Vec<FrameEncoder>,
+    send_queue: Vec<(RpcChannel, FrameEncoder)>,
     protocol_errors: Vec<anyhow::Error>,
     frozen: bool,
 }
@@ -222,6 +223,8 @@ impl RpcClient {
                         node_id,
                         userdata_ty: NamedTypeId::of::<()>(),
                         userdata: Index::DANGLING,
+                        next_unreliable_seq: 0,
+                        last_unreliable_seq: None,
                     });
 
                     entry.insert(rpc);
@@ -249,6 +252,22 @@ impl RpcClient {
 
                     kind_state.push_message(rpc, message)?;
                 }
+                RpcCbHeader::SendUnreliableMessage(node_id, seq) => {
+                    let message = packet.expect()?;
+
+                    // Unreliable messages can arrive before their node is created or after it is
+                    // deleted so we ignore rather than reject messages to unknown nodes.
+                    if let Ok(mut rpc) = self.lookup_any_node(node_id) {
+                        if is_newer_unreliable_seq(seq, rpc.last_unreliable_seq) {
+                            rpc.last_unreliable_seq = Some(seq);
+
+                            let kind_state = self.kinds_by_ty.get_mut(&rpc.kind_id).unwrap();
+                            let kind_state = Arc::get_mut(kind_state).unwrap();
+
+                            kind_state.push_message(rpc, message)?;
+                        }
+                    }
+                }
             }
         };
 
@@ -256,7 +275,7 @@ impl RpcClient {
     }
 
     #[must_use]
-    pub fn flush_sends(&mut self) -> Vec<FrameEncoder> {
+    pub fn flush_sends(&mut self) -> Vec<(RpcChannel, FrameEncoder)> {
         mem::take(&mut self.send_queue)
     }
 
@@ -290,6 +309,12 @@ pub struct RpcClientNode {
     kind_id: NamedTypeId,
     userdata_ty: NamedTypeId,
     userdata: Index,
+
+    /// The sequence number of the next unreliable message sent by this node.
+    next_unreliable_seq: u32,
+
+    /// The sequence number of the most recent unreliable message delivered to this node.
+    last_unreliable_seq: Option<u32>,
 }
 
 component!(RpcClientNode);
@@ -313,9 +338,19 @@ impl RpcClientNode {
         let mut encoder = FrameEncoder::new();
 
         encoder.encode_multi_part(packet);
-        encoder.encode_multi_part(&RpcSbHeader::SendMessage(self.node_id));
 
-        self.client.send_queue.push(encoder);
+        let channel = if K::is_unreliable_sb(packet) {
+            let seq = self.next_unreliable_seq;
+            self.next_unreliable_seq = seq.wrapping_add(1);
+
+            encoder.encode_multi_part(&RpcSbHeader::SendUnreliableMessage(self.node_id, seq));
+            RpcChannel::Unreliable(K::STREAM)
+        } else {
+            encoder.encode_multi_part(&RpcSbHeader::SendMessage(self.node_id));
+            RpcChannel::Reliable(K::STREAM)
+        };
+
+        self.client.send_queue.push((channel, encoder));
     }
 
     pub fn opt_userdata<T: Component>(&self) -> Result<Obj<T>, BadRpcNodeKindError> {
@@ -549,3 +584,65 @@ impl<'a, K: RpcKind> ClientMessageRequest<'a, K> {
         self.rpc().userdata()
     }
 }
+
+// === Tests === //
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroU64;
+
+    use bytes::{Bytes, BytesMut};
+    use hg_ecs::{bind, Entity, World};
+
+    use crate::{
+        net::{MultiPartSerializeExt as _, RpcPacket},
+        rpc::{RpcCbHeader, RpcKind, RpcNodeId},
+    };
+
+    use super::RpcClient;
+
+    struct PositionRpcKind;
+
+    impl RpcKind for PositionRpcKind {
+        const ID: &'static str = "position";
+
+        type Catchup = ();
+        type ServerBound = ();
+        type ClientBound = u32;
+    }
+
+    fn packet(header: RpcCbHeader, body: &impl RpcPacket) -> Bytes {
+        let mut packet = BytesMut::new();
+        packet.encode_multi_part(body);
+        packet.encode_multi_part(&header);
+        packet.freeze()
+    }
+
+    #[test]
+    fn drops_stale_unreliable_messages() {
+        let mut world = World::new();
+        bind!(world);
+
+        let mut client = Entity::new(Entity::root()).add(RpcClient::new());
+        client.define::<PositionRpcKind>();
+        client.reset().unwrap();
+
+        let node = RpcNodeId(NonZeroU64::new(1).unwrap());
+        client.recv_packet(packet(
+            RpcCbHeader::CreateNode(node, PositionRpcKind::ID.into()),
+            &(),
+        ));
+
+        // Messages overtaken by a newer message to the same node are dropped.
+        for seq in [5, 3, 6, 6, u32::MAX] {
+            client.recv_packet(packet(RpcCbHeader::SendUnreliableMessage(node, seq), &seq));
+        }
+
+        client.freeze();
+
+        let msgs = client.query::<PositionRpcKind>();
+        let msgs = msgs.msgs().map(|msg| *msg.packet()).collect::<Vec<_>>();
+
+        assert_eq!(msgs, [5, 6]);
+    }
+}
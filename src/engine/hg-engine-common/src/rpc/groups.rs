@@ -1,6 +1,6 @@
 use std::context::{infer_bundle, pack, Bundle};
 
-use hg_ecs::{bind, component, query::query_removed, Obj};
+use hg_ecs::{bind, component, Obj};
 use hg_utils::hash::FxHashSet;
 
 use crate::{
@@ -109,7 +109,14 @@ pub struct RpcGroupFollower {
     excluded: Option<Obj<RpcServerPeer>>,
 }
 
-component!(RpcGroupFollower);
+component!(RpcGroupFollower {
+    // See `RpcServerPeer`.
+    hook_order: 2,
+    on_remove: |world, node| {
+        bind!(world);
+        node.unregister();
+    },
+});
 
 impl RpcGroupFollower {
     pub fn unregister(self: Obj<Self>) {
@@ -135,9 +142,3 @@ impl RpcGroupFollower {
         });
     }
 }
-
-pub fn sys_flush_rpc_groups() {
-    for node in query_removed::<RpcGroupFollower>() {
-        node.unregister();
-    }
-}
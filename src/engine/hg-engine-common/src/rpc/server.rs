@@ -12,19 +12,20 @@ use anyhow::Context;
 use bytes::Bytes;
 use derive_where::derive_where;
 use hg_ecs::{
-    bind, component, entity::Component, query::query_removed, AccessComp, AccessCompRef, Entity,
-    Index, Obj, World, WORLD,
+    bind, component, entity::Component, AccessComp, AccessCompRef, Entity, Index, Obj, World, WORLD,
 };
 use hg_utils::hash::{FxHashMap, FxHashSet};
 
 use crate::{
-    net::{FrameEncoder, MultiPartDecoder, MultiPartSerializeExt as _, RpcPacket},
+    net::{
+        FrameEncoder, MultiPartDecoder, MultiPartSerializeExt as _, RpcPacket, TransportStream,
+    },
     utils::lang::NamedTypeId,
 };
 
 use super::{
-    BadRpcNodeKindError, NoSuchRpcNodeError, RpcCbHeader, RpcKind, RpcNodeId, RpcNodeLookupError,
-    RpcSbHeader,
+    is_newer_unreliable_seq, BadRpcNodeKindError, NoSuchRpcNodeError, RpcCbHeader, RpcChannel,
+    RpcKind, RpcNodeId, RpcNodeLookupError, RpcSbHeader,
 };
 
 // === RpcKind === //
@@ -109,6 +110,7 @@ enum QueuedAction {
     },
     Broadcast {
         queue: Obj<RpcNodeServerQueue>,
+        channel: RpcChannel,
         packet: FrameEncoder,
     },
     DestroyNode {
@@ -119,6 +121,7 @@ enum QueuedAction {
 #[derive(Debug)]
 pub struct RpcNodeServerQueue {
     node_id: RpcNodeId,
+    stream: TransportStream,
     visible_to: FxHashSet<Obj<RpcServerPeer>>,
 }
 
@@ -161,6 +164,7 @@ impl RpcServer {
         // Create the queue node
         let queue = Entity::new(self.entity()).add(RpcNodeServerQueue {
             node_id,
+            stream: K::STREAM,
             visible_to: FxHashSet::default(),
         });
 
@@ -173,6 +177,8 @@ impl RpcServer {
             vtable: <T as HasKindVtable<K>>::VTABLE,
             visible_to: FxHashSet::default(),
             queue,
+            next_unreliable_seq: 0,
+            last_unreliable_seqs: FxHashMap::default(),
             userdata_ty: NamedTypeId::of::<T>(),
             userdata: Obj::raw(userdata),
         });
@@ -213,20 +219,40 @@ impl RpcServer {
             .expect_rich::<RpcSbHeader>()
             .context("failed to parse RPC header")?;
 
-        let RpcSbHeader::SendMessage(target_id) = header;
+        let (target_id, seq) = match header {
+            RpcSbHeader::SendMessage(target_id) => (target_id, None),
+            RpcSbHeader::SendUnreliableMessage(target_id, seq) => (target_id, Some(seq)),
+        };
 
         let data = packet.expect().context("failed to parse RPC data")?;
 
-        let Ok(target) = self.lookup_any_node(target_id) else {
-            tracing::warn!("node with ID {target_id:?} does not exist");
+        // Unreliable messages can arrive after their node has been destroyed or hidden so we only
+        // warn about reliable ones.
+        let Ok(mut target) = self.lookup_any_node(target_id) else {
+            if seq.is_none() {
+                tracing::warn!("node with ID {target_id:?} does not exist");
+            }
             return Ok(());
         };
 
         if !target.is_visible_to(sender) {
-            tracing::warn!("{target_id:?} is not visible to {sender:?}");
+            if seq.is_none() {
+                tracing::warn!("{target_id:?} is not visible to {sender:?}");
+            }
             return Ok(());
         }
 
+        if let Some(seq) = seq {
+            let last = target.last_unreliable_seqs.get(&sender).copied();
+
+            if !is_newer_unreliable_seq(seq, last) {
+                // (drop stale unreliable message)
+                return Ok(());
+            }
+
+            target.last_unreliable_seqs.insert(sender, seq);
+        }
+
         (target.vtable.process_inbound)(&mut WORLD, target, sender, data)
     }
 
@@ -251,8 +277,8 @@ impl RpcServer {
                         continue;
                     }
 
-                    let packet = target.complete_packet(packet);
-                    target.send_packet(&mut WORLD, peer, packet);
+                    let (stream, packet) = (queue.stream, target.complete_packet(packet));
+                    target.send_packet(&mut WORLD, peer, stream, packet);
 
                     queue.visible_to.insert(peer);
                 }
@@ -264,17 +290,33 @@ impl RpcServer {
                     let mut encoder = FrameEncoder::new();
                     encoder.encode_multi_part(&RpcCbHeader::DeleteNode(queue.node_id));
 
-                    let packet = target.complete_packet(encoder);
-                    target.send_packet(&mut WORLD, peer, packet);
+                    let (stream, packet) = (queue.stream, target.complete_packet(encoder));
+                    target.send_packet(&mut WORLD, peer, stream, packet);
 
                     queue.visible_to.remove(&peer);
                 }
-                QueuedAction::Broadcast { queue, packet } => {
+                QueuedAction::Broadcast {
+                    queue,
+                    channel: RpcChannel::Reliable(stream),
+                    packet,
+                } => {
                     let packet = target.complete_packet(packet);
 
                     // TODO: Don't clone
                     for peer in queue.visible_to.clone() {
-                        target.send_packet(&mut WORLD, peer, packet.clone());
+                        target.send_packet(&mut WORLD, peer, stream, packet.clone());
+                    }
+                }
+                QueuedAction::Broadcast {
+                    queue,
+                    channel: RpcChannel::Unreliable(stream),
+                    packet,
+                } => {
+                    let packet = packet.finish_unframed();
+
+                    // TODO: Don't clone
+                    for peer in queue.visible_to.clone() {
+                        target.send_unreliable_packet(&mut WORLD, peer, stream, packet.clone());
                     }
                 }
                 QueuedAction::DestroyNode { mut queue } => {
@@ -283,10 +325,10 @@ impl RpcServer {
                     encoder.encode_multi_part(&RpcCbHeader::DeleteNode(queue.node_id));
 
                     // Broadcast it
-                    let packet = target.complete_packet(encoder);
+                    let (stream, packet) = (queue.stream, target.complete_packet(encoder));
 
                     for peer in mem::take(&mut queue.visible_to) {
-                        target.send_packet(&mut WORLD, peer, packet.clone());
+                        target.send_packet(&mut WORLD, peer, stream, packet.clone());
                     }
 
                     // Destroy the unused queue
@@ -303,7 +345,25 @@ pub trait RpcServerFlushTransport {
         encoder.finish()
     }
 
-    fn send_packet(&mut self, world: &mut World, target: Obj<RpcServerPeer>, packet: Bytes);
+    fn send_packet(
+        &mut self,
+        world: &mut World,
+        target: Obj<RpcServerPeer>,
+        stream: TransportStream,
+        packet: Bytes,
+    );
+
+    /// Sends an unframed packet over the unreliable channel. Transports without one send it
+    /// reliably over `stream` instead.
+    fn send_unreliable_packet(
+        &mut self,
+        world: &mut World,
+        target: Obj<RpcServerPeer>,
+        stream: TransportStream,
+        packet: Bytes,
+    ) {
+        self.send_packet(world, target, stream, FrameEncoder::frame(&packet));
+    }
 }
 
 // === RpcServerNode === //
@@ -315,11 +375,25 @@ pub struct RpcServerNode {
     vtable: KindVtableRef,
     visible_to: FxHashSet<Obj<RpcServerPeer>>,
     queue: Obj<RpcNodeServerQueue>,
+
+    /// The sequence number of the next unreliable message broadcast by this node.
+    next_unreliable_seq: u32,
+
+    /// The sequence number of the most recent unreliable message delivered from each peer.
+    last_unreliable_seqs: FxHashMap<Obj<RpcServerPeer>, u32>,
+
     userdata_ty: NamedTypeId,
     userdata: Index,
 }
 
-component!(RpcServerNode);
+component!(RpcServerNode {
+    // Nodes unregister before their peers disconnect.
+    hook_order: 0,
+    on_remove: |world, node| {
+        bind!(world);
+        node.unregister();
+    },
+});
 
 impl RpcServerNode {
     pub fn server(&self) -> Obj<RpcServer> {
@@ -369,6 +443,7 @@ impl RpcServerNode {
         }
 
         peer.vis_set.remove(&self);
+        self.last_unreliable_seqs.remove(&peer);
 
         self.server
             .action_queue
@@ -384,10 +459,21 @@ impl RpcServerNode {
         let mut encoder = FrameEncoder::new();
 
         encoder.encode_multi_part(packet);
-        encoder.encode_multi_part(&RpcCbHeader::SendMessage(self.node_id));
+
+        let channel = if K::is_unreliable_cb(packet) {
+            let seq = self.next_unreliable_seq;
+            self.next_unreliable_seq = seq.wrapping_add(1);
+
+            encoder.encode_multi_part(&RpcCbHeader::SendUnreliableMessage(self.node_id, seq));
+            RpcChannel::Unreliable(K::STREAM)
+        } else {
+            encoder.encode_multi_part(&RpcCbHeader::SendMessage(self.node_id));
+            RpcChannel::Reliable(K::STREAM)
+        };
 
         self.server.action_queue.push(QueuedAction::Broadcast {
             queue: self.queue,
+            channel,
             packet: encoder,
         });
     }
@@ -433,7 +519,15 @@ pub struct RpcServerPeer {
     connected: bool,
 }
 
-component!(RpcServerPeer);
+component!(RpcServerPeer {
+    // Peers disconnect before group followers unregister so that followers skip de-replicating
+    // from them.
+    hook_order: 1,
+    on_remove: |world, peer| {
+        bind!(world);
+        peer.disconnect();
+    },
+});
 
 impl RpcServerPeer {
     pub fn server(&self) -> Obj<RpcServer> {
@@ -453,6 +547,7 @@ impl RpcServerPeer {
 
         for mut replicated_to in self.vis_set.drain() {
             replicated_to.visible_to.remove(&self);
+            replicated_to.last_unreliable_seqs.remove(&self);
         }
     }
 }
@@ -534,13 +629,3 @@ where
 
     RpcServerHandle::wrap(rpc)
 }
-
-pub fn sys_flush_rpc_server() {
-    for node in query_removed::<RpcServerNode>() {
-        node.unregister();
-    }
-
-    for peer in query_removed::<RpcServerPeer>() {
-        peer.disconnect();
-    }
-}
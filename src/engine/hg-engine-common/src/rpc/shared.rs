@@ -3,7 +3,10 @@ use std::{borrow::Cow, num::NonZeroU64, u64};
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
-use crate::{net::RpcPacket, utils::lang::NamedTypeId};
+use crate::{
+    net::{RpcPacket, TransportStream},
+    utils::lang::NamedTypeId,
+};
 
 // === Errors === //
 
@@ -49,6 +52,7 @@ pub struct RpcNodeId(pub NonZeroU64);
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum RpcSbHeader {
     SendMessage(RpcNodeId),
+    SendUnreliableMessage(RpcNodeId, u32),
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -56,6 +60,35 @@ pub enum RpcCbHeader {
     CreateNode(RpcNodeId, Cow<'static, str>),
     DeleteNode(RpcNodeId),
     SendMessage(RpcNodeId),
+    SendUnreliableMessage(RpcNodeId, u32),
+}
+
+/// The channel over which an RPC packet should be sent.
+#[derive(Debug, Copy, Clone, Hash, Eq, PartialEq)]
+pub enum RpcChannel {
+    Reliable(TransportStream),
+
+    /// The packet is unframed and may be sent as a datagram. Transports fall back to sending it
+    /// over the given stream if it is too large to do so.
+    Unreliable(TransportStream),
+}
+
+impl RpcChannel {
+    pub fn stream(self) -> TransportStream {
+        match self {
+            Self::Reliable(stream) | Self::Unreliable(stream) => stream,
+        }
+    }
+}
+
+/// Determines whether an unreliable message with sequence number `seq` should be delivered given
+/// the sequence number of the last one delivered to the same node.
+pub(crate) fn is_newer_unreliable_seq(seq: u32, last: Option<u32>) -> bool {
+    match last {
+        // Sequence numbers wrap around so "newer" means less than half the range ahead.
+        Some(last) => (seq.wrapping_sub(last) as i32) > 0,
+        None => true,
+    }
 }
 
 // === RpcKind === //
@@ -66,4 +99,48 @@ pub trait RpcKind: Sized + 'static {
     type Catchup: RpcPacket;
     type ServerBound: RpcPacket;
     type ClientBound: RpcPacket;
+
+    /// The stream over which nodes of this kind are created, messaged and deleted.
+    ///
+    /// Packets sent over different streams may overtake one another so kinds whose catchups refer
+    /// to each other's nodes must use the same stream.
+    const STREAM: TransportStream = TransportStream::Normal;
+
+    /// Opts a server-bound message into the unreliable channel, which avoids head-of-line blocking
+    /// at the cost of the message possibly being lost. Unreliable messages are also dropped when
+    /// they arrive after a more recent unreliable message to the same node so this should only be
+    /// used for messages superseded by later ones, such as position updates.
+    fn is_unreliable_sb(_packet: &Self::ServerBound) -> bool {
+        false
+    }
+
+    /// Opts a client-bound message into the unreliable channel. See
+    /// [`RpcKind::is_unreliable_sb`] for the caveats.
+    fn is_unreliable_cb(_packet: &Self::ClientBound) -> bool {
+        false
+    }
+}
+
+// === Tests === //
+
+#[cfg(test)]
+mod tests {
+    use super::is_newer_unreliable_seq;
+
+    #[test]
+    fn unreliable_seqs_wrap_around() {
+        assert!(is_newer_unreliable_seq(0, None));
+        assert!(is_newer_unreliable_seq(6, Some(5)));
+        assert!(!is_newer_unreliable_seq(5, Some(5)));
+        assert!(!is_newer_unreliable_seq(4, Some(5)));
+
+        // Sequence numbers just past the wraparound are newer than those just before it...
+        assert!(is_newer_unreliable_seq(0, Some(u32::MAX)));
+        assert!(is_newer_unreliable_seq(10, Some(u32::MAX - 10)));
+        assert!(!is_newer_unreliable_seq(u32::MAX, Some(0)));
+
+        // ...but only up to half of the range ahead.
+        assert!(is_newer_unreliable_seq(i32::MAX as u32, Some(0)));
+        assert!(!is_newer_unreliable_seq(1 << 31, Some(0)));
+    }
 }
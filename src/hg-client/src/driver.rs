@@ -1,6 +1,12 @@
 use std::time::Instant;
 
-use hg_ecs::{bind, Entity, World};
+use hg_ecs::{
+    bind,
+    profile::Profiler,
+    schedule::{system, Schedule, Stage},
+    stats::MemoryStats,
+    Entity, Resource as _, World,
+};
 use hg_engine_client::gfx::{
     bus::find_gfx,
     camera::{sys_update_virtual_cameras, VirtualCameraSelector},
@@ -8,12 +14,15 @@ use hg_engine_client::gfx::{
     tile::TileRenderer,
 };
 use hg_engine_common::{
-    collide::{bus::sys_flush_colliders, group::sys_update_colliders},
+    collide::group::sys_update_colliders,
     debug::DebugDraw,
     kinematic::{sys_apply_kinematics, sys_kinematic_start_of_frame},
     mp::sys_update_mp_clients,
 };
-use macroquad::time::get_frame_time;
+use macroquad::{
+    input::{is_key_pressed, KeyCode},
+    time::get_frame_time,
+};
 
 use crate::game::{
     bullet::BulletTrailRenderer,
@@ -25,27 +34,69 @@ use crate::game::{
 pub fn world_init(world: &mut World) {
     bind!(world);
 
+    world_schedule();
     spawn_level(Entity::root());
 }
 
+fn world_schedule() {
+    let schedule = Schedule::fetch_mut();
+
+    schedule
+        // Player input is applied as artificial velocity, which must be reset every frame.
+        .add(system!(sys_kinematic_start_of_frame).in_stage(Stage::StartOfFrame))
+        // Players are spawned and driven by the packets we receive.
+        .add(system!(sys_update_mp_clients))
+        .add(system!(sys_update_players).after("sys_update_mp_clients"))
+        // Kinematics consume the velocities set by player input...
+        .add(
+            system!(sys_apply_kinematics => sys_apply_kinematics(get_frame_time()))
+                .label("physics")
+                .after("sys_update_players"),
+        )
+        // ...and colliders follow the positions they produce.
+        .add(system!(sys_update_colliders).after("physics"))
+        // Cameras track the final positions of the frame.
+        .add(system!(sys_update_player_camera).in_stage(Stage::PostUpdate))
+        .add(
+            system!(sys_update_virtual_cameras)
+                .in_stage(Stage::PostUpdate)
+                .after("sys_update_player_camera"),
+        )
+        .add(system!(sys_update_debug).in_stage(Stage::PostUpdate));
+
+    if let Err(err) = schedule.resolve() {
+        panic!("failed to resolve system schedule: {err}");
+    }
+
+    tracing::debug!("resolved system schedule:\n{schedule}");
+}
+
 pub fn world_tick(world: &mut World) {
     bind!(world);
 
-    Entity::flush(world_flush);
+    Entity::flush(|world| {
+        bind!(world);
+        Schedule::run(Stage::Flush);
+    });
 
-    world_update();
+    Schedule::run_frame();
     world_render();
+    world_profile();
 }
 
-pub fn world_update() {
-    sys_kinematic_start_of_frame();
-    sys_update_mp_clients();
-    sys_update_players();
-    sys_apply_kinematics(get_frame_time());
-    sys_update_colliders();
-    sys_update_player_camera();
-    sys_update_virtual_cameras();
-    sys_update_debug();
+fn world_profile() {
+    Profiler::end_frame();
+
+    // Dump the frame we just completed alongside the timing histograms collected thus far.
+    if is_key_pressed(KeyCode::F3) {
+        let profiler = Profiler::fetch();
+        tracing::info!("{}\n{profiler}", profiler.last_frame());
+    }
+
+    // Dump the memory used by the world to help track down leaks.
+    if is_key_pressed(KeyCode::F6) {
+        tracing::info!("{}", MemoryStats::collect());
+    }
 }
 
 pub fn world_render() {
@@ -72,9 +123,3 @@ pub fn world_render() {
         }
     }
 }
-
-pub fn world_flush(world: &mut World) {
-    bind!(world);
-
-    sys_flush_colliders();
-}
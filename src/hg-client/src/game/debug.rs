@@ -1,12 +1,53 @@
-use hg_ecs::{Entity, Obj, Query};
+use hg_ecs::{
+    digest::WorldDigest, reflect::InspectorTree, resource, Entity, Obj, Query, Resource as _,
+};
 use macroquad::input::{is_key_pressed, KeyCode};
 
 use super::player::PlayerController;
 
+#[derive(Debug, Default)]
+pub struct DebugDigest {
+    last: Option<WorldDigest>,
+}
+
+resource!(DebugDigest);
+
 pub fn sys_update_debug() {
     if is_key_pressed(KeyCode::K) {
         for (player, _obj) in Query::<(Entity, Obj<PlayerController>)>::new() {
             player.destroy();
         }
     }
+
+    // Hash the world and diff it against the previous capture.
+    if is_key_pressed(KeyCode::F4) {
+        let digest = match WorldDigest::capture(Entity::root()) {
+            Ok(digest) => digest,
+            Err(err) => {
+                tracing::error!("failed to capture world digest: {err}");
+                return;
+            }
+        };
+
+        tracing::info!("world hash: {:016x}", digest.hash());
+
+        let state = DebugDigest::fetch_mut();
+
+        if let Some(last) = &state.last {
+            match last.diff(&digest) {
+                Some(mismatch) => tracing::info!("first change since last capture: {mismatch}"),
+                None => tracing::info!("no changes since last capture"),
+            }
+        }
+
+        state.last = Some(digest);
+    }
+
+    // Dump the entire entity tree alongside every reflected component.
+    if is_key_pressed(KeyCode::F5) {
+        match InspectorTree::capture(Entity::root()) {
+            Ok(tree) => tracing::info!("entity tree:\n{tree}"),
+            Err(err) => tracing::error!("failed to inspect entity tree: {err}"),
+        }
+    }
 }
@@ -20,7 +20,7 @@ use hg_engine_common::{
     debug::{set_debug_draw, DebugDraw},
     kinematic::Pos,
     mp::MpClient,
-    net::{fetch_dev_pub_cert, quic_client::QuicClientTransport},
+    net::{fetch_dev_pub_cert, quic_client::QuicClientTransport, FrameCompression},
     rpc::RpcClient,
     tile::{TileConfig, TileLayer, TileLayerSet, TilePalette},
     try_sync,
@@ -61,6 +61,7 @@ pub fn spawn_level(parent: Entity) -> Entity {
             config,
             SocketAddr::from_str("127.0.0.1:8080").unwrap(),
             "localhost",
+            Some(FrameCompression::default()),
         );
 
         Box::new(transport)
@@ -42,7 +42,7 @@ pub struct PlayerController {
     jump_extend_time: u8,
 }
 
-component!(PlayerController);
+component!(PlayerController { rollback });
 
 impl PlayerController {
     pub fn is_on_ground(&self) -> bool {
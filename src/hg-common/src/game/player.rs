@@ -1,7 +1,10 @@
 use glam::Vec2;
 use serde::{Deserialize, Serialize};
 
-use hg_engine_common::rpc::{RpcKind, RpcNodeId};
+use hg_engine_common::{
+    net::TransportStream,
+    rpc::{RpcKind, RpcNodeId},
+};
 
 // === Rpc === //
 
@@ -22,6 +25,9 @@ pub struct PlayerRpcKind;
 impl RpcKind for PlayerRpcKind {
     const ID: &'static str = "player";
 
+    // Player kinds refer to each other's nodes so they must all share a stream.
+    const STREAM: TransportStream = TransportStream::Realtime;
+
     type Catchup = PlayerRpcCatchup;
     type ServerBound = PlayerRpcSb;
     type ClientBound = PlayerRpcCb;
@@ -39,10 +45,15 @@ pub struct PlayerPuppetRpcKind;
 
 impl RpcKind for PlayerPuppetRpcKind {
     const ID: &'static str = "player_puppet";
+    const STREAM: TransportStream = TransportStream::Realtime;
 
     type Catchup = RpcNodeId;
     type ServerBound = PlayerPuppetRpcSb;
     type ClientBound = PlayerPuppetRpcCb;
+
+    fn is_unreliable_cb(packet: &Self::ClientBound) -> bool {
+        matches!(packet, PlayerPuppetRpcCb::SetPos(_))
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -57,8 +68,13 @@ pub struct PlayerOwnerRpcKind;
 
 impl RpcKind for PlayerOwnerRpcKind {
     const ID: &'static str = "player_owner";
+    const STREAM: TransportStream = TransportStream::Realtime;
 
     type Catchup = RpcNodeId;
     type ServerBound = PlayerOwnerRpcSb;
     type ClientBound = PlayerOwnerRpcCb;
+
+    fn is_unreliable_sb(packet: &Self::ServerBound) -> bool {
+        matches!(packet, PlayerOwnerRpcSb::SetPos(_))
+    }
 }
@@ -1,11 +1,18 @@
 use std::{net::SocketAddr, str::FromStr as _, sync::Arc};
 
 use anyhow::Context as _;
-use hg_ecs::{bind, Entity, Obj, World};
+use hg_ecs::{
+    bind,
+    event::EventBus,
+    profile::Profiler,
+    schedule::{system, Schedule, Stage},
+    stats::MemoryStats,
+    Entity, Obj, Resource as _, World,
+};
 use hg_engine_common::{
-    mp::MpServer,
-    net::{generate_dev_priv_key, quic_server::QuicServerTransport},
-    rpc::{sys_flush_rpc_groups, sys_flush_rpc_server, RpcServer},
+    mp::{sys_update_mp_servers, MpServer, MpServerJoined, MpServerQuit},
+    net::{generate_dev_priv_key, quic_server::QuicServerTransport, FrameCompression},
+    rpc::RpcServer,
     time::{tps_to_dt, RunLoop},
 };
 use quinn::crypto::rustls::QuicServerConfig;
@@ -30,7 +37,7 @@ pub fn world_init(world: &mut World) -> anyhow::Result<()> {
     // Setup server
     let bind_addr = SocketAddr::from_str("127.0.0.1:8080").unwrap();
     let config = quinn::ServerConfig::with_crypto(crypto);
-    let transport = QuicServerTransport::new(config, bind_addr);
+    let transport = QuicServerTransport::new(config, bind_addr, Some(FrameCompression::default()));
 
     // Setup engine root
     let rpc = Entity::root().add(RpcServer::new());
@@ -39,6 +46,24 @@ pub fn world_init(world: &mut World) -> anyhow::Result<()> {
         .with(MpServer::new(Entity::root(), Box::new(transport), rpc))
         .with(RunLoop::new(tps_to_dt(60.)));
 
+    // Setup event readers
+    let events = EventBus::fetch_mut();
+    events.add_reader::<MpServerJoined>("sys_spawn_joined_players");
+    events.add_reader::<MpServerQuit>("sys_despawn_quit_players");
+
+    // Setup systems
+    let schedule = Schedule::fetch_mut();
+
+    schedule
+        .add(system!(sys_update_mp_servers))
+        // Join and quit events are sent while the server processes its sessions.
+        .add(system!(sys_spawn_joined_players).after("sys_update_mp_servers"))
+        .add(system!(sys_despawn_quit_players).after("sys_update_mp_servers"));
+
+    schedule.resolve().context("failed to resolve system schedule")?;
+
+    tracing::debug!("resolved system schedule:\n{schedule}");
+
     Ok(())
 }
 
@@ -49,11 +74,12 @@ pub fn world_main_loop(world: &mut World) {
 
     loop {
         // Process tick
-        world_tick();
+        Schedule::run_frame();
         Entity::flush(|world| {
             bind!(world);
-            world_flush();
+            Schedule::run(Stage::Flush);
         });
+        world_profile();
 
         // Wait for next tick
         if rl.should_exit() {
@@ -64,11 +90,34 @@ pub fn world_main_loop(world: &mut World) {
     }
 }
 
-fn world_tick() {
-    let mp = Entity::service::<MpServer>();
-    mp.process();
+/// The number of ticks between timing histogram dumps.
+const PROFILE_REPORT_INTERVAL: u64 = 60 * 10;
+
+fn world_profile() {
+    Profiler::end_frame();
+
+    let profiler = Profiler::fetch_mut();
+    tracing::trace!("{}", profiler.last_frame());
+
+    if (profiler.last_frame().index + 1) % PROFILE_REPORT_INTERVAL != 0 {
+        return;
+    }
+
+    tracing::info!("timings over the last {PROFILE_REPORT_INTERVAL} ticks:\n{profiler}");
+    profiler.clear_histograms();
+
+    // Growth between these reports is the easiest way to spot a leak on a long-running server.
+    tracing::debug!("memory usage:\n{}", MemoryStats::collect());
+}
+
+fn sys_spawn_joined_players() {
+    let joined = EventBus::fetch_mut().read::<MpServerJoined>("sys_spawn_joined_players");
+
+    for MpServerJoined(sess) in joined {
+        if !Obj::is_alive(sess) {
+            continue;
+        }
 
-    for &sess in &mp.on_join() {
         let mut owner = sess.entity().add(PlayerOwner {
             peer: sess.peer(),
             sess,
@@ -77,13 +126,12 @@ fn world_tick() {
         let player = spawn_player(Entity::root(), owner);
         owner.player = player.get();
     }
+}
+
+fn sys_despawn_quit_players() {
+    let quit = EventBus::fetch_mut().read::<MpServerQuit>("sys_despawn_quit_players");
 
-    for &sess in &mp.on_quit() {
+    for MpServerQuit(sess) in quit {
         PlayerOwner::downcast(sess.peer()).player.entity().destroy();
     }
 }
-
-fn world_flush() {
-    sys_flush_rpc_server();
-    sys_flush_rpc_groups();
-}